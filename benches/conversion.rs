@@ -0,0 +1,53 @@
+//! Conversion pipeline benchmarks over the query corpus in `benches/fixtures`.
+//!
+//! Run with `cargo bench`; compare a change against a baseline with
+//! `cargo bench -- --save-baseline before` and `cargo bench -- --baseline before`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use serde_json::{json, Value};
+
+use subgraph_converter::conversion::{
+    convert_subgraph_to_hyperindex_with_options, ConversionOptions,
+};
+
+const FIXTURES: &[(&str, &str)] = &[
+    ("small", include_str!("fixtures/small.graphql")),
+    ("large", include_str!("fixtures/large.graphql")),
+    ("fragments", include_str!("fixtures/fragments.graphql")),
+    ("nested", include_str!("fixtures/nested.graphql")),
+];
+
+fn payload(query: &str) -> Value {
+    json!({ "query": query, "variables": { "sender": "0xabc" } })
+}
+
+fn conversion(c: &mut Criterion) {
+    let options = ConversionOptions::default();
+    let mut group = c.benchmark_group("convert");
+    for (name, query) in FIXTURES {
+        let payload = payload(query);
+        // A fixture that stops converting would otherwise only benchmark the error path
+        if let Err(e) = convert_subgraph_to_hyperindex_with_options(&payload, None, &options) {
+            panic!("fixture {} no longer converts: {}", name, e);
+        }
+        group.throughput(Throughput::Bytes(query.len() as u64));
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                convert_subgraph_to_hyperindex_with_options(black_box(&payload), None, &options)
+            })
+        });
+        group.bench_function(format!("{}/chain", name), |b| {
+            b.iter(|| {
+                convert_subgraph_to_hyperindex_with_options(
+                    black_box(&payload),
+                    Some("1"),
+                    &options,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, conversion);
+criterion_main!(benches);
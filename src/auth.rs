@@ -0,0 +1,329 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonwebtoken::{
+    errors::ErrorKind,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::config::AuthConfig;
+use crate::AppState;
+
+// A token signed with an unknown key id refetches the key set (keys rotate), at most this often
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Missing bearer token")]
+    MissingToken,
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("Token is signed with an unknown key")]
+    UnknownKey,
+}
+
+#[derive(Clone)]
+struct Key {
+    decoding: DecodingKey,
+    // Pinned by the JWK's `alg`, when it has one
+    algorithm: Option<Algorithm>,
+}
+
+/// Verifies bearer tokens against the keys published at `auth.jwks_url`.
+pub struct Authenticator {
+    config: AuthConfig,
+    http: reqwest::Client,
+    // By key id; keys without one are stored under ""
+    keys: RwLock<HashMap<String, Key>>,
+    refreshed_at: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig, http: reqwest::Client) -> Option<Self> {
+        config.jwks_url.as_ref()?;
+        Some(Self {
+            config: config.clone(),
+            http,
+            keys: RwLock::default(),
+            refreshed_at: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// Fetches the key set, keeping the current keys if that fails.
+    pub async fn refresh(&self) {
+        let Some(url) = &self.config.jwks_url else {
+            return;
+        };
+        *self.refreshed_at.lock().await = Some(Instant::now());
+        let result = async { self.http.get(url).send().await?.json::<JwkSet>().await }.await;
+        match result {
+            Ok(jwks) => self.set_keys(&jwks),
+            Err(e) => tracing::error!("Failed to fetch JWKS from {}: {}", url, e),
+        }
+    }
+
+    fn set_keys(&self, jwks: &JwkSet) {
+        let keys = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| match key(jwk) {
+                Ok(key) => Some((jwk.common.key_id.clone().unwrap_or_default(), key)),
+                Err(e) => {
+                    tracing::warn!("Skipping JWK {:?}: {}", jwk.common.key_id, e);
+                    None
+                }
+            })
+            .collect();
+        *self.keys.write().unwrap() = keys;
+    }
+
+    fn key(&self, kid: &str) -> Option<Key> {
+        let keys = self.keys.read().unwrap();
+        match keys.get(kid) {
+            Some(key) => Some(key.clone()),
+            // A token without a key id can only mean the one key there is
+            None if kid.is_empty() && keys.len() == 1 => keys.values().next().cloned(),
+            None => None,
+        }
+    }
+
+    /// The verified claims of the request's bearer token, or `None` when there is no token and
+    /// `auth.required` is off.
+    pub async fn verify(&self, headers: &HeaderMap) -> Result<Option<Value>, AuthError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        let Some(token) = token else {
+            return if self.config.required {
+                Err(AuthError::MissingToken)
+            } else {
+                Ok(None)
+            };
+        };
+
+        let token_header = jsonwebtoken::decode_header(token)?;
+        let kid = token_header.kid.unwrap_or_default();
+        let key = match self.key(&kid) {
+            Some(key) => key,
+            None => {
+                let stale = self
+                    .refreshed_at
+                    .lock()
+                    .await
+                    .is_none_or(|at| at.elapsed() >= MIN_REFRESH_INTERVAL);
+                if stale {
+                    self.refresh().await;
+                }
+                self.key(&kid).ok_or(AuthError::UnknownKey)?
+            }
+        };
+        if key.algorithm.is_some_and(|alg| alg != token_header.alg) {
+            return Err(AuthError::InvalidToken(ErrorKind::InvalidAlgorithm.into()));
+        }
+
+        let mut validation = Validation::new(token_header.alg);
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        let data = jsonwebtoken::decode::<Value>(token, &key.decoding, &validation)?;
+        Ok(Some(data.claims))
+    }
+
+    /// `auth.claim_headers` filled in from `claims`; claims that are missing are skipped.
+    fn claim_headers(&self, claims: &Value) -> Vec<(HeaderName, HeaderValue)> {
+        self.config
+            .claim_headers
+            .iter()
+            .filter_map(|(name, claim)| {
+                let value = if claim.starts_with('/') {
+                    claims.pointer(claim)
+                } else {
+                    claims.get(claim)
+                }?;
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Null => return None,
+                    other => other.to_string(),
+                };
+                Some((
+                    HeaderName::from_bytes(name.as_bytes()).ok()?,
+                    HeaderValue::from_str(&value).ok()?,
+                ))
+            })
+            .collect()
+    }
+}
+
+fn key(jwk: &Jwk) -> Result<Key, jsonwebtoken::errors::Error> {
+    Ok(Key {
+        decoding: DecodingKey::from_jwk(jwk)?,
+        algorithm: jwk
+            .common
+            .key_algorithm
+            .map(|alg| Algorithm::from_str(&alg.to_string()))
+            .transpose()?,
+    })
+}
+
+/// Middleware rejecting requests without a valid token when `auth.jwks_url` is set. Claim
+/// headers sent by the client are dropped, then set from the verified token.
+pub async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(auth) = &state.auth else {
+        return next.run(request).await;
+    };
+    for name in auth.config.claim_headers.keys() {
+        request.headers_mut().remove(name.as_str());
+    }
+    match auth.verify(request.headers()).await {
+        Ok(Some(claims)) => {
+            for (name, value) in auth.claim_headers(&claims) {
+                request.headers_mut().insert(name, value);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => {
+            tracing::info!("Rejected unauthenticated request: {}", e);
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Json(serde_json::json!({
+                    "errors": [{
+                        "message": e.to_string(),
+                        "extensions": { "code": "UNAUTHENTICATED" },
+                    }]
+                })),
+            )
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn authenticator(config: AuthConfig) -> Authenticator {
+        let auth = Authenticator::new(
+            &AuthConfig {
+                jwks_url: Some("http://localhost/jwks.json".to_string()),
+                ..config
+            },
+            reqwest::Client::new(),
+        )
+        .unwrap();
+        // `k` is base64url("test-secret")
+        let jwks: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [{ "kty": "oct", "kid": "k1", "alg": "HS256", "k": "dGVzdC1zZWNyZXQ" }]
+        }))
+        .unwrap();
+        auth.set_keys(&jwks);
+        auth
+    }
+
+    fn bearer(claims: Value) -> HeaderMap {
+        let header = Header {
+            kid: Some("k1".to_string()),
+            ..Header::new(Algorithm::HS256)
+        };
+        let token =
+            jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_verified_claims_map_to_headers() {
+        let auth = authenticator(AuthConfig {
+            issuer: Some("https://issuer".to_string()),
+            claim_headers: HashMap::from([
+                ("x-tenant".to_string(), "org".to_string()),
+                (
+                    "x-hasura-user-id".to_string(),
+                    "/https:~1~1hasura.io~1jwt~1claims/x-hasura-user-id".to_string(),
+                ),
+            ]),
+            ..Default::default()
+        });
+        let exp = jsonwebtoken::get_current_timestamp() + 60;
+        let claims = auth
+            .verify(&bearer(serde_json::json!({
+                "iss": "https://issuer",
+                "exp": exp,
+                "org": "acme",
+                "https://hasura.io/jwt/claims": { "x-hasura-user-id": "u-1" },
+            })))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut headers = auth.claim_headers(&claims);
+        headers.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        assert_eq!(
+            headers,
+            vec![
+                (
+                    HeaderName::from_static("x-hasura-user-id"),
+                    HeaderValue::from_static("u-1")
+                ),
+                (
+                    HeaderName::from_static("x-tenant"),
+                    HeaderValue::from_static("acme")
+                ),
+            ]
+        );
+
+        let wrong_issuer = serde_json::json!({ "iss": "https://other", "exp": exp });
+        assert!(matches!(
+            auth.verify(&bearer(wrong_issuer)).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+        let expired = serde_json::json!({ "iss": "https://issuer", "exp": exp - 3600 });
+        assert!(matches!(
+            auth.verify(&bearer(expired)).await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_token() {
+        let auth = authenticator(AuthConfig::default());
+        assert!(matches!(
+            auth.verify(&HeaderMap::new()).await,
+            Err(AuthError::MissingToken)
+        ));
+
+        let auth = authenticator(AuthConfig {
+            required: false,
+            ..Default::default()
+        });
+        assert!(auth.verify(&HeaderMap::new()).await.unwrap().is_none());
+    }
+}
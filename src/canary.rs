@@ -0,0 +1,123 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::AppState;
+
+/// Request header pinning a request to one deployment, and response header reporting which one
+/// served it. Clients echo it back to keep a session on the same deployment.
+pub const TARGET_HEADER: &str = "x-hyperindex-target";
+
+/// Which of the two configured deployments a request is sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// `upstream.hyperindex_url` (or the chain's own URL).
+    Primary,
+    /// `upstream.canary_url`.
+    Canary,
+}
+
+impl Target {
+    fn as_str(self) -> &'static str {
+        match self {
+            Target::Primary => "primary",
+            Target::Canary => "canary",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "primary" => Ok(Target::Primary),
+            "canary" => Ok(Target::Canary),
+            other => Err(format!(
+                "unknown {} {:?}, expected primary or canary",
+                TARGET_HEADER, other
+            )),
+        }
+    }
+}
+
+/// The target a request was routed to by `route`.
+pub fn requested(headers: &axum::http::HeaderMap) -> Option<Target> {
+    headers
+        .get(TARGET_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Sends `percent` of every 100 requests to the canary, spread evenly rather than in bursts.
+pub struct Splitter {
+    percent: u64,
+    seen: AtomicU64,
+}
+
+impl Splitter {
+    pub fn new(percent: u8) -> Self {
+        Self {
+            percent: u64::from(percent.min(100)),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    pub fn pick(&self) -> Target {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) % 100;
+        if n * self.percent / 100 != (n + 1) * self.percent / 100 {
+            Target::Canary
+        } else {
+            Target::Primary
+        }
+    }
+}
+
+/// Middleware assigning each request a target when `upstream.canary_url` is set. A valid
+/// `x-hyperindex-target` sent by the client is kept; otherwise one is picked by
+/// `upstream.canary_percent`. The handlers read the target back from the request headers, and
+/// it is returned on the response.
+pub async fn route(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    if state.config.upstream.canary_url.is_none() {
+        return next.run(request).await;
+    }
+    let target = requested(request.headers()).unwrap_or_else(|| state.canary.pick());
+    let value = HeaderValue::from_static(target.as_str());
+    request.headers_mut().insert(TARGET_HEADER, value.clone());
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(TARGET_HEADER, value);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_by_percentage() {
+        let splitter = Splitter::new(25);
+        let picks: Vec<Target> = (0..200).map(|_| splitter.pick()).collect();
+        let canary = picks.iter().filter(|t| **t == Target::Canary).count();
+        assert_eq!(canary, 50);
+        // Evenly spread: never two canary requests in a row at 25%
+        assert!(picks
+            .windows(2)
+            .all(|w| w != [Target::Canary, Target::Canary]));
+
+        assert!((0..100).all(|_| Splitter::new(0).pick() == Target::Primary));
+        let all = Splitter::new(100);
+        assert!((0..100).all(|_| all.pick() == Target::Canary));
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(" Canary ".parse::<Target>(), Ok(Target::Canary));
+        assert_eq!("primary".parse::<Target>(), Ok(Target::Primary));
+        assert!("both".parse::<Target>().is_err());
+    }
+}
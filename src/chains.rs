@@ -0,0 +1,136 @@
+//! The `{chain_id}` of `/chainId/{chain_id}` routes, checked before anything is converted so a
+//! mistyped path fails instead of filtering every entity by a chain that doesn't exist.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::AppState;
+
+/// The chain a `/chainId/{chain_id}` request is for, resolved to its id.
+pub struct ChainId(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ChainId {
+    type Rejection = (StatusCode, Json<Value>);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| (e.status(), Json(error_body(&state.config, &e.body_text()))))?;
+        resolve(&state.config, &value)
+            .map(ChainId)
+            .map_err(|status| {
+                let message = match status {
+                    StatusCode::NOT_FOUND => format!("Chain {} is not served here", value),
+                    _ => format!("{:?} is not a chain id or a configured chain alias", value),
+                };
+                tracing::info!("Rejected chain route: {}", message);
+                (status, Json(error_body(&state.config, &message)))
+            })
+    }
+}
+
+/// The chain id `value` names: a chain under `chains`, one of their `aliases`, or any other
+/// numeric id unless `chain_filter.configured_only` is set. Numeric ids are normalized (`01` is
+/// chain `1`). Unknown numeric ids are a 404, anything else a 400.
+pub fn resolve(config: &Config, value: &str) -> Result<String, StatusCode> {
+    if config.chains.contains_key(value) {
+        return Ok(value.to_string());
+    }
+    let aliased = config.chains.iter().find(|(_, chain)| {
+        chain
+            .aliases
+            .iter()
+            .any(|alias| alias.eq_ignore_ascii_case(value))
+    });
+    if let Some((chain_id, _)) = aliased {
+        return Ok(chain_id.clone());
+    }
+    let numeric = !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+    let chain_id = numeric
+        .then(|| value.parse::<u64>().ok())
+        .flatten()
+        .ok_or(StatusCode::BAD_REQUEST)?
+        .to_string();
+    if config.chains.contains_key(&chain_id) || !config.chain_filter.configured_only {
+        Ok(chain_id)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+// A GraphQL error listing the configured chains and their aliases, ordered by id.
+fn error_body(config: &Config, message: &str) -> Value {
+    let mut chains: Vec<_> = config.chains.iter().collect();
+    chains.sort_by_key(|(id, _)| (id.parse::<u64>().unwrap_or(u64::MAX), id.as_str()));
+    let chains: Vec<Value> = chains
+        .into_iter()
+        .map(|(id, chain)| serde_json::json!({ "chainId": id, "aliases": chain.aliases }))
+        .collect();
+    serde_json::json!({ "errors": [{
+        "message": message,
+        "extensions": { "code": "UNKNOWN_CHAIN", "chains": chains },
+    }] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainConfig;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.chains.insert(
+            "1".to_string(),
+            ChainConfig {
+                aliases: vec!["mainnet".to_string(), "ethereum".to_string()],
+                ..Default::default()
+            },
+        );
+        config
+            .chains
+            .insert("137".to_string(), ChainConfig::default());
+        config
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut config = config();
+        assert_eq!(resolve(&config, "137").unwrap(), "137");
+        assert_eq!(resolve(&config, "Mainnet").unwrap(), "1");
+        assert_eq!(resolve(&config, "010").unwrap(), "10");
+        assert_eq!(resolve(&config, "polygon"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(resolve(&config, "1 OR 1=1"), Err(StatusCode::BAD_REQUEST));
+        assert_eq!(
+            resolve(&config, "99999999999999999999999"),
+            Err(StatusCode::BAD_REQUEST)
+        );
+
+        config.chain_filter.configured_only = true;
+        assert_eq!(resolve(&config, "0137").unwrap(), "137");
+        assert_eq!(resolve(&config, "10"), Err(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_error_lists_configured_chains() {
+        let body = error_body(&config(), "Chain 10 is not served here");
+        let error = &body["errors"][0];
+        assert_eq!(error["extensions"]["code"], "UNKNOWN_CHAIN");
+        assert_eq!(
+            error["extensions"]["chains"],
+            serde_json::json!([
+                { "chainId": "1", "aliases": ["mainnet", "ethereum"] },
+                { "chainId": "137", "aliases": [] },
+            ])
+        );
+    }
+}
@@ -0,0 +1,546 @@
+//! Response fields the proxy fills in from other fields of the same row, for subgraph fields
+//! Hyperindex doesn't store (e.g. `totalValueUSD = "amount * priceUSD"`, or a relationship
+//! stored only as its key column), or answers with `null` because Hyperindex doesn't have them
+//! at all.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use num_bigint::{BigInt, Sign};
+use serde_json::{Map, Value};
+
+use crate::numeric;
+use crate::transform::ValueTransform;
+
+/// Prefix of the aliases a computed field's inputs are selected under; removed from the
+/// response once the field is computed.
+pub const INPUT_PREFIX: &str = "__computed_";
+
+/// Arithmetic over fields of a row: `+ - * /`, parentheses, numbers and field names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(Decimal),
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// An exact decimal number, `digits / 10^scale`, so `BigInt`/`BigDecimal` inputs such as wei
+/// amounts are computed on without rounding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimal {
+    digits: BigInt,
+    scale: u32,
+}
+
+// Fraction digits a quotient keeps, like Graph Node's 34-digit `BigDecimal`; the rest is
+// truncated.
+const DIVISION_SCALE: u32 = 34;
+
+impl Decimal {
+    /// Parses a numeric literal in any notation `numeric::exact_decimal` reads, e.g. `"1e18"`.
+    pub fn parse(token: &str) -> Option<Self> {
+        let plain = numeric::exact_decimal(token.trim())?;
+        let scale = plain
+            .split_once('.')
+            .map_or(0, |(_, fraction)| fraction.len());
+        Some(Self {
+            digits: plain.replace('.', "").parse().ok()?,
+            scale: u32::try_from(scale).ok()?,
+        })
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Self::parse(&n.to_string()),
+            Value::String(s) => Self::parse(s),
+            _ => None,
+        }
+    }
+
+    // The digits at a scale no smaller than this one's.
+    fn digits_at(&self, scale: u32) -> BigInt {
+        &self.digits * pow10(scale - self.scale)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let scale = self.scale.max(other.scale);
+        Self {
+            digits: self.digits_at(scale) + other.digits_at(scale),
+            scale,
+        }
+    }
+
+    fn neg(&self) -> Self {
+        Self {
+            digits: -&self.digits,
+            scale: self.scale,
+        }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Self {
+            digits: &self.digits * &other.digits,
+            scale: self.scale + other.scale,
+        }
+    }
+
+    fn div(&self, other: &Self) -> Option<Self> {
+        if other.digits.sign() == Sign::NoSign {
+            return None;
+        }
+        // Scale the dividend so the quotient has `DIVISION_SCALE` fraction digits
+        let (dividend, divisor) = match (DIVISION_SCALE + other.scale).cmp(&self.scale) {
+            Ordering::Less => (
+                self.digits.clone(),
+                &other.digits * pow10(self.scale - DIVISION_SCALE - other.scale),
+            ),
+            _ => (
+                self.digits_at(DIVISION_SCALE + other.scale),
+                other.digits.clone(),
+            ),
+        };
+        Some(Self {
+            digits: dividend / divisor,
+            scale: DIVISION_SCALE,
+        })
+    }
+
+    // A JSON number: exact for integers that fit in 64 bits, the nearest float otherwise.
+    fn to_number(&self) -> Value {
+        self.to_string().parse().map_or(Value::Null, Value::Number)
+    }
+}
+
+fn pow10(exponent: u32) -> BigInt {
+    BigInt::from(10u8).pow(exponent)
+}
+
+// Plain decimal notation without insignificant zeros, like subgraph `BigDecimal`s.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.digits.magnitude().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        let fraction = fraction.trim_end_matches('0');
+        if self.digits.sign() == Sign::Minus && (whole != "0" || !fraction.is_empty()) {
+            f.write_str("-")?;
+        }
+        f.write_str(whole)?;
+        if !fraction.is_empty() {
+            write!(f, ".{}", fraction)?;
+        }
+        Ok(())
+    }
+}
+
+/// A computed field in a converted query: where its rows are in the Hyperindex response and
+/// how to compute it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedField {
+    /// Hyperindex root response key.
+    pub root: String,
+    /// Response keys from the root rows down to the rows holding the field.
+    pub path: Vec<String>,
+    /// Response key the value is written under.
+    pub field: String,
+    pub source: Source,
+}
+
+/// Where a computed field's value comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Arithmetic over the row's fields.
+    Expr(Expr),
+    /// `null`, for a field stripped from the query.
+    Null,
+    /// An object standing in for a relationship Hyperindex only stores the key column of:
+    /// each response key with its constant value, or `None` for the key itself (`id`). The
+    /// object is `null` when the key is.
+    Reference {
+        column: String,
+        fields: Vec<(String, Option<Value>)>,
+    },
+    /// A column selected under an input alias, converted back to the subgraph's unit.
+    Transform {
+        column: String,
+        transform: ValueTransform,
+    },
+}
+
+impl Expr {
+    /// Field names the expression reads, each once.
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields = Vec::new();
+        self.collect_fields(&mut fields);
+        fields
+    }
+
+    fn collect_fields<'a>(&'a self, fields: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Field(name) if fields.contains(&name.as_str()) => {}
+            Expr::Field(name) => fields.push(name),
+            Expr::Neg(expr) => expr.collect_fields(fields),
+            Expr::Binary(left, _, right) => {
+                left.collect_fields(fields);
+                right.collect_fields(fields);
+            }
+        }
+    }
+
+    /// The exact value over `row`, whose inputs are under `INPUT_PREFIX` aliases; `None` when
+    /// an input is missing or not a number, or on division by zero.
+    pub fn eval(&self, row: &Map<String, Value>) -> Option<Decimal> {
+        match self {
+            Expr::Number(n) => Some(n.clone()),
+            Expr::Field(name) => {
+                Decimal::from_value(row.get(&format!("{}{}", INPUT_PREFIX, name))?)
+            }
+            Expr::Neg(expr) => expr.eval(row).map(|n| n.neg()),
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.eval(row)?, right.eval(row)?);
+                match op {
+                    BinOp::Add => Some(left.add(&right)),
+                    BinOp::Sub => Some(left.add(&right.neg())),
+                    BinOp::Mul => Some(left.mul(&right)),
+                    BinOp::Div => left.div(&right),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            bytes: s.as_bytes(),
+            source: s,
+            pos: 0,
+        };
+        let expr = parser.sum()?;
+        parser.skip_whitespace();
+        match parser.bytes.get(parser.pos) {
+            None => Ok(expr),
+            Some(b) => Err(format!("unexpected '{}' at {}", *b as char, parser.pos)),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Field(name) => f.write_str(name),
+            Expr::Neg(expr) => write!(f, "-{}", expr),
+            Expr::Binary(left, op, right) => {
+                let op = match op {
+                    BinOp::Add => "+",
+                    BinOp::Sub => "-",
+                    BinOp::Mul => "*",
+                    BinOp::Div => "/",
+                };
+                write!(f, "({} {} {})", left, op, right)
+            }
+        }
+    }
+}
+
+// Recursive descent over `sum := product (('+' | '-') product)*`,
+// `product := unary (('*' | '/') unary)*`, `unary := '-' unary | number | field | '(' sum ')'`.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    source: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op @ (b'+' | b'-')) = self.peek() {
+            self.pos += 1;
+            let op = if op == b'+' { BinOp::Add } else { BinOp::Sub };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op @ (b'*' | b'/')) = self.peek() {
+            self.pos += 1;
+            let op = if op == b'*' { BinOp::Mul } else { BinOp::Div };
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let expr = self.sum()?;
+                if self.peek() != Some(b')') {
+                    return Err(format!("unclosed '(' at {}", start));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(b) if b.is_ascii_digit() || b == b'.' => {
+                let start = self.pos;
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|b| b.is_ascii_digit() || *b == b'.')
+                {
+                    self.pos += 1;
+                }
+                let number = &self.source[start..self.pos];
+                Decimal::parse(number)
+                    .map(Expr::Number)
+                    .ok_or_else(|| format!("invalid number {:?}", number))
+            }
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' => {
+                let start = self.pos;
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+                {
+                    self.pos += 1;
+                }
+                Ok(Expr::Field(self.source[start..self.pos].to_string()))
+            }
+            Some(b) => Err(format!("unexpected '{}' at {}", b as char, self.pos)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Computes `fields` on the rows of a Hyperindex response, then drops their input aliases.
+/// Values are strings when an input was (as `BigDecimal`s are), numbers otherwise; `null`
+/// when they can't be computed.
+pub fn apply(mut resp: Value, fields: &[ComputedField]) -> Value {
+    if fields.is_empty() {
+        return resp;
+    }
+    for field in fields {
+        let Some(root) = resp
+            .get_mut("data")
+            .and_then(|data| data.get_mut(&field.root))
+        else {
+            continue;
+        };
+        for_each_row(root, &field.path, &mut |row| {
+            let value = match &field.source {
+                Source::Expr(expr) => match expr.eval(row) {
+                    Some(n) if strings_in(expr, row) => Value::String(n.to_string()),
+                    Some(n) => n.to_number(),
+                    None => Value::Null,
+                },
+                Source::Null => Value::Null,
+                Source::Reference { column, fields } => {
+                    match row.get(&format!("{}{}", INPUT_PREFIX, column)) {
+                        None | Some(Value::Null) => Value::Null,
+                        Some(key) => Value::Object(
+                            fields
+                                .iter()
+                                .map(|(name, value)| {
+                                    (name.clone(), value.clone().unwrap_or_else(|| key.clone()))
+                                })
+                                .collect(),
+                        ),
+                    }
+                }
+                Source::Transform { column, transform } => row
+                    .get(&format!("{}{}", INPUT_PREFIX, column))
+                    .map_or(Value::Null, |value| transform.outbound(value)),
+            };
+            row.insert(field.field.clone(), value);
+        });
+    }
+    for field in fields {
+        let Some(root) = resp
+            .get_mut("data")
+            .and_then(|data| data.get_mut(&field.root))
+        else {
+            continue;
+        };
+        for_each_row(root, &field.path, &mut |row| {
+            row.retain(|key, _| !key.starts_with(INPUT_PREFIX))
+        });
+    }
+    resp
+}
+
+/// Removes `field` from the rows at `path` under `root`, for fields selected only for the proxy.
+pub fn remove(resp: &mut Value, root: &str, path: &[String], field: &str) {
+    if let Some(root) = resp.get_mut("data").and_then(|data| data.get_mut(root)) {
+        for_each_row(root, path, &mut |row| {
+            row.remove(field);
+        });
+    }
+}
+
+fn strings_in(expr: &Expr, row: &Map<String, Value>) -> bool {
+    expr.fields().iter().any(|name| {
+        row.get(&format!("{}{}", INPUT_PREFIX, name))
+            .is_some_and(Value::is_string)
+    })
+}
+
+fn for_each_row(value: &mut Value, path: &[String], f: &mut dyn FnMut(&mut Map<String, Value>)) {
+    match value {
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| for_each_row(item, path, f)),
+        Value::Object(row) => match path.split_first() {
+            None => f(row),
+            Some((key, rest)) => {
+                if let Some(value) = row.get_mut(key) {
+                    for_each_row(value, rest, f);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr: Expr = "amount * priceUSD + -fee / (2 - x)".parse().unwrap();
+        assert_eq!(expr.to_string(), "((amount * priceUSD) + (-fee / (2 - x)))");
+        assert_eq!(expr.fields(), ["amount", "priceUSD", "fee", "x"]);
+        assert!("amount *".parse::<Expr>().is_err());
+        assert!("(amount".parse::<Expr>().is_err());
+        assert!("amount priceUSD".parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_eval_is_exact() {
+        let row = json!({
+            "__computed_amount": "123456789012345678901234567",
+            "__computed_price": "0.000000000000000001",
+            "__computed_fee": 3,
+        });
+        let row = row.as_object().unwrap();
+        let eval = |expr: &str| {
+            expr.parse::<Expr>()
+                .unwrap()
+                .eval(row)
+                .map(|n| n.to_string())
+        };
+        assert_eq!(
+            eval("amount * price").unwrap(),
+            "123456789.012345678901234567"
+        );
+        assert_eq!(
+            eval("amount + 1 - fee").unwrap(),
+            "123456789012345678901234565"
+        );
+        assert_eq!(eval("-fee / 4").unwrap(), "-0.75");
+        assert_eq!(
+            eval("1 / fee").unwrap(),
+            "0.3333333333333333333333333333333333"
+        );
+        assert_eq!(
+            eval("fee * 1000000000000000000").unwrap(),
+            "3000000000000000000"
+        );
+        assert_eq!(eval("amount / (fee - 3)"), None);
+    }
+
+    #[test]
+    fn test_remove_from_rows() {
+        let mut resp = json!({ "data": { "Swap": [{ "id": "1", "pair": { "id": "2", "fee": 3 } }, { "id": "4", "pair": null }] } });
+        remove(&mut resp, "Swap", &["pair".to_string()], "id");
+        assert_eq!(
+            resp,
+            json!({ "data": { "Swap": [{ "id": "1", "pair": { "fee": 3 } }, { "id": "4", "pair": null }] } })
+        );
+    }
+
+    #[test]
+    fn test_apply_computes_and_drops_inputs() {
+        let resp = json!({ "data": { "Swap": [
+            { "id": "1", "__computed_amount": "2.5", "__computed_priceUSD": 4, "pair": { "__computed_fee": 3 }, "__computed_token_id": "0xa" },
+            { "id": "2", "__computed_amount": null, "__computed_priceUSD": 4, "pair": null, "__computed_token_id": null }
+        ] } });
+        let fields = vec![
+            ComputedField {
+                root: "Swap".to_string(),
+                path: Vec::new(),
+                field: "totalValueUSD".to_string(),
+                source: Source::Expr("amount * priceUSD".parse().unwrap()),
+            },
+            ComputedField {
+                root: "Swap".to_string(),
+                path: vec!["pair".to_string()],
+                field: "feePercent".to_string(),
+                source: Source::Expr("fee / 100".parse().unwrap()),
+            },
+            ComputedField {
+                root: "Swap".to_string(),
+                path: Vec::new(),
+                field: "legacy".to_string(),
+                source: Source::Null,
+            },
+            ComputedField {
+                root: "Swap".to_string(),
+                path: Vec::new(),
+                field: "token".to_string(),
+                source: Source::Reference {
+                    column: "token_id".to_string(),
+                    fields: vec![
+                        ("id".to_string(), None),
+                        ("symbol".to_string(), Some(Value::Null)),
+                    ],
+                },
+            },
+        ];
+        assert_eq!(
+            apply(resp, &fields),
+            json!({ "data": { "Swap": [
+                { "id": "1", "totalValueUSD": "10", "pair": { "feePercent": 0.03 }, "legacy": null, "token": { "id": "0xa", "symbol": null } },
+                { "id": "2", "totalValueUSD": null, "pair": null, "legacy": null, "token": null }
+            ] } })
+        );
+    }
+}
@@ -0,0 +1,1246 @@
+use figment::{
+    providers::{Env, Format, Serialized, Toml, Yaml},
+    Figment,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use crate::computed::Expr;
+use crate::conversion::{ChainIdType, ConversionMode, InjectIds, Redaction, Relationship};
+use crate::ids::AddressCase;
+use crate::indexed_block::IndexedBlock;
+use crate::numeric::NumericLiterals;
+use crate::post_filter;
+use crate::response::{MissingEntity, ResponseMode};
+use crate::schema::Schema;
+use crate::transform::ValueTransform;
+
+/// Config file used when `CONFIG_PATH` is not set. Missing files are ignored.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Prefix for structured environment overrides, e.g. `CONVERTER_SERVER__PORT=4000`.
+const ENV_PREFIX: &str = "CONVERTER_";
+
+// Environment variables supported before the config file existed, mapped onto config keys.
+// Later entries take precedence over earlier ones (the API key fallbacks are listed lowest first).
+const LEGACY_ENV_VARS: &[(&str, &str)] = &[
+    ("HYPERINDEX_URL", "upstream.hyperindex_url"),
+    ("SUBGRAPH_DEBUG_URL", "subgraph_debug.url"),
+    ("SUBGRAPH_AUTH_HEADER", "subgraph_debug.auth_header"),
+    ("SUBGRAPH_AUTH_VALUE", "subgraph_debug.auth_value"),
+    ("SUBGRAPH_BEARER_TOKEN", "subgraph_debug.bearer_token"),
+    ("TEST_THEGRAPH_API_KEY", "subgraph_debug.api_key"),
+    ("THEGRAPH_API_KEY", "subgraph_debug.api_key"),
+    ("SUBGRAPH_API_KEY", "subgraph_debug.api_key"),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub upstream: UpstreamConfig,
+    pub subgraph_debug: SubgraphDebugConfig,
+    /// Per-chain settings keyed by chain id, used by the `/chainId/{chain_id}` routes.
+    pub chains: HashMap<String, ChainConfig>,
+    pub limits: LimitsConfig,
+    pub cors: CorsConfig,
+    pub mappings: MappingsConfig,
+    pub live: LiveConfig,
+    pub response: ResponseConfig,
+    pub ids: IdsConfig,
+    pub extensions: ExtensionsConfig,
+    pub conversion: ConversionConfig,
+    /// Hyperindex column types per entity, enabling type-aware filter conversion.
+    pub schema: Schema,
+    pub stats: StatsConfig,
+    pub query_log: QueryLogConfig,
+    pub logging: LoggingConfig,
+    pub tenancy: TenancyConfig,
+    pub redaction: RedactionConfig,
+    pub consistent_reads: ConsistentReadsConfig,
+    pub auth: AuthConfig,
+    pub persisted: PersistedConfig,
+    pub chain_filter: ChainFilterConfig,
+    pub self_test: SelfTestConfig,
+    pub status: StatusConfig,
+    pub health: HealthConfig,
+    pub staleness: StalenessConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Serve HTTPS directly when set; otherwise plain HTTP.
+    pub tls: Option<TlsConfig>,
+}
+
+/// PEM-encoded certificate chain and private key for HTTPS serving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl ServerConfig {
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::net::AddrParseError> {
+        format!("{}:{}", self.host, self.port).parse()
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            tls: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    /// Required; an empty value fails validation at startup.
+    pub hyperindex_url: String,
+    /// Sent with every upstream request, e.g. `x-hasura-admin-secret`.
+    pub headers: HashMap<String, String>,
+    /// Let an `x-hyperindex-url` request header replace the upstream for that request, to
+    /// compare dev or staging indexers through the same proxy. `headers` and `signing` are not
+    /// sent to the override.
+    pub allow_url_override: bool,
+    /// Second deployment receiving `canary_percent` of the traffic, e.g. an indexer upgrade
+    /// being validated. Chains with their own `hyperindex_url` are not split.
+    pub canary_url: Option<String>,
+    /// Share of requests (0-100) sent to `canary_url`; `x-hyperindex-target` overrides it per
+    /// request.
+    pub canary_percent: u8,
+    /// Sign every forwarded body, for deployments that only accept signed requests.
+    pub signing: Option<SigningConfig>,
+}
+
+/// HMAC-SHA256 of each forwarded request body, keyed with a shared secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    pub secret: String,
+    /// Header carrying the hex-encoded signature.
+    pub header: String,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            header: "x-signature".to_string(),
+        }
+    }
+}
+
+/// Optional subgraph endpoint queried alongside failures to help debug conversions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubgraphDebugConfig {
+    pub url: Option<String>,
+    pub auth_header: Option<String>,
+    pub auth_value: Option<String>,
+    pub bearer_token: Option<String>,
+    pub api_key: Option<String>,
+    /// Give up on the subgraph after this long and answer without `subgraphResponse`.
+    pub timeout_ms: u64,
+    /// Fetch for every failed request, not only those sending `x-subgraph-debug: true`.
+    pub always: bool,
+    /// Send `[REDACTED]` instead of the values of variables not in `keep_variables`.
+    pub redact_variables: bool,
+    pub keep_variables: Vec<String>,
+}
+
+impl Default for SubgraphDebugConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            auth_header: None,
+            auth_value: None,
+            bearer_token: None,
+            api_key: None,
+            timeout_ms: 2000,
+            always: false,
+            redact_variables: true,
+            keep_variables: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainConfig {
+    /// Hyperindex endpoint serving this chain; falls back to `upstream.hyperindex_url`.
+    pub hyperindex_url: Option<String>,
+    /// Names accepted in place of the id in `/chainId/{chain_id}` paths (e.g. `mainnet`).
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Timeout applied to each upstream request.
+    pub upstream_timeout_secs: u64,
+    /// Longest upstream deadline a client can ask for with `x-request-timeout-ms`; 0 ignores
+    /// the header.
+    pub max_request_timeout_ms: u64,
+    /// Maximum accepted request body size.
+    pub max_body_bytes: usize,
+    /// Largest upstream response body read before the request fails with a 502; 0 for no limit.
+    pub max_upstream_response_bytes: usize,
+    /// Requests processed at once across all routes; excess requests get a 503.
+    pub max_concurrent_requests: usize,
+    /// Requests sent to Hyperindex at once; excess ones wait, interactive before background.
+    /// 0 for no limit.
+    pub max_upstream_requests: usize,
+    /// Hyperindex entity -> stricter limits for queries on it (e.g. `Swap = { max_rows = 500,
+    /// timeout_ms = 2000 }`).
+    pub entities: HashMap<String, EntityLimits>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntityLimits {
+    /// Most rows a list of the entity returns, root or nested; larger `first`s are lowered.
+    pub max_rows: Option<u64>,
+    /// Upstream timeout for queries selecting the entity at the root.
+    pub timeout_ms: Option<u64>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            upstream_timeout_secs: 30,
+            max_request_timeout_ms: 120_000,
+            max_body_bytes: 2 * 1024 * 1024,
+            max_upstream_response_bytes: 64 * 1024 * 1024,
+            max_concurrent_requests: 512,
+            max_upstream_requests: 0,
+            entities: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Allowed origins; empty allows any origin.
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MappingsConfig {
+    /// Subgraph collection field -> Hyperindex entity name, for names the
+    /// singularization rules get wrong (e.g. `people = "Person"`).
+    pub entities: HashMap<String, String>,
+    /// Hyperindex nested relationship key -> subgraph key, applied at any depth of the response.
+    pub fields: HashMap<String, String>,
+    /// Hyperindex `__typename` -> subgraph type name.
+    pub typenames: HashMap<String, String>,
+    /// Hyperindex entity -> primary key columns for single-entity queries (default `["id"]`).
+    /// An empty list sends those lookups as a one-row `where` query instead of `_by_pk`.
+    pub primary_keys: HashMap<String, Vec<String>>,
+    /// Subgraph interface collection field -> implementing Hyperindex entities; the field is
+    /// queried on each entity and the rows merged (e.g. `positions = ["LendingPosition"]`).
+    pub interfaces: HashMap<String, Vec<String>>,
+    /// Hyperindex entity -> text columns matched by a full-text `text` argument
+    /// (e.g. `Token = ["name", "symbol"]`).
+    pub text_search: HashMap<String, Vec<String>>,
+    /// Hyperindex entity -> subgraph field -> Hyperindex column, for fields the indexer renamed
+    /// (e.g. `Swap = { txHash = "transactionHash" }`). Selected columns are aliased back.
+    pub columns: HashMap<String, HashMap<String, String>>,
+    /// Hyperindex entity -> subgraph relationship field -> the Hyperindex relationship and
+    /// entity it resolves to, for reverse (`@derivedFrom`) lookups Hyperindex names differently
+    /// (e.g. `User = { streams = { field = "streams_by_user", entity = "Stream" } }`).
+    pub relationships: HashMap<String, HashMap<String, Relationship>>,
+    /// Hyperindex entity -> subgraph relationship field -> column holding the related id, for
+    /// schemas without the object relationship (e.g. `Stream = { asset = "asset_id" }`).
+    pub foreign_keys: HashMap<String, HashMap<String, String>>,
+    /// Hyperindex entity -> subgraph field -> arithmetic over the entity's fields, computed on
+    /// each response row (e.g. `Swap = { totalValueUSD = "amount * priceUSD" }`).
+    pub computed: HashMap<String, HashMap<String, String>>,
+    /// Hyperindex entity -> column -> unit conversion for values the subgraph stored
+    /// differently (e.g. `Swap = { timestamp = "seconds_to_iso8601" }`), applied to filter
+    /// values and converted back in responses.
+    pub transforms: HashMap<String, HashMap<String, ValueTransform>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResponseConfig {
+    /// Default response mode; overridable per request via `x-response-mode` or `?responseMode=`.
+    pub mode: ResponseMode,
+    /// Lowercase `id` values that are (or start with) a 0x-prefixed address, as subgraphs do.
+    pub lowercase_address_ids: bool,
+    /// Return numbers that can't be a subgraph `Int` (fractions, values outside i32) as strings,
+    /// the way subgraphs serialize `BigInt`/`BigDecimal`.
+    pub stringify_numbers: bool,
+    /// Fields always returned as strings when `stringify_numbers` is on, for `BigInt` values
+    /// small enough to pass as an `Int`.
+    pub big_number_fields: Vec<String>,
+    /// Whether a `_by_pk` lookup that found nothing returns `null` or drops the field.
+    pub missing_entity: MissingEntity,
+    /// Send the converted query in an `x-converted-query` header on every response, not only
+    /// when the request has `x-debug: true`.
+    pub converted_query_header: bool,
+    /// Report the latest indexed block in an `x-indexed-block` header and
+    /// `extensions.indexedBlocks`, read by each query (`query`) or from the health probe
+    /// (`probe`).
+    pub indexed_block: IndexedBlock,
+}
+
+impl Default for ResponseConfig {
+    fn default() -> Self {
+        Self {
+            mode: ResponseMode::default(),
+            lowercase_address_ids: false,
+            stringify_numbers: true,
+            big_number_fields: Vec::new(),
+            missing_entity: MissingEntity::default(),
+            converted_query_header: false,
+            indexed_block: IndexedBlock::default(),
+        }
+    }
+}
+
+/// Address-typed identifiers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdsConfig {
+    /// How Hyperindex stores `0x` addresses in ids. Anything but `preserve` recases address
+    /// literals in id filters to match, and lowercases address ids in responses like subgraphs do.
+    pub address_case: AddressCase,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConversionConfig {
+    /// `strict` rejects constructs that can't be converted faithfully; `lenient` drops them and
+    /// reports each in `extensions.warnings`. Overridable per request via `x-conversion-mode`.
+    pub mode: ConversionMode,
+    /// Filter operators (e.g. `_containsAny`) that lenient mode applies to the response rows of
+    /// root lists in the proxy, instead of sending them to Hyperindex.
+    pub post_filters: Vec<String>,
+    /// Most rows a root list with post-filtered conditions is read with; a response with that
+    /// many rows is reported as `POST_FILTER_TRUNCATED`.
+    pub post_filter_max_rows: u64,
+    /// Send `_contains`/`_starts_with`/`_ends_with` values as raw LIKE patterns, so `%` and `_`
+    /// act as wildcards instead of matching literally.
+    pub raw_like_patterns: bool,
+    /// How filter literals on `BigInt`/`BigDecimal` columns declared in `schema` are sent:
+    /// `auto` (numbers when they fit in 64 bits, strings otherwise), `string` or `number`.
+    pub numeric_literals: NumericLiterals,
+    /// Forward mutations to Hyperindex unchanged instead of rejecting them.
+    pub forward_mutations: bool,
+    /// Reject root fields and filters that `schema` doesn't declare, instead of converting them
+    /// without type information.
+    pub validate_schema: bool,
+    /// Strip optional whitespace and commas from converted queries before forwarding them.
+    pub minify_queries: bool,
+    /// Reject queries whose conversion is larger than this many bytes; 0 for no limit.
+    pub max_query_bytes: usize,
+    /// Parse every converted query as GraphQL before forwarding it; by default only its
+    /// braces, brackets and strings are checked.
+    pub parse_check: bool,
+    /// How Hyperindex names tables and columns.
+    pub naming: Naming,
+    /// Introspect Hyperindex's types at startup and strip selected fields they don't have,
+    /// answering them with `null` and a warning instead of failing the whole query upstream.
+    pub strip_missing_fields: bool,
+    /// Select `id` on every entity, for clients that normalize responses by it.
+    pub inject_ids: InjectIds,
+    /// Substitute the request's variable values into the query before converting it, for
+    /// clients whose variables are fixed per query.
+    pub inline_variables: bool,
+    /// Field root lists are ordered by when their `orderBy` is a variable that can't be resolved
+    /// from `variables`; unordered when unset.
+    pub default_order_by: Option<String>,
+    /// `asc` or `desc`, for `default_order_by`.
+    pub default_order_direction: String,
+    /// Rows asked for from nested lists without `first`, as The Graph does; 0 for all of them.
+    pub nested_first_default: u64,
+    /// Largest `first` a nested list is asked for; 0 for no limit.
+    pub nested_first_max: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Naming {
+    /// As in the subgraph schema: `LiquidityPosition`, `startTime`.
+    #[default]
+    Pascal,
+    /// `liquidity_position`, `start_time`.
+    SnakeCase,
+    /// Detected from Hyperindex's schema at startup, falling back to `pascal`.
+    Introspect,
+}
+
+impl Default for ConversionConfig {
+    fn default() -> Self {
+        Self {
+            mode: ConversionMode::default(),
+            post_filters: Vec::new(),
+            post_filter_max_rows: 1000,
+            raw_like_patterns: false,
+            numeric_literals: NumericLiterals::default(),
+            forward_mutations: false,
+            validate_schema: false,
+            minify_queries: false,
+            max_query_bytes: 256 * 1024,
+            parse_check: false,
+            naming: Naming::default(),
+            strip_missing_fields: false,
+            inject_ids: InjectIds::default(),
+            inline_variables: false,
+            default_order_by: None,
+            default_order_direction: "asc".to_string(),
+            nested_first_default: 100,
+            nested_first_max: 1000,
+        }
+    }
+}
+
+/// JWT validation of incoming requests; off unless `jwks_url` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// JSON Web Key Set holding the keys tokens are signed with.
+    pub jwks_url: Option<String>,
+    /// Expected `iss` claim, checked when set.
+    pub issuer: Option<String>,
+    /// Expected `aud` claim, checked when set.
+    pub audience: Option<String>,
+    /// Reject requests without a bearer token; otherwise they are served without claim headers.
+    pub required: bool,
+    /// Request header -> claim it is set from: a top-level claim name, or a JSON pointer for
+    /// nested claims (e.g. `/https:~1~1hasura.io~1jwt~1claims/x-hasura-user-id`). The headers
+    /// are forwarded upstream and can be used by `tenancy.rules`.
+    pub claim_headers: HashMap<String, String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwks_url: None,
+            issuer: None,
+            audience: None,
+            required: true,
+            claim_headers: HashMap::new(),
+        }
+    }
+}
+
+/// Operations known ahead of time, listed by hash in a manifest file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistedConfig {
+    /// JSON manifest of hashes to documents: graphql-codegen's `persisted-documents.json` or an
+    /// Apollo persisted query manifest.
+    pub manifest: Option<String>,
+    /// Only accept operations from the manifest, sent by hash or as the listed document.
+    pub enforce: bool,
+}
+
+/// Mandatory `where` conditions for exposing a shared Hyperindex to several tenants.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenancyConfig {
+    pub rules: Vec<TenancyRule>,
+}
+
+/// Restricts every root query of `entity` to rows whose `field` equals the value of the
+/// request's `header` (e.g. set by an auth gateway from a token claim).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenancyRule {
+    /// Hyperindex entity name, e.g. `Stream`.
+    pub entity: String,
+    pub field: String,
+    pub header: String,
+}
+
+/// Response fields hidden from clients, e.g. internal bookkeeping columns.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Hyperindex entity -> Hyperindex column -> `strip` (drop the field from responses) or
+    /// `mask` (return it as `null`).
+    pub fields: HashMap<String, HashMap<String, Redaction>>,
+}
+
+/// Pinning every root field of a query to the block Hyperindex had processed when the request
+/// arrived, so they all read the same snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConsistentReadsConfig {
+    pub enabled: bool,
+    /// Block number column of entities `schema` declares it for.
+    pub column: String,
+    /// Hyperindex entity -> block number column, for entities that name it differently or
+    /// aren't declared in `schema`.
+    pub entities: HashMap<String, String>,
+}
+
+impl Default for ConsistentReadsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            column: "blockNumber".to_string(),
+            entities: HashMap::new(),
+        }
+    }
+}
+
+/// The `field: {_eq: <chain id>}` condition added by the `/chainId/{chain_id}` routes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainFilterConfig {
+    pub field: String,
+    /// `string` writes the chain id quoted, `int` as a number.
+    #[serde(rename = "type")]
+    pub kind: ChainIdType,
+    /// Hyperindex entity name -> overrides for entities whose chain column differs.
+    pub entities: HashMap<String, ChainFilterOverride>,
+    /// Hyperindex entities without a chain column (e.g. `Factory`), left unfiltered.
+    pub skip_entities: Vec<String>,
+    /// Only serve the chains under `chains`; other numeric ids get a 404.
+    pub configured_only: bool,
+}
+
+impl Default for ChainFilterConfig {
+    fn default() -> Self {
+        Self {
+            field: "chainId".to_string(),
+            kind: ChainIdType::String,
+            entities: HashMap::new(),
+            skip_entities: Vec::new(),
+            configured_only: false,
+        }
+    }
+}
+
+/// Per-entity chain filter settings; unset keys fall back to `chain_filter`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainFilterOverride {
+    pub field: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<ChainIdType>,
+}
+
+/// Smoke queries converted and run against Hyperindex at startup, before traffic is served.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelfTestConfig {
+    pub queries: Vec<SelfTestQuery>,
+    /// Exit when any query fails instead of only logging the failures.
+    pub exit_on_failure: bool,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self {
+            queries: Vec::new(),
+            exit_on_failure: true,
+        }
+    }
+}
+
+/// Graph Node style indexing status served at `/status` and `/index-node/graphql`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Reported as the deployment (`subgraph`) of the status, e.g. the old subgraph's `Qm...`
+    /// hash so dashboards keep matching it.
+    pub deployment: String,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            deployment: "hyperindex".to_string(),
+        }
+    }
+}
+
+/// Background probing of the default Hyperindex upstream, reported at `/readyz` and `/metrics`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Seconds between `chain_metadata` probes; 0 disables probing.
+    pub probe_interval_secs: u64,
+    /// Answer queries for the probed upstream with 503 right away while its last probe failed.
+    pub fail_fast: bool,
+}
+
+/// Comparing each probed chain's latest block with the chain head, to warn about or reject
+/// queries while Hyperindex lags behind.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StalenessConfig {
+    /// Blocks a chain may lag its head before queries for it are stale; 0 disables the check.
+    pub max_block_lag: u64,
+    pub action: StaleAction,
+    /// Where the head of each checked chain is read, keyed by chain id.
+    pub chains: HashMap<String, ChainHeadSource>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleAction {
+    /// Serve the query with an `x-hyperindex-block-lag` header and `extensions.staleness`.
+    #[default]
+    Warn,
+    /// Answer with a 503 `STALE_DATA` error.
+    Reject,
+}
+
+/// Exactly one of the two is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainHeadSource {
+    /// JSON-RPC endpoint answering `eth_blockNumber`.
+    pub rpc_url: Option<String>,
+    /// The Graph endpoint answering `_meta { block { number } }`, e.g. the old subgraph.
+    pub subgraph_url: Option<String>,
+}
+
+/// A subgraph query that must convert and run without errors.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelfTestQuery {
+    /// Shown in failure logs; defaults to the query's position in the list.
+    pub name: String,
+    pub query: String,
+    pub variables: Option<serde_json::Value>,
+    /// Runs the query as `/chainId/{chain_id}` would.
+    pub chain_id: Option<String>,
+}
+
+/// Opt-in query features beyond what subgraphs support.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtensionsConfig {
+    /// Answer `<collection>Aggregate { count sum { field } ... }` root fields from Hyperindex
+    /// `_aggregate` queries.
+    pub aggregates: bool,
+    /// Forward a reserved `distinctOn: [field]` argument on root lists as Hasura `distinct_on`.
+    pub distinct_on: bool,
+}
+
+/// Server-Sent Events live queries (`/live`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveConfig {
+    /// How often each live query re-polls Hyperindex.
+    pub poll_interval_ms: u64,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Log the payload, converted query and upstream response of one request in this many at
+    /// info level; `0` logs none. Failures are always logged in full.
+    pub verbose_sample_rate: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            verbose_sample_rate: 1,
+        }
+    }
+}
+
+/// Usage analytics served at `/admin/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Count entities, filter operators and unsupported constructs in converted traffic.
+    pub enabled: bool,
+    /// Serve the counters at `/admin/stats`. They reveal query shapes and entity usage, so
+    /// this is off unless turned on, ideally with `auth` set.
+    pub expose: bool,
+    /// SQLite file the counters are loaded from at startup and flushed to; requires building
+    /// with the `sqlite` feature. Counters are kept in memory only when unset.
+    pub sqlite_path: Option<String>,
+    /// How often the counters are flushed to `sqlite_path`.
+    pub flush_interval_secs: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            expose: false,
+            sqlite_path: None,
+            flush_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryLogSink {
+    /// JSON lines, rotated by size.
+    #[default]
+    File,
+    /// A `query_log` table; requires building with the `sqlite` feature.
+    Sqlite,
+}
+
+/// Record of every query, its conversion outcome and the upstream status, for post-incident
+/// analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryLogConfig {
+    pub enabled: bool,
+    pub sink: QueryLogSink,
+    /// Log file, or SQLite database for the `sqlite` sink.
+    pub path: String,
+    /// Size at which the log file is rotated to `<path>.1`.
+    pub max_bytes: u64,
+    /// Rotated files kept alongside the current one.
+    pub max_files: usize,
+    /// Replace variable values with `[REDACTED]` before they are written.
+    pub redact_variables: bool,
+    /// Variables logged verbatim even when redaction is on (e.g. `first`, `skip`).
+    pub keep_variables: Vec<String>,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sink: QueryLogSink::File,
+            path: "query-log.jsonl".to_string(),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            redact_variables: true,
+            keep_variables: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads defaults, then the config file (`CONFIG_PATH` or `config.toml`), then
+    /// legacy environment variables, then `CONVERTER_`-prefixed overrides.
+    pub fn load() -> Result<Self, Box<figment::Error>> {
+        let path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        Self::figment(&path).extract().map_err(Box::new)
+    }
+
+    fn figment(path: &str) -> Figment {
+        let mut figment = Figment::from(Serialized::defaults(Config::default()));
+
+        let is_yaml = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"))
+            .unwrap_or(false);
+        figment = if is_yaml {
+            figment.merge(Yaml::file(path))
+        } else {
+            figment.merge(Toml::file(path))
+        };
+
+        for (var, key) in LEGACY_ENV_VARS {
+            if let Ok(value) = std::env::var(var) {
+                if !value.trim().is_empty() {
+                    figment = figment.merge(Serialized::default(key, value.trim()));
+                }
+            }
+        }
+
+        figment.merge(Env::prefixed(ENV_PREFIX).split("__"))
+    }
+
+    /// Checks everything the service needs to serve traffic, returning one actionable
+    /// message per problem so they can all be fixed in one go.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        if self.upstream.hyperindex_url.trim().is_empty() {
+            problems.push(
+                "upstream.hyperindex_url is not set; set HYPERINDEX_URL or add it to the config file"
+                    .to_string(),
+            );
+        } else if let Err(e) = validate_url(&self.upstream.hyperindex_url) {
+            problems.push(format!("upstream.hyperindex_url {}", e));
+        }
+
+        if let Some(url) = &self.upstream.canary_url {
+            if let Err(e) = validate_url(url) {
+                problems.push(format!("upstream.canary_url {}", e));
+            }
+        }
+        if self.upstream.canary_percent > 100 {
+            problems.push(format!(
+                "upstream.canary_percent must be between 0 and 100, got {}",
+                self.upstream.canary_percent
+            ));
+        }
+
+        for (chain_id, chain) in &self.chains {
+            if let Some(url) = &chain.hyperindex_url {
+                if let Err(e) = validate_url(url) {
+                    problems.push(format!("chains.{}.hyperindex_url {}", chain_id, e));
+                }
+            }
+            for alias in &chain.aliases {
+                let taken = self.chains.iter().any(|(id, other)| {
+                    id.eq_ignore_ascii_case(alias)
+                        || (id != chain_id
+                            && other.aliases.iter().any(|a| a.eq_ignore_ascii_case(alias)))
+                });
+                if alias.is_empty() || alias.bytes().all(|b| b.is_ascii_digit()) || taken {
+                    problems.push(format!(
+                        "chains.{}.aliases has {:?}, which is numeric or names another chain",
+                        chain_id, alias
+                    ));
+                }
+            }
+        }
+
+        let debug = &self.subgraph_debug;
+        if let Some(url) = &debug.url {
+            if let Err(e) = validate_url(url) {
+                problems.push(format!("subgraph_debug.url {}", e));
+            }
+        }
+        if debug.auth_header.is_some() != debug.auth_value.is_some() {
+            problems.push(
+                "subgraph_debug.auth_header and subgraph_debug.auth_value (SUBGRAPH_AUTH_HEADER/SUBGRAPH_AUTH_VALUE) must be set together"
+                    .to_string(),
+            );
+        }
+
+        if let Err(e) = self.server.socket_addr() {
+            problems.push(format!(
+                "server.host/server.port ({}:{}) is not a valid socket address: {}",
+                self.server.host, self.server.port, e
+            ));
+        }
+
+        if let Some(signing) = &self.upstream.signing {
+            if signing.secret.is_empty() {
+                problems.push("upstream.signing.secret is empty".to_string());
+            }
+            if reqwest::header::HeaderName::from_bytes(signing.header.as_bytes()).is_err() {
+                problems.push(format!(
+                    "upstream.signing.header is not a valid header name: {:?}",
+                    signing.header
+                ));
+            }
+        }
+
+        if let Some(tls) = &self.server.tls {
+            for (key, path) in [
+                ("server.tls.cert_path", &tls.cert_path),
+                ("server.tls.key_path", &tls.key_path),
+            ] {
+                if !Path::new(path).is_file() {
+                    problems.push(format!("{} points to a missing file: {:?}", key, path));
+                }
+            }
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if axum::http::HeaderValue::from_str(origin).is_err() {
+                problems.push(format!(
+                    "cors.allowed_origins entry {:?} is not a valid origin",
+                    origin
+                ));
+            }
+        }
+
+        for (entity, fields) in &self.mappings.relationships {
+            for (field, relationship) in fields {
+                if relationship.field.trim().is_empty() || relationship.entity.trim().is_empty() {
+                    problems.push(format!(
+                        "mappings.relationships.{}.{} needs a field and an entity",
+                        entity, field
+                    ));
+                }
+            }
+        }
+
+        for (entity, fields) in &self.mappings.computed {
+            for (field, expr) in fields {
+                if let Err(e) = expr.parse::<Expr>() {
+                    problems.push(format!(
+                        "mappings.computed.{}.{} is not a valid expression: {}",
+                        entity, field, e
+                    ));
+                }
+            }
+        }
+
+        if self.limits.upstream_timeout_secs == 0 {
+            problems.push("limits.upstream_timeout_secs must be greater than 0".to_string());
+        }
+        if self.live.poll_interval_ms == 0 {
+            problems.push("live.poll_interval_ms must be greater than 0".to_string());
+        }
+        if self.limits.max_concurrent_requests == 0 {
+            problems.push("limits.max_concurrent_requests must be greater than 0".to_string());
+        }
+        if self.stats.sqlite_path.is_some() {
+            if !cfg!(feature = "sqlite") {
+                problems.push(
+                    "stats.sqlite_path requires building with `--features sqlite`".to_string(),
+                );
+            }
+            if self.stats.flush_interval_secs == 0 {
+                problems.push("stats.flush_interval_secs must be greater than 0".to_string());
+            }
+        }
+        if self.query_log.enabled {
+            if self.query_log.sink == QueryLogSink::Sqlite && !cfg!(feature = "sqlite") {
+                problems.push(
+                    "query_log.sink = \"sqlite\" requires building with `--features sqlite`"
+                        .to_string(),
+                );
+            }
+            if self.query_log.max_bytes == 0 {
+                problems.push("query_log.max_bytes must be greater than 0".to_string());
+            }
+        }
+
+        if self.chain_filter.field.trim().is_empty()
+            || self
+                .chain_filter
+                .entities
+                .values()
+                .any(|o| o.field.as_deref().is_some_and(|f| f.trim().is_empty()))
+        {
+            problems.push("chain_filter field names must not be empty".to_string());
+        }
+
+        for (name, value) in &self.upstream.headers {
+            if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err()
+                || axum::http::HeaderValue::from_str(value).is_err()
+            {
+                problems.push(format!("upstream.headers.{} is not a valid header", name));
+            }
+        }
+        if let Some(url) = &self.auth.jwks_url {
+            if let Err(e) = validate_url(url) {
+                problems.push(format!("auth.jwks_url {}", e));
+            }
+        }
+        for name in self.auth.claim_headers.keys() {
+            if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                problems.push(format!(
+                    "auth.claim_headers key {:?} is not a valid header name",
+                    name
+                ));
+            }
+        }
+        if self.auth.jwks_url.is_none() && !self.auth.claim_headers.is_empty() {
+            problems.push("auth.claim_headers requires auth.jwks_url".to_string());
+        }
+
+        for (i, rule) in self.tenancy.rules.iter().enumerate() {
+            if rule.entity.trim().is_empty() || rule.field.trim().is_empty() {
+                problems.push(format!("tenancy.rules[{}] needs an entity and a field", i));
+            }
+            if axum::http::HeaderName::from_bytes(rule.header.as_bytes()).is_err() {
+                problems.push(format!(
+                    "tenancy.rules[{}].header {:?} is not a valid header name",
+                    i, rule.header
+                ));
+            }
+        }
+        if !self.tenancy.rules.is_empty() && self.conversion.forward_mutations {
+            problems.push(
+                "conversion.forward_mutations can't be combined with tenancy.rules; forwarded mutations aren't scoped"
+                    .to_string(),
+            );
+        }
+
+        if !matches!(
+            self.conversion
+                .default_order_direction
+                .to_ascii_lowercase()
+                .as_str(),
+            "asc" | "desc"
+        ) {
+            problems.push(format!(
+                "conversion.default_order_direction must be asc or desc, got {:?}",
+                self.conversion.default_order_direction
+            ));
+        }
+        for operator in &self.conversion.post_filters {
+            if !post_filter::OPERATORS.contains(&operator.as_str()) {
+                problems.push(format!(
+                    "conversion.post_filters: {:?} can't be applied by the proxy; supported operators are {}",
+                    operator,
+                    post_filter::OPERATORS.join(", ")
+                ));
+            }
+        }
+        if !self.conversion.post_filters.is_empty() && self.conversion.post_filter_max_rows == 0 {
+            problems.push(
+                "conversion.post_filter_max_rows must be above 0 when conversion.post_filters is set"
+                    .to_string(),
+            );
+        }
+        let (default, max) = (
+            self.conversion.nested_first_default,
+            self.conversion.nested_first_max,
+        );
+        if max > 0 && default > max {
+            problems.push(format!(
+                "conversion.nested_first_default ({}) is larger than conversion.nested_first_max ({})",
+                default, max
+            ));
+        }
+
+        if self.staleness.max_block_lag > 0 {
+            if self.health.probe_interval_secs == 0 {
+                problems.push(
+                    "staleness.max_block_lag requires health.probe_interval_secs; the probe reads the latest blocks"
+                        .to_string(),
+                );
+            }
+            if self.staleness.chains.is_empty() {
+                problems.push(
+                    "staleness.max_block_lag requires at least one staleness.chains entry"
+                        .to_string(),
+                );
+            }
+        }
+        for (chain_id, source) in &self.staleness.chains {
+            match (&source.rpc_url, &source.subgraph_url) {
+                (Some(url), None) | (None, Some(url)) => {
+                    if let Err(e) = validate_url(url) {
+                        problems.push(format!("staleness.chains.{} {}", chain_id, e));
+                    }
+                }
+                _ => problems.push(format!(
+                    "staleness.chains.{} needs exactly one of rpc_url and subgraph_url",
+                    chain_id
+                )),
+            }
+        }
+
+        match &self.persisted.manifest {
+            Some(path) if !Path::new(path).is_file() => {
+                problems.push(format!(
+                    "persisted.manifest points to a missing file: {:?}",
+                    path
+                ));
+            }
+            None if self.persisted.enforce => {
+                problems.push("persisted.enforce requires persisted.manifest".to_string());
+            }
+            _ => {}
+        }
+
+        if self.response.indexed_block == IndexedBlock::Probe
+            && self.health.probe_interval_secs == 0
+        {
+            problems.push(
+                "response.indexed_block = \"probe\" requires health.probe_interval_secs"
+                    .to_string(),
+            );
+        }
+
+        for (i, test) in self.self_test.queries.iter().enumerate() {
+            if test.query.trim().is_empty() {
+                problems.push(format!("self_test.queries[{}].query must not be empty", i));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Hyperindex endpoint for a request, honouring per-chain overrides.
+    pub fn hyperindex_url(&self, chain_id: Option<&str>) -> &str {
+        chain_id
+            .and_then(|id| self.chains.get(id))
+            .and_then(|chain| chain.hyperindex_url.as_deref())
+            .unwrap_or(&self.upstream.hyperindex_url)
+    }
+
+    /// Canary endpoint for a request, unless its chain has its own upstream.
+    pub fn canary_url(&self, chain_id: Option<&str>) -> Option<&str> {
+        let chain_url = chain_id
+            .and_then(|id| self.chains.get(id))
+            .and_then(|chain| chain.hyperindex_url.as_deref());
+        match chain_url {
+            Some(_) => None,
+            None => self.upstream.canary_url.as_deref(),
+        }
+    }
+}
+
+pub fn validate_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        Ok(parsed) => Err(format!(
+            "must use http or https, got {:?} in {:?}",
+            parsed.scheme(),
+            url
+        )),
+        Err(e) => Err(format!("is not a valid URL ({}): {:?}", e, url)),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::result_large_err)] // `Jail` closures return `figment::Error`
+mod tests {
+    use super::*;
+    use figment::Jail;
+
+    #[test]
+    fn test_defaults_without_file_or_env() {
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+            let config: Config = Config::figment("missing.toml").extract()?;
+            assert_eq!(config.server.port, 3000);
+            assert!(config.upstream.hyperindex_url.is_empty());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_file_with_env_overrides() {
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+            jail.create_file(
+                "config.toml",
+                r#"
+                [upstream]
+                hyperindex_url = "http://file/v1/graphql"
+
+                [chains.137]
+                hyperindex_url = "http://polygon/v1/graphql"
+
+                [mappings.entities]
+                people = "Person"
+                "#,
+            )?;
+            jail.set_env("CONVERTER_SERVER__PORT", "4000");
+            jail.set_env("HYPERINDEX_URL", "http://env/v1/graphql");
+
+            let config: Config = Config::figment("config.toml").extract()?;
+            assert_eq!(config.server.port, 4000);
+            assert_eq!(config.hyperindex_url(None), "http://env/v1/graphql");
+            assert_eq!(
+                config.hyperindex_url(Some("137")),
+                "http://polygon/v1/graphql"
+            );
+            assert_eq!(config.hyperindex_url(Some("1")), "http://env/v1/graphql");
+            assert_eq!(config.mappings.entities.get("people").unwrap(), "Person");
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_yaml_file() {
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+            jail.create_file(
+                "config.yaml",
+                "upstream:\n  hyperindex_url: http://yaml/v1/graphql\n",
+            )?;
+            let config: Config = Config::figment("config.yaml").extract()?;
+            assert_eq!(config.hyperindex_url(None), "http://yaml/v1/graphql");
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let mut config = Config::default();
+        config.subgraph_debug.auth_header = Some("x-api-key".to_string());
+        config.chains.insert(
+            "137".to_string(),
+            ChainConfig {
+                hyperindex_url: Some("not a url".to_string()),
+                aliases: vec!["polygon".to_string(), "137".to_string()],
+            },
+        );
+        config.conversion.post_filters = vec!["_containsAny".to_string(), "_gt".to_string()];
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 5);
+        assert!(problems[0].contains("HYPERINDEX_URL"));
+        assert!(problems[2].contains("\"137\""));
+        assert!(problems[4].contains("\"_gt\""));
+
+        config.upstream.hyperindex_url = "http://localhost:8080/v1/graphql".to_string();
+        config.subgraph_debug.auth_header = None;
+        config.chains.clear();
+        config.conversion.post_filters.truncate(1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_staleness() {
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = "http://localhost:8080/v1/graphql".to_string();
+        config.staleness.max_block_lag = 50;
+        config.staleness.chains.insert(
+            "1".to_string(),
+            ChainHeadSource {
+                rpc_url: Some("http://localhost:8545".to_string()),
+                subgraph_url: Some("http://localhost:8000".to_string()),
+            },
+        );
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("health.probe_interval_secs"));
+        assert!(problems[1].contains("exactly one of rpc_url and subgraph_url"));
+
+        config.health.probe_interval_secs = 10;
+        config.staleness.chains.get_mut("1").unwrap().subgraph_url = None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_missing_tls_files() {
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = "http://localhost:8080/v1/graphql".to_string();
+        config.server.tls = Some(TlsConfig {
+            cert_path: "/nonexistent/cert.pem".to_string(),
+            key_path: "/nonexistent/key.pem".to_string(),
+        });
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("server.tls.cert_path"));
+    }
+
+    #[test]
+    fn test_validate_persisted_manifest() {
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = "http://localhost:8080/v1/graphql".to_string();
+        config.persisted.enforce = true;
+        let problems = config.validate().unwrap_err();
+        assert_eq!(problems, ["persisted.enforce requires persisted.manifest"]);
+
+        config.persisted.manifest = Some("/nonexistent/persisted-documents.json".to_string());
+        let problems = config.validate().unwrap_err();
+        assert!(problems[0].starts_with("persisted.manifest points to a missing file"));
+    }
+}
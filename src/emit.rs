@@ -0,0 +1,364 @@
+//! Writing a converted [`Operation`] as a GraphQL document for the upstream API.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::operation::{Argument, Operation, RootField, RootKind};
+use crate::where_expr::WhereExpr;
+
+/// Spells out an operation for one target API. Every method has Hasura's spelling as its
+/// default, so a target only overrides where it differs.
+pub trait Emitter: fmt::Debug + Send + Sync {
+    /// The root field name reading `entity` the way `kind` asks, which is also its response
+    /// key unless aliased.
+    fn root_name(&self, entity: &str, kind: RootKind) -> String {
+        match kind {
+            RootKind::List => entity.to_string(),
+            RootKind::ByPk => format!("{}_by_pk", entity),
+            RootKind::Aggregate => format!("{}_aggregate", entity),
+        }
+    }
+
+    /// The column a field of an entity is stored in.
+    fn column(&self, field: &str) -> String {
+        field.to_string()
+    }
+
+    /// The input type of `where` arguments on `entity`.
+    fn bool_exp_type(&self, entity: &str) -> String {
+        format!("{}_bool_exp", self.root_name(entity, RootKind::List))
+    }
+
+    /// `where: {...}`, or an empty string without conditions.
+    fn where_argument(&self, expr: &WhereExpr) -> String {
+        WhereExpr::argument(Some(&expr.renamed(&|field| self.column(field))))
+    }
+
+    /// A selection set (or fragment definitions) as the target names its types and fields;
+    /// the response must keep the subgraph's field names.
+    fn selection<'a>(&self, selection: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(selection)
+    }
+
+    fn argument(&self, argument: &Argument) -> String {
+        match argument {
+            Argument::Limit(value) => format!("limit: {}", value),
+            Argument::Offset(value) => format!("offset: {}", value),
+            Argument::PrimaryKey(column, value) => format!("{}: {}", self.column(column), value),
+            Argument::DistinctOn(columns) => {
+                let columns: Vec<String> = columns.iter().map(|c| self.column(c)).collect();
+                format!("distinct_on: [{}]", columns.join(", "))
+            }
+            Argument::OrderBy { terms, list } => {
+                let terms: Vec<String> = terms
+                    .iter()
+                    .map(|(column, dir)| format!("{{{}: {}}}", self.column(column), dir))
+                    .collect();
+                if *list || terms.len() != 1 {
+                    format!("order_by: [{}]", terms.join(", "))
+                } else {
+                    format!("order_by: {}", terms[0])
+                }
+            }
+            Argument::Where(expr) => self.where_argument(expr),
+            Argument::WhereVariable(name) => format!("where: ${}", name),
+        }
+    }
+
+    /// One root field on its own indented line, without the trailing newline.
+    fn root_field(&self, field: &RootField) -> String {
+        let mut line = String::from("  ");
+        if let Some(alias) = &field.alias {
+            line.push_str(alias);
+            line.push_str(": ");
+        }
+        line.push_str(&self.root_name(&field.entity, field.kind));
+        let arguments: Vec<String> = field
+            .arguments
+            .iter()
+            .map(|argument| self.argument(argument))
+            .filter(|argument| !argument.is_empty())
+            .collect();
+        if !arguments.is_empty() {
+            line.push('(');
+            line.push_str(&arguments.join(", "));
+            line.push(')');
+        }
+        match field.kind {
+            RootKind::Aggregate => {
+                line.push_str(" { aggregate ");
+                line.push_str(&self.selection(&field.selection));
+                line.push_str(" }");
+            }
+            RootKind::List | RootKind::ByPk => {
+                line.push(' ');
+                line.push_str(&self.selection(&field.selection));
+            }
+        }
+        line
+    }
+
+    fn operation(&self, operation: &Operation) -> String {
+        let mut document = String::new();
+        if !operation.fragments.is_empty() {
+            document.push_str(&self.selection(&operation.fragments));
+            document.push('\n');
+        }
+        document.push_str(operation.kind.as_str());
+        if let Some(name) = &operation.name {
+            document.push(' ');
+            document.push_str(name);
+        }
+        if !operation.variables.is_empty() {
+            let definitions: Vec<String> = operation
+                .variables
+                .iter()
+                .map(|v| format!("${}: {}", v.name, v.type_name))
+                .collect();
+            document.push('(');
+            document.push_str(&definitions.join(", "));
+            document.push(')');
+        }
+        let fields: Vec<String> = operation
+            .fields
+            .iter()
+            .map(|f| self.root_field(f))
+            .collect();
+        document.push_str(" {\n");
+        document.push_str(&fields.join("\n"));
+        document.push_str("\n}");
+        document
+    }
+}
+
+/// Hasura's GraphQL API, as served by Hyperindex.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hasura;
+
+impl Emitter for Hasura {}
+
+/// Hyperindex deployments whose tables and columns are snake_case (`liquidity_position`,
+/// `start_time`). Selected fields are aliased back to their subgraph names, so responses keep
+/// the client's shape.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SnakeCase;
+
+impl Emitter for SnakeCase {
+    fn root_name(&self, entity: &str, kind: RootKind) -> String {
+        Hasura.root_name(&snake_case(entity), kind)
+    }
+
+    fn column(&self, field: &str) -> String {
+        snake_case(field)
+    }
+
+    fn selection<'a>(&self, selection: &'a str) -> Cow<'a, str> {
+        Cow::Owned(snake_case_selection(selection))
+    }
+}
+
+/// `LiquidityPosition` -> `liquidity_position`, `USDValue` -> `usd_value`. Names starting with
+/// `_` (e.g. `__typename`) are left alone.
+pub fn snake_case(name: &str) -> String {
+    if name.starts_with('_') {
+        return name.to_string();
+    }
+    let bytes = name.as_bytes();
+    let mut output = String::with_capacity(name.len() + 4);
+    for (i, &b) in bytes.iter().enumerate() {
+        if b.is_ascii_uppercase() && i > 0 {
+            let prev = bytes[i - 1];
+            let next_lower = bytes.get(i + 1).is_some_and(u8::is_ascii_lowercase);
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower)
+            {
+                output.push('_');
+            }
+        }
+        output.push(b.to_ascii_lowercase() as char);
+    }
+    output
+}
+
+// Renames the fields and type conditions of a selection set or fragment definitions to
+// snake_case. Unaliased fields whose name changes get their subgraph name as an alias; aliases,
+// fragment names and `__typename` are kept.
+fn snake_case_selection(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut output = String::with_capacity(source.len() + source.len() / 4);
+    let mut copied = 0;
+    let mut depth = 0;
+    let mut spread = false;
+    let mut expect_type = false;
+    let mut aliased = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b'.' => spread = true,
+            // Arguments of nested lists (`limit`, `offset`) take literal values
+            b'(' => {
+                while i < bytes.len() && bytes[i] != b')' {
+                    i += 1;
+                }
+            }
+            _ if b.is_ascii_alphabetic() || b == b'_' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let name = &source[start..i];
+                let replacement = if expect_type {
+                    expect_type = false;
+                    Some(snake_case(name))
+                } else if spread || depth == 0 {
+                    // Fragment names are kept; `on` introduces a type condition
+                    spread = false;
+                    expect_type = name == "on";
+                    None
+                } else if bytes[i..].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b':') {
+                    aliased = true;
+                    None
+                } else if std::mem::take(&mut aliased) {
+                    Some(snake_case(name))
+                } else {
+                    let column = snake_case(name);
+                    (column != name).then(|| format!("{}: {}", name, column))
+                };
+                if let Some(replacement) = replacement.filter(|r| r != name) {
+                    output.push_str(&source[copied..start]);
+                    output.push_str(&replacement);
+                    copied = i;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    output.push_str(&source[copied..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::{OperationKind, VariableDefinition};
+    use crate::where_expr::Op;
+
+    #[test]
+    fn test_emits_hasura_document() {
+        let mut operation = Operation::new(OperationKind::Query);
+        operation.name = Some("Streams".to_string());
+        operation.variables.push(VariableDefinition {
+            name: "first".to_string(),
+            type_name: "Int".to_string(),
+        });
+        let mut list = RootField::new("Stream", RootKind::List, "{ id }");
+        list.arguments = vec![
+            Argument::Limit("$first".to_string()),
+            Argument::OrderBy {
+                terms: vec![("timestamp".to_string(), "desc".to_string())],
+                list: false,
+            },
+            Argument::Where(WhereExpr::All(vec![WhereExpr::compare(
+                "sender",
+                Op::Eq,
+                "\"0x1\"",
+            )])),
+        ];
+        let mut count = RootField::new("Stream", RootKind::Aggregate, "{ count }")
+            .aliased("streams__totalCount");
+        count
+            .arguments
+            .push(Argument::WhereVariable("filter".to_string()));
+        let mut by_pk = RootField::new("stream", RootKind::ByPk, "{ id }");
+        by_pk
+            .arguments
+            .push(Argument::PrimaryKey("id".to_string(), "\"1\"".to_string()));
+        operation.fields = vec![list, count, by_pk];
+
+        assert_eq!(
+            Hasura.operation(&operation),
+            concat!(
+                "query Streams($first: Int) {\n",
+                "  Stream(limit: $first, order_by: {timestamp: desc}, where: {sender: {_eq: \"0x1\"}}) { id }\n",
+                "  streams__totalCount: Stream_aggregate(where: $filter) { aggregate { count } }\n",
+                "  stream_by_pk(id: \"1\") { id }\n",
+                "}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_snake_case_names() {
+        assert_eq!(snake_case("LiquidityPosition"), "liquidity_position");
+        assert_eq!(snake_case("startTime"), "start_time");
+        assert_eq!(snake_case("USDValue"), "usd_value");
+        assert_eq!(snake_case("token0Price"), "token0_price");
+        assert_eq!(snake_case("chain_id"), "chain_id");
+        assert_eq!(snake_case("__typename"), "__typename");
+    }
+
+    #[test]
+    fn test_snake_case_emitter() {
+        let mut field = RootField::new(
+            "LiquidityPosition",
+            RootKind::List,
+            "{ id startTime total: totalValue pair { token0Price } ...Extra ... on LiquidityPosition { ownerAddress } __typename }",
+        );
+        field.arguments = vec![
+            Argument::OrderBy {
+                terms: vec![("startTime".to_string(), "desc".to_string())],
+                list: false,
+            },
+            Argument::Where(WhereExpr::All(vec![
+                WhereExpr::compare("chainId", Op::Eq, "1"),
+                WhereExpr::nested("pair", WhereExpr::compare("feeTier", Op::Gt, "3")),
+            ])),
+        ];
+        let mut operation = Operation::new(OperationKind::Query);
+        operation.fragments = "fragment Extra on LiquidityPosition { createdAt }".to_string();
+        operation.fields.push(field);
+        assert_eq!(
+            SnakeCase.operation(&operation),
+            concat!(
+                "fragment Extra on liquidity_position { createdAt: created_at }\n",
+                "query {\n",
+                "  liquidity_position(order_by: {start_time: desc}, where: {chain_id: {_eq: 1}, pair: {fee_tier: {_gt: 3}}}) ",
+                "{ id startTime: start_time total: total_value pair { token0Price: token0_price } ...Extra ",
+                "... on liquidity_position { ownerAddress: owner_address } __typename }\n",
+                "}"
+            )
+        );
+        assert_eq!(
+            SnakeCase.selection("{ recentSwaps(limit: 5, offset: 1) { startTime } }"),
+            "{ recentSwaps: recent_swaps(limit: 5, offset: 1) { startTime: start_time } }"
+        );
+        assert_eq!(
+            SnakeCase.bool_exp_type("LiquidityPosition"),
+            "liquidity_position_bool_exp"
+        );
+        assert_eq!(
+            SnakeCase.root_name("liquidityPosition", RootKind::ByPk),
+            "liquidity_position_by_pk"
+        );
+    }
+
+    #[test]
+    fn test_order_by_list_form() {
+        let order_by = Argument::OrderBy {
+            terms: vec![("sender".to_string(), "asc".to_string())],
+            list: true,
+        };
+        assert_eq!(Hasura.argument(&order_by), "order_by: [{sender: asc}]");
+        assert_eq!(
+            Hasura.argument(&Argument::Where(WhereExpr::All(Vec::new()))),
+            ""
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use axum::{
+    extract::{Json, State},
+    http::{HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::chains::ChainId;
+use crate::conversion::{self, ConvertedQuery, Location};
+use crate::scheduler;
+use crate::{
+    conversion_error_reasoning, conversion_options, conversion_span, format, forward_to_hyperindex,
+    hyperindex_url, rejected, upstream_failure_response, upstream_failure_status, AppState,
+};
+
+pub async fn handle_explain(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Response {
+    explain(state, headers, payload, None).await
+}
+
+pub async fn handle_chain_explain(
+    State(state): State<AppState>,
+    ChainId(chain_id): ChainId,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Response {
+    explain(state, headers, payload, Some(chain_id)).await
+}
+
+// Converts the query as `/` would, then sends a probe of it (every root list limited to zero
+// rows) to Hyperindex and reports whether it was accepted. Errors from either stage carry
+// `locations` in the original query.
+async fn explain(
+    state: AppState,
+    mut headers: HeaderMap,
+    mut payload: Value,
+    chain_id: Option<String>,
+) -> Response {
+    if let Err(rejection) = state.persisted.resolve(&mut payload) {
+        return rejected(rejection).into_response();
+    }
+    // Probes are tooling traffic; they wait for interactive queries when upstream is busy
+    headers.insert(
+        scheduler::PRIORITY_HEADER,
+        HeaderValue::from_static("background"),
+    );
+    let query = payload
+        .get("query")
+        .and_then(|q| q.as_str())
+        .unwrap_or_default()
+        .to_string();
+    // Mutations would be executed rather than validated, so they are never forwarded here
+    let options = conversion::ConversionOptions {
+        probe: true,
+        total_count: false,
+        forward_mutations: false,
+        ..conversion_options(&state, &headers).into_owned()
+    };
+
+    let converted = match conversion_span(&headers, chain_id.as_deref()).in_scope(|| {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            chain_id.as_deref(),
+            &options,
+        )
+    }) {
+        Ok(converted) => converted,
+        Err(e) => {
+            tracing::info!("Explain conversion error: {}", e);
+            return Json(serde_json::json!({
+                "valid": false,
+                "stage": "conversion",
+                "errors": [{
+                    "message": e.to_string(),
+                    "locations": e.locations(&query),
+                    "extensions": {
+                        "code": e.code(),
+                        "reasoning": conversion_error_reasoning(&e),
+                    },
+                }],
+            }))
+            .into_response();
+        }
+    };
+
+    let response =
+        match forward_to_hyperindex(&state, chain_id.as_deref(), &headers, &converted.body).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!("Explain upstream request error: {}", e);
+                return (
+                    upstream_failure_status(e.as_ref()),
+                    Json(serde_json::json!({
+                        "error": "Hyperindex request failed",
+                        "details": e.to_string(),
+                        "upstreamResponse": upstream_failure_response(e.as_ref()),
+                        "hyperindexUrl": hyperindex_url(&state, chain_id.as_deref(), &headers),
+                    })),
+                )
+                    .into_response();
+            }
+        };
+
+    let errors: Vec<Value> = response
+        .get("errors")
+        .and_then(Value::as_array)
+        .map(|errors| {
+            errors
+                .iter()
+                .map(|error| with_original_locations(error, &query, &converted))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut body = serde_json::json!({
+        "valid": errors.is_empty(),
+        "query": format::pretty(converted.body["query"].as_str().unwrap_or_default()),
+        "warnings": converted.warnings,
+    });
+    if let Some(variables) = converted.body.get("variables") {
+        body["variables"] = variables.clone();
+    }
+    if !errors.is_empty() {
+        body["stage"] = Value::from("upstream");
+        body["errors"] = Value::Array(errors);
+    }
+    Json(body).into_response()
+}
+
+// Hasura reports validation failures by path in the converted query (e.g.
+// `$.selectionSet.Stream.args.where.colour`) rather than by position, so the innermost name on
+// that path (or quoted in the message) that also appears in the original query is reported.
+fn with_original_locations(error: &Value, query: &str, converted: &ConvertedQuery) -> Value {
+    let mut error = error.clone();
+    let path = error
+        .pointer("/extensions/path")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let quoted = message.split(['\'', '"']).skip(1).step_by(2);
+    let location: Option<Location> = path
+        .rsplit('.')
+        .chain(quoted)
+        .filter(|name| !matches!(*name, "$" | "selectionSet" | "args" | ""))
+        .find_map(|name| {
+            // Root fields are renamed (e.g. `Stream` is the client's `streams`)
+            let original = converted.field_map.get(name).map_or(name, String::as_str);
+            conversion::name_location(query, original)
+        });
+    if let (Some(location), Some(object)) = (location, error.as_object_mut()) {
+        object.insert("locations".to_string(), serde_json::json!([location]));
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn converted() -> ConvertedQuery {
+        ConvertedQuery {
+            field_map: HashMap::from([("Stream".to_string(), "streams".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_upstream_error_mapped_to_original_location() {
+        let query = "query {\n  streams(where: {colour: \"red\"}) {\n    id\n  }\n}";
+        let error = serde_json::json!({
+            "message": "field 'colour' not found in type: 'Stream_bool_exp'",
+            "extensions": { "path": "$.selectionSet.Stream.args.where.colour", "code": "validation-failed" }
+        });
+        let mapped = with_original_locations(&error, query, &converted());
+        assert_eq!(
+            mapped["locations"],
+            serde_json::json!([{ "line": 2, "column": 19 }])
+        );
+        assert_eq!(mapped["extensions"]["code"], "validation-failed");
+    }
+
+    #[test]
+    fn test_upstream_error_falls_back_to_root_field_or_message() {
+        let query = "{ streams { id } }";
+        let error = serde_json::json!({
+            "message": "something failed",
+            "extensions": { "path": "$.selectionSet.Stream.selectionSet.unknown_column" }
+        });
+        let mapped = with_original_locations(&error, query, &converted());
+        assert_eq!(
+            mapped["locations"],
+            serde_json::json!([{ "line": 1, "column": 3 }])
+        );
+
+        let error = serde_json::json!({ "message": "field 'id' not found" });
+        let mapped = with_original_locations(&error, query, &converted());
+        assert_eq!(
+            mapped["locations"],
+            serde_json::json!([{ "line": 1, "column": 13 }])
+        );
+
+        let error = serde_json::json!({ "message": "internal error" });
+        assert!(with_original_locations(&error, query, &converted())
+            .get("locations")
+            .is_none());
+    }
+}
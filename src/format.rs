@@ -0,0 +1,161 @@
+/// Canonical layout for emitted GraphQL documents: one selection per line, two-space
+/// indentation, arguments and input values kept inline as `name: value, name: value`.
+pub fn pretty(query: &str) -> String {
+    render(query, true)
+}
+
+/// The same document with every optional space, comma and newline removed.
+pub fn minify(query: &str) -> String {
+    render(query, false)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    // Names, numbers, enum values and `$variables`
+    Word(&'a str),
+    Str(&'a str),
+    Punct(char),
+    Spread,
+}
+
+pub(crate) fn tokenize(query: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            // Commas are insignificant in GraphQL and are re-inserted when rendering
+            c if c.is_whitespace() || c == ',' => {}
+            '"' => {
+                let mut end = query.len();
+                let mut escaped = false;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => {
+                            end = i + 1;
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                tokens.push(Token::Str(&query[start..end]));
+            }
+            '.' if query[start..].starts_with("...") => {
+                chars.next();
+                chars.next();
+                tokens.push(Token::Spread);
+            }
+            c if is_name_char(c) || c == '$' || c == '-' => {
+                // Numbers also take `.`, `+` and `-` (e.g. `-1.5e+3`)
+                let number = c.is_ascii_digit() || c == '-';
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !(is_name_char(c) || number && matches!(c, '.' | '+' | '-')) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(&query[start..end]));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn render(query: &str, pretty: bool) -> String {
+    let mut output = String::with_capacity(query.len());
+    // Open brackets: `{` for selection sets, `(`, `[` or `o` (input object) for inline values
+    let mut stack: Vec<char> = Vec::new();
+    let mut previous: Option<Token> = None;
+    // Set between `... on` and the type name of an inline fragment
+    let mut type_condition = false;
+    for token in tokenize(query) {
+        let inline = stack.last().is_some_and(|open| *open != '{');
+        let depth = stack.len();
+        let newline = |depth: usize| format!("\n{}", "  ".repeat(depth));
+        let separator = match (previous, token) {
+            (None, _) => String::new(),
+            _ if type_condition => " ".into(),
+            (_, Token::Punct(')' | ']' | '}' | ':' | '!' | '(')) if inline => String::new(),
+            (Some(Token::Punct('(' | '[' | '{')), _) if inline => String::new(),
+            (_, Token::Punct('=')) | (Some(Token::Punct(':' | '=')), _) if pretty => " ".into(),
+            (_, Token::Punct('=')) | (Some(Token::Punct(':' | '=')), _) => String::new(),
+            _ if inline && pretty => ", ".into(),
+            _ if inline => ",".into(),
+            (_, Token::Punct('}')) if pretty => newline(depth - 1),
+            (Some(Token::Punct('{')), _) if pretty => newline(depth),
+            (_, Token::Punct('{')) if pretty => " ".into(),
+            (_, Token::Punct('(' | ':')) => String::new(),
+            (Some(Token::Spread), Token::Word(word)) if pretty && word == "on" => " ".into(),
+            (Some(Token::Spread), _) => String::new(),
+            (Some(Token::Punct('}')), _) if pretty && depth == 0 => "\n\n".into(),
+            _ if pretty && depth > 0 => newline(depth),
+            (Some(Token::Word(_)), Token::Word(_)) => " ".into(),
+            _ if pretty => " ".into(),
+            _ => String::new(),
+        };
+        output.push_str(&separator);
+        match token {
+            Token::Word(text) | Token::Str(text) => output.push_str(text),
+            Token::Spread => output.push_str("..."),
+            Token::Punct(c) => {
+                match c {
+                    '{' if inline => stack.push('o'),
+                    '{' | '(' | '[' => stack.push(c),
+                    '}' | ')' | ']' => {
+                        stack.pop();
+                    }
+                    _ => {}
+                }
+                output.push(c);
+            }
+        }
+        type_condition = previous == Some(Token::Spread) && token == Token::Word("on");
+        previous = Some(token);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "query($filter: Stream_bool_exp, $n: Int = 2) {\n  Stream(limit: 2,where: {chainId: {_eq: \"1\"}  name: {_in: [\"a b\", \"c,d\"]}}) {\n    id name  label\n  ...F pair { id }\n  }\n}";
+
+    #[test]
+    fn test_pretty_is_canonical() {
+        assert_eq!(
+            pretty(MESSY),
+            "query($filter: Stream_bool_exp, $n: Int = 2) {\n  Stream(limit: 2, where: {chainId: {_eq: \"1\"}, name: {_in: [\"a b\", \"c,d\"]}}) {\n    id\n    name\n    label\n    ...F\n    pair {\n      id\n    }\n  }\n}"
+        );
+        assert_eq!(pretty(&pretty(MESSY)), pretty(MESSY));
+        assert_eq!(pretty(&minify(MESSY)), pretty(MESSY));
+    }
+
+    #[test]
+    fn test_minify() {
+        assert_eq!(
+            minify(MESSY),
+            "query($filter:Stream_bool_exp,$n:Int=2){Stream(limit:2,where:{chainId:{_eq:\"1\"},name:{_in:[\"a b\",\"c,d\"]}}){id name label...F pair{id}}}"
+        );
+    }
+
+    #[test]
+    fn test_fragments_and_inline_fragments() {
+        let query = "fragment F on Stream { id }\nquery { Stream { ... on Stream { name } } }";
+        assert_eq!(
+            pretty(query),
+            "fragment F on Stream {\n  id\n}\n\nquery {\n  Stream {\n    ... on Stream {\n      name\n    }\n  }\n}"
+        );
+        assert_eq!(
+            minify(query),
+            "fragment F on Stream{id}query{Stream{...on Stream{name}}}"
+        );
+    }
+}
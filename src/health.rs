@@ -0,0 +1,575 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::future::join_all;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::config::{ChainHeadSource, StaleAction};
+use crate::{canary, chains, forward_to_hyperindex, AppState};
+
+/// Response header carrying the block lag of a stale response under `staleness.action = "warn"`.
+pub const BLOCK_LAG_HEADER: &str = "x-hyperindex-block-lag";
+
+const PROBE_QUERY: &str = "query { chain_metadata { chain_id latest_fetched_block_number } }";
+
+/// The result of the last upstream probe, shared by `/readyz`, `/metrics` and the fail-fast
+/// middleware.
+#[derive(Default)]
+pub struct Probe {
+    last: RwLock<Option<ProbeResult>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeResult {
+    pub at: Instant,
+    pub latency: Duration,
+    /// Latest fetched block per chain id, when the probe succeeded.
+    pub blocks: Result<BTreeMap<String, u64>, String>,
+    /// Chain head per `staleness.chains` entry that could be read.
+    pub heads: BTreeMap<String, u64>,
+    /// Failed probes in a row, including this one.
+    pub consecutive_failures: u64,
+}
+
+/// How far one chain's latest fetched block is behind its head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lag {
+    pub chain_id: String,
+    pub latest_block: u64,
+    pub head_block: u64,
+}
+
+impl Lag {
+    pub fn blocks(&self) -> u64 {
+        self.head_block.saturating_sub(self.latest_block)
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "chainId": self.chain_id,
+            "latestBlock": self.latest_block,
+            "headBlock": self.head_block,
+            "blockLag": self.blocks(),
+        })
+    }
+}
+
+impl ProbeResult {
+    /// The largest lag over `chain_id`, or over every checked chain for the default endpoint.
+    fn worst_lag(&self, chain_id: Option<&str>) -> Option<Lag> {
+        let blocks = self.blocks.as_ref().ok()?;
+        self.heads
+            .iter()
+            .filter(|(id, _)| chain_id.is_none_or(|chain_id| chain_id == id.as_str()))
+            .filter_map(|(id, head)| {
+                Some(Lag {
+                    chain_id: id.clone(),
+                    latest_block: *blocks.get(id)?,
+                    head_block: *head,
+                })
+            })
+            .max_by_key(Lag::blocks)
+    }
+}
+
+impl Probe {
+    pub fn last(&self) -> Option<ProbeResult> {
+        self.last.read().unwrap().clone()
+    }
+
+    fn record(
+        &self,
+        latency: Duration,
+        blocks: Result<BTreeMap<String, u64>, String>,
+        heads: BTreeMap<String, u64>,
+    ) {
+        let mut last = self.last.write().unwrap();
+        let failures = last
+            .as_ref()
+            .map_or(0, |result| result.consecutive_failures);
+        *last = Some(ProbeResult {
+            at: Instant::now(),
+            latency,
+            consecutive_failures: if blocks.is_ok() { 0 } else { failures + 1 },
+            blocks,
+            heads,
+        });
+    }
+
+    /// Hyperindex answered the last probe.
+    fn up(&self) -> bool {
+        self.last().is_some_and(|result| result.blocks.is_ok())
+    }
+}
+
+/// Probes the default upstream every `health.probe_interval_secs` until the process exits.
+pub fn spawn(state: AppState) {
+    let interval = Duration::from_secs(state.config.health.probe_interval_secs);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            probe(&state).await;
+        }
+    });
+}
+
+/// Runs one `chain_metadata` query against the default upstream, reads the head of every
+/// `staleness.chains` entry, and caches the outcome.
+pub async fn probe(state: &AppState) {
+    let start = Instant::now();
+    let body = serde_json::json!({ "query": PROBE_QUERY });
+    let blocks = match forward_to_hyperindex(state, None, &HeaderMap::new(), &body).await {
+        Ok(response) => latest_blocks(&response, "latest_fetched_block_number"),
+        Err(e) => Err(format!("Hyperindex request failed: {}", e)),
+    };
+    let latency = start.elapsed();
+    if let Err(e) = &blocks {
+        tracing::warn!("Upstream health probe failed: {}", e);
+    }
+
+    let heads = join_all(state.config.staleness.chains.iter().map(
+        |(chain_id, source)| async move {
+            match chain_head(state, source).await {
+                Ok(head) => Some((chain_id.clone(), head)),
+                Err(e) => {
+                    tracing::warn!("Failed to read the head of chain {}: {}", chain_id, e);
+                    None
+                }
+            }
+        },
+    ))
+    .await;
+    state
+        .health
+        .record(latency, blocks, heads.into_iter().flatten().collect());
+}
+
+async fn chain_head(state: &AppState, source: &ChainHeadSource) -> Result<u64, String> {
+    let (url, body) = match (&source.rpc_url, &source.subgraph_url) {
+        (Some(url), _) => (
+            url,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] }),
+        ),
+        (None, Some(url)) => (
+            url,
+            serde_json::json!({ "query": "{ _meta { block { number } } }" }),
+        ),
+        (None, None) => return Err("no rpc_url or subgraph_url".to_string()),
+    };
+    let response: Value = state
+        .http
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let head = match &response["result"] {
+        Value::String(hex) => u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok(),
+        _ => response["data"]["_meta"]["block"]["number"].as_u64(),
+    };
+    head.ok_or_else(|| format!("unexpected response {}", response))
+}
+
+/// `column` per chain id of a `chain_metadata` query response.
+pub fn latest_blocks(response: &Value, column: &str) -> Result<BTreeMap<String, u64>, String> {
+    if let Some(errors) = response.get("errors") {
+        return Err(format!("Hyperindex returned errors: {}", errors));
+    }
+    let Value::Array(rows) = &response["data"]["chain_metadata"] else {
+        return Err("Hyperindex returned no chain_metadata".to_string());
+    };
+    Ok(blocks(rows, column))
+}
+
+/// `column` (e.g. `latest_fetched_block_number`) per chain id of `chain_metadata` rows.
+pub fn blocks(rows: &[Value], column: &str) -> BTreeMap<String, u64> {
+    rows.iter()
+        .filter_map(|row| {
+            let chain_id = match &row["chain_id"] {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            Some((chain_id, row[column].as_u64()?))
+        })
+        .collect()
+}
+
+/// Whether probing is configured; without it the readiness and fail-fast checks pass.
+fn enabled(state: &AppState) -> bool {
+    state.config.health.probe_interval_secs > 0
+}
+
+/// `GET /readyz`: 503 until the first probe succeeds and whenever the last one failed.
+pub async fn handle_readyz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    if !enabled(&state) {
+        return (StatusCode::OK, Json(serde_json::json!({ "status": "ok" })));
+    }
+    let Some(result) = state.health.last() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "starting" })),
+        );
+    };
+    let body = serde_json::json!({
+        "latencyMs": result.latency.as_secs_f64() * 1000.0,
+        "checkedSecondsAgo": result.at.elapsed().as_secs(),
+    });
+    match result.blocks {
+        Ok(blocks) => (
+            StatusCode::OK,
+            Json(merge(
+                body,
+                serde_json::json!({ "status": "ok", "latestBlocks": blocks }),
+            )),
+        ),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(merge(
+                body,
+                serde_json::json!({
+                    "status": "unavailable",
+                    "error": e,
+                    "consecutiveFailures": result.consecutive_failures,
+                }),
+            )),
+        ),
+    }
+}
+
+fn merge(mut base: Value, extra: Value) -> Value {
+    if let (Value::Object(base), Value::Object(extra)) = (&mut base, extra) {
+        base.extend(extra);
+    }
+    base
+}
+
+/// `GET /metrics`: the cached probe result in the Prometheus text format.
+pub async fn handle_metrics(State(state): State<AppState>) -> Response {
+    let mut out = String::new();
+    if let Some(result) = state.health.last().filter(|_| enabled(&state)) {
+        let up = u8::from(result.blocks.is_ok());
+        out.push_str("# HELP hyperindex_up Whether the last upstream probe succeeded.\n");
+        out.push_str("# TYPE hyperindex_up gauge\n");
+        out.push_str(&format!("hyperindex_up {}\n", up));
+        out.push_str(
+            "# HELP hyperindex_probe_latency_seconds Duration of the last upstream probe.\n",
+        );
+        out.push_str("# TYPE hyperindex_probe_latency_seconds gauge\n");
+        out.push_str(&format!(
+            "hyperindex_probe_latency_seconds {}\n",
+            result.latency.as_secs_f64()
+        ));
+        out.push_str("# HELP hyperindex_probe_age_seconds Time since the last upstream probe.\n");
+        out.push_str("# TYPE hyperindex_probe_age_seconds gauge\n");
+        out.push_str(&format!(
+            "hyperindex_probe_age_seconds {}\n",
+            result.at.elapsed().as_secs_f64()
+        ));
+        out.push_str(
+            "# HELP hyperindex_probe_consecutive_failures Failed upstream probes in a row.\n",
+        );
+        out.push_str("# TYPE hyperindex_probe_consecutive_failures gauge\n");
+        out.push_str(&format!(
+            "hyperindex_probe_consecutive_failures {}\n",
+            result.consecutive_failures
+        ));
+        if let Ok(blocks) = &result.blocks {
+            out.push_str(
+                "# HELP hyperindex_latest_block Latest block fetched by Hyperindex per chain.\n",
+            );
+            out.push_str("# TYPE hyperindex_latest_block gauge\n");
+            for (chain_id, block) in blocks {
+                out.push_str(&format!(
+                    "hyperindex_latest_block{{chain_id=\"{}\"}} {}\n",
+                    label(chain_id),
+                    block
+                ));
+            }
+        }
+        if !result.heads.is_empty() {
+            out.push_str(
+                "# HELP hyperindex_chain_head_block Chain head read from staleness.chains.\n",
+            );
+            out.push_str("# TYPE hyperindex_chain_head_block gauge\n");
+            for (chain_id, head) in &result.heads {
+                out.push_str(&format!(
+                    "hyperindex_chain_head_block{{chain_id=\"{}\"}} {}\n",
+                    label(chain_id),
+                    head
+                ));
+            }
+        }
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response()
+}
+
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Middleware answering queries with 503 straight away while the last probe of the default
+/// upstream failed, when `health.fail_fast` is set. Chains with their own upstream aren't
+/// probed and always go through.
+pub async fn fail_fast(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.config.health.fail_fast
+        || !enabled(&state)
+        || probed_scope(&state, &request).is_none()
+        || state.health.last().is_none()
+        || state.health.up()
+    {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "errors": [{
+                "message": "Hyperindex is unavailable",
+                "extensions": { "code": "UPSTREAM_UNAVAILABLE" },
+            }]
+        })),
+    )
+        .into_response()
+}
+
+/// Middleware applying `staleness.action` to queries whose chain (every checked chain for the
+/// default endpoint) is more than `staleness.max_block_lag` blocks behind its head at the last
+/// probe. Chains missing from the probe, or whose head couldn't be read, are never stale.
+pub async fn guard_staleness(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let max_lag = state.config.staleness.max_block_lag;
+    let lag = match (probed_scope(&state, &request), state.health.last()) {
+        (Some(chain_id), Some(result)) if max_lag > 0 => result
+            .worst_lag(chain_id.as_deref())
+            .filter(|lag| lag.blocks() > max_lag),
+        _ => None,
+    };
+    let Some(lag) = lag else {
+        return next.run(request).await;
+    };
+
+    if state.config.staleness.action == StaleAction::Reject {
+        let mut extensions = lag.to_json();
+        extensions["code"] = "STALE_DATA".into();
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "errors": [{
+                    "message": format!(
+                        "Hyperindex is {} blocks behind the head of chain {}",
+                        lag.blocks(),
+                        lag.chain_id
+                    ),
+                    "extensions": extensions,
+                }]
+            })),
+        )
+            .into_response();
+    }
+
+    let response = next.run(request).await;
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(BLOCK_LAG_HEADER, lag.blocks().into());
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return Response::from_parts(parts, body);
+    }
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, parts.headers).into_response();
+    };
+    let mut json = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(json)) => json,
+        _ => return Response::from_parts(parts, axum::body::Body::from(bytes)),
+    };
+    let extensions = json
+        .entry("extensions")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(extensions) = extensions {
+        extensions.insert("staleness".to_string(), lag.to_json());
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::to_vec(&json).unwrap_or_default();
+    Response::from_parts(parts, axum::body::Body::from(body))
+}
+
+/// Which chain a query request reads from the probed default upstream: `Some(None)` for the
+/// default endpoint, `Some(Some(id))` for a chain endpoint, `None` for requests the probe says
+/// nothing about (other routes, chains with their own upstream, canary traffic).
+fn probed_scope(state: &AppState, request: &Request) -> Option<Option<String>> {
+    if canary::requested(request.headers()) == Some(canary::Target::Canary) {
+        return None;
+    }
+    let path = request.uri().path();
+    if path == "/" {
+        return Some(None);
+    }
+    let segment = path.strip_prefix("/chainId/")?.split('/').next()?;
+    let chain_id = chains::resolve(&state.config, segment).ok()?;
+    let own_upstream = state
+        .config
+        .chains
+        .get(&chain_id)
+        .is_some_and(|chain| chain.hyperindex_url.is_some());
+    (!own_upstream).then_some(Some(chain_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::{routing::post, Router};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    async fn serve(healthy: Arc<AtomicBool>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route(
+                "/rpc",
+                post(|| async { Json(serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": "0x1f4" })) }),
+            )
+            .route(
+            "/",
+            post(move || async move {
+                if healthy.load(Ordering::Relaxed) {
+                    Json(serde_json::json!({ "data": { "chain_metadata": [
+                        { "chain_id": 1, "latest_fetched_block_number": 100 },
+                        { "chain_id": 10, "latest_fetched_block_number": 200 },
+                    ] } }))
+                } else {
+                    Json(serde_json::json!({ "errors": [{ "message": "database unavailable" }] }))
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_probe_drives_readyz_and_metrics() {
+        let healthy = Arc::new(AtomicBool::new(true));
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = serve(healthy.clone()).await;
+        config.health.probe_interval_secs = 30;
+        let state = AppState::new(config);
+
+        let (status, _) = handle_readyz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        probe(&state).await;
+        let (status, Json(body)) = handle_readyz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["latestBlocks"],
+            serde_json::json!({ "1": 100, "10": 200 })
+        );
+
+        let metrics = handle_metrics(State(state.clone())).await;
+        let bytes = axum::body::to_bytes(metrics.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains("hyperindex_up 1\n"));
+        assert!(text.contains("hyperindex_latest_block{chain_id=\"10\"} 200\n"));
+
+        healthy.store(false, Ordering::Relaxed);
+        probe(&state).await;
+        probe(&state).await;
+        let (status, Json(body)) = handle_readyz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["consecutiveFailures"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_ready_when_probing_disabled() {
+        let (status, _) = handle_readyz(State(AppState::new(Config::default()))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_staleness_warns_or_rejects() {
+        let upstream = serve(Arc::new(AtomicBool::new(true))).await;
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = upstream.clone();
+        config.health.probe_interval_secs = 30;
+        config.staleness.max_block_lag = 300;
+        config.staleness.chains.insert(
+            "1".to_string(),
+            ChainHeadSource {
+                rpc_url: Some(format!("{}rpc", upstream)),
+                subgraph_url: None,
+            },
+        );
+        let state = AppState::new(config);
+        probe(&state).await;
+        let lag = state.health.last().unwrap().worst_lag(None).unwrap();
+        assert_eq!((lag.chain_id.as_str(), lag.blocks()), ("1", 400));
+        assert!(state.health.last().unwrap().worst_lag(Some("10")).is_none());
+
+        let proxy = |state: AppState| async move {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let ok = || async { Json(serde_json::json!({ "data": { "streams": [] } })) };
+            let app = Router::new()
+                .route("/", post(ok))
+                .route("/chainId/:chain_id", post(ok))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    guard_staleness,
+                ))
+                .with_state(state);
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            format!("http://{}", addr)
+        };
+
+        let url = proxy(state.clone()).await;
+        let response = state.http.post(format!("{}/", url)).send().await.unwrap();
+        assert_eq!(response.headers()[BLOCK_LAG_HEADER], "400");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["data"]["streams"], serde_json::json!([]));
+        assert_eq!(body["extensions"]["staleness"]["headBlock"], 500);
+        let response = state
+            .http
+            .post(format!("{}/chainId/10", url))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.headers().get(BLOCK_LAG_HEADER).is_none());
+
+        let mut config = (*state.config).clone();
+        config.staleness.action = StaleAction::Reject;
+        let rejecting = AppState {
+            config: Arc::new(config),
+            ..state.clone()
+        };
+        let url = proxy(rejecting).await;
+        let response = state
+            .http
+            .post(format!("{}/chainId/1", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["errors"][0]["extensions"]["code"], "STALE_DATA");
+        assert_eq!(body["errors"][0]["extensions"]["blockLag"], 400);
+    }
+}
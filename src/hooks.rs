@@ -0,0 +1,175 @@
+use axum::http::{HeaderMap, StatusCode};
+use serde_json::Value;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// The request a hook is running for.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// Set for the `/chainId/{chain_id}` routes.
+    pub chain_id: Option<String>,
+    /// Headers of the HTTP request, or of the WebSocket upgrade for subscriptions.
+    pub headers: HeaderMap,
+}
+
+/// A hook refusing to serve a request, returned to the client as a GraphQL error.
+#[derive(Debug, Error)]
+#[error("{message}")]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+/// Rewrites queries around the core conversion, e.g. to add a tenant scoping filter.
+pub trait QueryRewriter: Send + Sync {
+    /// Runs on the incoming subgraph request (`query`, `variables`) before it is converted.
+    fn before_conversion(
+        &self,
+        _context: &RequestContext,
+        _payload: &mut Value,
+    ) -> Result<(), Rejection> {
+        Ok(())
+    }
+
+    /// Runs on the converted Hyperindex request body before it is sent upstream.
+    fn after_conversion(
+        &self,
+        _context: &RequestContext,
+        _body: &mut Value,
+    ) -> Result<(), Rejection> {
+        Ok(())
+    }
+}
+
+/// Rewrites responses after they are mapped back to the subgraph shape, e.g. to redact fields.
+pub trait ResponseRewriter: Send + Sync {
+    fn rewrite_response(&self, context: &RequestContext, response: &mut Value);
+}
+
+/// Rewriters registered on `AppState`, run in registration order on `/`, `/chainId/{chain_id}`,
+/// live queries and subscriptions.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    queries: Vec<Arc<dyn QueryRewriter>>,
+    responses: Vec<Arc<dyn ResponseRewriter>>,
+}
+
+impl Hooks {
+    pub fn with_query_rewriter(mut self, rewriter: impl QueryRewriter + 'static) -> Self {
+        self.queries.push(Arc::new(rewriter));
+        self
+    }
+
+    pub fn with_response_rewriter(mut self, rewriter: impl ResponseRewriter + 'static) -> Self {
+        self.responses.push(Arc::new(rewriter));
+        self
+    }
+
+    pub fn before_conversion(
+        &self,
+        context: &RequestContext,
+        payload: &mut Value,
+    ) -> Result<(), Rejection> {
+        self.queries
+            .iter()
+            .try_for_each(|rewriter| rewriter.before_conversion(context, payload))
+    }
+
+    pub fn after_conversion(
+        &self,
+        context: &RequestContext,
+        body: &mut Value,
+    ) -> Result<(), Rejection> {
+        self.queries
+            .iter()
+            .try_for_each(|rewriter| rewriter.after_conversion(context, body))
+    }
+
+    pub fn rewrite_response(&self, context: &RequestContext, response: &mut Value) {
+        for rewriter in &self.responses {
+            rewriter.rewrite_response(context, response);
+        }
+    }
+}
+
+impl Rejection {
+    /// GraphQL error body for HTTP responses and subscription `error` frames.
+    pub fn errors(&self) -> Value {
+        serde_json::json!([{
+            "message": self.message,
+            "extensions": { "code": "REJECTED" },
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only lets tenants see their own streams, by header
+    struct TenantScope;
+
+    impl QueryRewriter for TenantScope {
+        fn before_conversion(
+            &self,
+            context: &RequestContext,
+            payload: &mut Value,
+        ) -> Result<(), Rejection> {
+            let tenant = context
+                .headers
+                .get("x-tenant")
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| Rejection {
+                    status: StatusCode::FORBIDDEN,
+                    message: "x-tenant is required".to_string(),
+                })?;
+            payload["variables"]["tenant"] = Value::from(tenant);
+            Ok(())
+        }
+
+        fn after_conversion(
+            &self,
+            _context: &RequestContext,
+            body: &mut Value,
+        ) -> Result<(), Rejection> {
+            body["query"] = Value::from(body["query"].as_str().unwrap_or_default().to_uppercase());
+            Ok(())
+        }
+    }
+
+    struct Redact(&'static str);
+
+    impl ResponseRewriter for Redact {
+        fn rewrite_response(&self, _context: &RequestContext, response: &mut Value) {
+            if let Some(data) = response.get_mut("data").and_then(Value::as_object_mut) {
+                data.remove(self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hooks_run_in_order() {
+        let hooks = Hooks::default()
+            .with_query_rewriter(TenantScope)
+            .with_response_rewriter(Redact("secrets"))
+            .with_response_rewriter(Redact("tokens"));
+
+        let mut payload = serde_json::json!({ "query": "{ streams { id } }" });
+        let rejected = hooks.before_conversion(&RequestContext::default(), &mut payload);
+        assert_eq!(rejected.unwrap_err().status, StatusCode::FORBIDDEN);
+
+        let mut context = RequestContext::default();
+        context.headers.insert("x-tenant", "acme".parse().unwrap());
+        hooks.before_conversion(&context, &mut payload).unwrap();
+        assert_eq!(payload["variables"]["tenant"], "acme");
+
+        let mut body = serde_json::json!({ "query": "query { Stream { id } }" });
+        hooks.after_conversion(&context, &mut body).unwrap();
+        assert_eq!(body["query"], "QUERY { STREAM { ID } }");
+
+        let mut response =
+            serde_json::json!({ "data": { "streams": [], "secrets": [], "tokens": [] } });
+        hooks.rewrite_response(&context, &mut response);
+        assert_eq!(response, serde_json::json!({ "data": { "streams": [] } }));
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use tiny_keccak::{Hasher, Keccak};
+
+/// Casing Hyperindex uses for `0x` addresses stored in ids. Subgraphs always use lowercase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressCase {
+    /// Leave ids exactly as sent and received.
+    #[default]
+    Preserve,
+    /// Addresses are stored lowercase.
+    Lower,
+    /// Addresses are stored EIP-55 checksummed.
+    Checksum,
+}
+
+const ADDRESS_LEN: usize = 42;
+
+/// Recases a leading `0x` address in `id`, keeping any suffix of composite ids
+/// (e.g. `0xAbC...-42`). Ids that don't start with an address are returned unchanged.
+pub fn recase_address(id: &str, case: AddressCase) -> String {
+    if case == AddressCase::Preserve || !starts_with_address(id) {
+        return id.to_string();
+    }
+    let (address, suffix) = id.split_at(ADDRESS_LEN);
+    let address = match case {
+        AddressCase::Lower | AddressCase::Preserve => address.to_ascii_lowercase(),
+        AddressCase::Checksum => checksum(address),
+    };
+    format!("{}{}", address, suffix)
+}
+
+/// Recases every address-prefixed string literal in a GraphQL argument value
+/// (`"0x..."`, `["0x...", ...]`, `{ id: "0x..." }`).
+pub fn recase_string_literals(text: &str, case: AddressCase) -> String {
+    if case == AddressCase::Preserve {
+        return text.to_string();
+    }
+    map_string_literals(text, |literal| recase_address(literal, case))
+}
+
+/// Normalizes hex string literals in a `Bytes` filter value to lowercase `0x`-prefixed form,
+/// the way subgraphs serialize bytes. With `strip_prefix` the `0x` is dropped instead, for
+/// substring matches. Non-hex literals are left unchanged.
+pub fn normalize_bytes_literals(text: &str, strip_prefix: bool) -> String {
+    map_string_literals(text, |literal| {
+        let digits = literal
+            .strip_prefix("0x")
+            .or_else(|| literal.strip_prefix("0X"))
+            .unwrap_or(literal);
+        if literal.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return literal.to_string();
+        }
+        let digits = digits.to_ascii_lowercase();
+        if strip_prefix {
+            digits
+        } else {
+            format!("0x{}", digits)
+        }
+    })
+}
+
+// Rewrites the contents of every string literal in `text` with `f`.
+fn map_string_literals(text: &str, f: impl Fn(&str) -> String) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('"') {
+        output.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+        let end = literal_end(rest);
+        output.push_str(&f(&rest[..end]));
+        rest = &rest[end..];
+        if let Some(quote) = rest.strip_prefix('"') {
+            output.push('"');
+            rest = quote;
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+// Byte offset of the closing quote of a string literal, skipping escaped quotes.
+fn literal_end(literal: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in literal.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return i,
+            _ => escaped = false,
+        }
+    }
+    literal.len()
+}
+
+fn starts_with_address(id: &str) -> bool {
+    let bytes = id.as_bytes();
+    bytes.len() >= ADDRESS_LEN
+        && bytes[..2].eq_ignore_ascii_case(b"0x")
+        && bytes[2..ADDRESS_LEN].iter().all(u8::is_ascii_hexdigit)
+}
+
+// EIP-55: uppercase each hex letter whose nibble in keccak256(lowercase hex) is >= 8.
+fn checksum(address: &str) -> String {
+    let hex = address[2..].to_ascii_lowercase();
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(hex.as_bytes());
+    keccak.finalize(&mut hash);
+
+    let mut output = String::from("0x");
+    for (i, c) in hex.chars().enumerate() {
+        let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+        if c.is_ascii_alphabetic() && nibble >= 8 {
+            output.push(c.to_ascii_uppercase());
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn test_checksum_matches_eip55() {
+        assert_eq!(
+            recase_address(&CHECKSUMMED.to_ascii_lowercase(), AddressCase::Checksum),
+            CHECKSUMMED
+        );
+        assert_eq!(
+            recase_address(
+                "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+                AddressCase::Checksum
+            ),
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"
+        );
+    }
+
+    #[test]
+    fn test_recase_keeps_composite_suffix_and_other_ids() {
+        let composite = format!("{}-42", CHECKSUMMED);
+        assert_eq!(
+            recase_address(&composite, AddressCase::Lower),
+            format!("{}-42", CHECKSUMMED.to_ascii_lowercase())
+        );
+        assert_eq!(
+            recase_address("Batch-0xABC", AddressCase::Lower),
+            "Batch-0xABC"
+        );
+        assert_eq!(
+            recase_address(CHECKSUMMED, AddressCase::Preserve),
+            CHECKSUMMED
+        );
+    }
+
+    #[test]
+    fn test_normalize_bytes_literals() {
+        assert_eq!(
+            normalize_bytes_literals(r#"["0xABcd", "EF", "name"]"#, false),
+            r#"["0xabcd", "0xef", "name"]"#
+        );
+        assert_eq!(normalize_bytes_literals(r#""0xAB""#, true), r#""ab""#);
+    }
+
+    #[test]
+    fn test_recase_string_literals() {
+        let lower = CHECKSUMMED.to_ascii_lowercase();
+        let text = format!(r#"{{ id_in: ["{}", "x\"y"], name: "Alice" }}"#, lower);
+        assert_eq!(
+            recase_string_literals(&text, AddressCase::Checksum),
+            format!(r#"{{ id_in: ["{}", "x\"y"], name: "Alice" }}"#, CHECKSUMMED)
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! The latest block Hyperindex has indexed, returned with query responses so clients can check
+//! data freshness without polling `_meta` in a second request, or fetched first to pin a query
+//! to one block for consistent reads.
+
+use axum::http::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::conversion::ConversionOptions;
+use crate::{canary, forward_to_hyperindex, health, AppState};
+
+/// Response header with the latest indexed block: the number alone for one chain, otherwise
+/// `<chain id>=<block>` pairs such as `1=19000000, 10=117000000`.
+pub const INDEXED_BLOCK_HEADER: &str = "x-indexed-block";
+
+/// Alias of the `chain_metadata` root field added to converted queries.
+const FIELD: &str = "__indexed_block";
+
+/// Where the latest indexed block of a response comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexedBlock {
+    /// Not reported.
+    #[default]
+    Off,
+    /// A `chain_metadata` selection added to every converted query, so the block is read in
+    /// the same request as the data.
+    Query,
+    /// The last background health probe, without changing queries.
+    Probe,
+}
+
+/// Adds the `chain_metadata` selection to a converted query body in `query` mode.
+pub fn piggyback(mode: IndexedBlock, body: &mut Value) {
+    if mode != IndexedBlock::Query {
+        return;
+    }
+    let Some(query) = body.get("query").and_then(Value::as_str) else {
+        return;
+    };
+    let Some(close) = query.rfind('}') else {
+        return;
+    };
+    let (head, tail) = query.split_at(close);
+    // Pretty-printed queries close on a line of their own; minified ones don't
+    let query = if head.ends_with('\n') {
+        format!(
+            "{}  {}: chain_metadata {{ chain_id latest_fetched_block_number }}\n{}",
+            head, FIELD, tail
+        )
+    } else {
+        format!(
+            "{} {}:chain_metadata{{chain_id latest_fetched_block_number}}{}",
+            head, FIELD, tail
+        )
+    };
+    body["query"] = Value::String(query);
+}
+
+/// The latest indexed block per chain id for a response to a `chain_id` endpoint (every chain
+/// for `/`), taking the added selection out of `response` in `query` mode. In `probe` mode,
+/// chains with their own upstream and canary traffic aren't reported, as the probe only asks
+/// the default upstream.
+pub fn take(
+    state: &AppState,
+    chain_id: Option<&str>,
+    headers: &HeaderMap,
+    response: &mut Value,
+) -> Option<BTreeMap<String, u64>> {
+    let blocks = match state.config.response.indexed_block {
+        IndexedBlock::Off => return None,
+        IndexedBlock::Query => {
+            let rows = response.get_mut("data")?.as_object_mut()?.remove(FIELD)?;
+            health::blocks(rows.as_array()?, "latest_fetched_block_number")
+        }
+        IndexedBlock::Probe => {
+            let own_upstream = chain_id
+                .and_then(|id| state.config.chains.get(id))
+                .is_some_and(|chain| chain.hyperindex_url.is_some());
+            if own_upstream || canary::requested(headers) == Some(canary::Target::Canary) {
+                return None;
+            }
+            state.health.last()?.blocks.ok()?
+        }
+    };
+    let blocks: BTreeMap<String, u64> = blocks
+        .into_iter()
+        .filter(|(id, _)| chain_id.is_none_or(|chain_id| chain_id == id))
+        .collect();
+    (!blocks.is_empty()).then_some(blocks)
+}
+
+/// Pins `options` to the latest block Hyperindex has processed on each chain (the request's
+/// chain on a `chain_id` endpoint) when `consistent_reads` is on. Rows of a block still being
+/// processed may already be written, so processed rather than fetched blocks are used. When
+/// the blocks can't be read, the query runs unpinned.
+pub async fn pin<'a>(
+    state: &AppState,
+    chain_id: Option<&str>,
+    headers: &HeaderMap,
+    options: Cow<'a, ConversionOptions>,
+) -> Cow<'a, ConversionOptions> {
+    if !state.config.consistent_reads.enabled {
+        return options;
+    }
+    let body = serde_json::json!({ "query": PIN_QUERY });
+    let blocks = match forward_to_hyperindex(state, chain_id, headers, &body).await {
+        Ok(response) => health::latest_blocks(&response, "latest_processed_block"),
+        Err(e) => Err(format!("Hyperindex request failed: {}", e)),
+    };
+    let pinned_blocks: BTreeMap<String, u64> = match blocks {
+        Ok(blocks) => blocks
+            .into_iter()
+            .filter(|(id, _)| chain_id.is_none_or(|chain_id| chain_id == id))
+            .collect(),
+        Err(e) => {
+            tracing::warn!(
+                "Running query unpinned, latest processed blocks unavailable: {}",
+                e
+            );
+            return options;
+        }
+    };
+    Cow::Owned(ConversionOptions {
+        pinned_blocks,
+        ..options.into_owned()
+    })
+}
+
+const PIN_QUERY: &str = "query { chain_metadata { chain_id latest_processed_block } }";
+
+/// Reports `blocks` in the `x-indexed-block` header and `extensions.indexedBlocks`.
+pub fn attach(blocks: &BTreeMap<String, u64>, headers: &mut HeaderMap, response: &mut Value) {
+    let value = match blocks.iter().collect::<Vec<_>>().as_slice() {
+        [(_, block)] => block.to_string(),
+        pairs => pairs
+            .iter()
+            .map(|(chain_id, block)| format!("{}={}", chain_id, block))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(INDEXED_BLOCK_HEADER, value);
+    }
+    if let Some(extensions) = response
+        .get_mut("extensions")
+        .and_then(Value::as_object_mut)
+    {
+        extensions.insert("indexedBlocks".to_string(), serde_json::json!(blocks));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_piggyback() {
+        let mut body = serde_json::json!({ "query": "query {\n  Stream {\n    id\n  }\n}" });
+        piggyback(IndexedBlock::Query, &mut body);
+        assert_eq!(
+            body["query"],
+            "query {\n  Stream {\n    id\n  }\n  __indexed_block: chain_metadata { chain_id latest_fetched_block_number }\n}"
+        );
+        let mut minified = serde_json::json!({ "query": "query{Stream{id}}" });
+        piggyback(IndexedBlock::Query, &mut minified);
+        assert_eq!(
+            minified["query"],
+            "query{Stream{id} __indexed_block:chain_metadata{chain_id latest_fetched_block_number}}"
+        );
+        let mut off = serde_json::json!({ "query": "query{Stream{id}}" });
+        piggyback(IndexedBlock::Probe, &mut off);
+        assert_eq!(off["query"], "query{Stream{id}}");
+    }
+
+    #[test]
+    fn test_take_and_attach() {
+        let mut config = Config::default();
+        config.response.indexed_block = IndexedBlock::Query;
+        let state = AppState::new(config);
+        let upstream = serde_json::json!({ "data": {
+            "Stream": [],
+            "__indexed_block": [
+                { "chain_id": 1, "latest_fetched_block_number": 19000000 },
+                { "chain_id": 10, "latest_fetched_block_number": 117000000 },
+            ],
+        } });
+
+        let mut response = upstream.clone();
+        let blocks = take(&state, None, &HeaderMap::new(), &mut response).unwrap();
+        assert_eq!(response["data"], serde_json::json!({ "Stream": [] }));
+        let mut headers = HeaderMap::new();
+        let mut shaped = serde_json::json!({ "data": {}, "extensions": {} });
+        attach(&blocks, &mut headers, &mut shaped);
+        assert_eq!(headers[INDEXED_BLOCK_HEADER], "1=19000000, 10=117000000");
+        assert_eq!(shaped["extensions"]["indexedBlocks"]["10"], 117000000);
+
+        let mut response = upstream.clone();
+        let blocks = take(&state, Some("10"), &HeaderMap::new(), &mut response).unwrap();
+        let mut headers = HeaderMap::new();
+        attach(&blocks, &mut headers, &mut serde_json::json!({}));
+        assert_eq!(headers[INDEXED_BLOCK_HEADER], "117000000");
+        let mut response = upstream;
+        assert!(take(&state, Some("137"), &HeaderMap::new(), &mut response).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pin() {
+        async fn upstream() -> axum::Json<Value> {
+            axum::Json(serde_json::json!({ "data": { "chain_metadata": [
+                { "chain_id": 1, "latest_processed_block": 19000000 },
+                { "chain_id": 10, "latest_processed_block": 117000000 },
+            ] } }))
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let app = axum::Router::new().route("/", axum::routing::post(upstream));
+            axum::serve(listener, app).await.unwrap();
+        });
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = format!("http://{}/", addr);
+        config.consistent_reads.enabled = true;
+        let state = AppState::new(config);
+        let shared = ConversionOptions::default();
+
+        let options = pin(
+            &state,
+            Some("10"),
+            &HeaderMap::new(),
+            Cow::Borrowed(&shared),
+        )
+        .await;
+        assert_eq!(
+            options.pinned_blocks,
+            BTreeMap::from([("10".to_string(), 117000000)])
+        );
+        let options = pin(&state, None, &HeaderMap::new(), Cow::Borrowed(&shared)).await;
+        assert_eq!(options.pinned_blocks.len(), 2);
+
+        // Unreachable upstreams leave the query unpinned
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = "http://127.0.0.1:1/".to_string();
+        config.consistent_reads.enabled = true;
+        let state = AppState::new(config);
+        let options = pin(&state, None, &HeaderMap::new(), Cow::Borrowed(&shared)).await;
+        assert!(matches!(options, Cow::Borrowed(_)));
+    }
+}
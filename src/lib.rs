@@ -0,0 +1,2200 @@
+//! The subgraph to Hyperindex query conversion pipeline and the HTTP service around it. The
+//! conversion modules can be driven without the service, by benchmarks and test harnesses;
+//! embedders build the service from an [`AppState`] with their [`hooks`] and serve [`router`].
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, Json, Query, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+// use reqwest; // avoid bringing reqwest::StatusCode into scope
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::error::Overloaded;
+use tower::{BoxError, ServiceBuilder};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+mod auth;
+mod canary;
+mod chains;
+pub mod computed;
+pub mod config;
+pub mod conversion;
+pub mod emit;
+mod explain;
+pub mod format;
+mod health;
+pub mod hooks;
+pub mod ids;
+mod indexed_block;
+#[cfg(test)]
+mod integration_tests;
+mod live;
+pub mod logging;
+mod naming;
+pub mod numeric;
+pub mod operation;
+mod persisted;
+pub mod post_filter;
+mod query_log;
+mod response;
+mod scheduler;
+pub mod schema;
+pub mod selection;
+mod self_test;
+mod stats;
+mod status;
+mod subscriptions;
+pub mod transform;
+pub mod types;
+pub mod where_expr;
+
+/// Shared, read-only state handed to every handler.
+#[derive(Clone)]
+pub struct AppState {
+    config: Arc<config::Config>,
+    conversion_options: Arc<conversion::ConversionOptions>,
+    http: reqwest::Client,
+    stats: Arc<stats::Stats>,
+    query_log: Arc<query_log::QueryLog>,
+    /// Requests whose details are logged in full at info level.
+    sampler: Arc<logging::Sampler>,
+    hooks: Arc<hooks::Hooks>,
+    /// Set when `auth.jwks_url` is configured.
+    auth: Option<Arc<auth::Authenticator>>,
+    /// Splits traffic onto `upstream.canary_url` when it is set.
+    canary: Arc<canary::Splitter>,
+    /// Last result of the background upstream probe.
+    health: Arc<health::Probe>,
+    /// Queues upstream requests beyond `limits.max_upstream_requests` by priority.
+    scheduler: Arc<scheduler::Scheduler>,
+    /// Operations of `persisted.manifest`, loaded after the conversion options are final.
+    persisted: Arc<persisted::Manifest>,
+}
+
+// The emitter for a configured naming convention; `None` is Hasura's own spelling.
+fn emitter(naming: config::Naming) -> Option<Arc<dyn emit::Emitter>> {
+    match naming {
+        config::Naming::SnakeCase => Some(Arc::new(emit::SnakeCase)),
+        config::Naming::Pascal | config::Naming::Introspect => None,
+    }
+}
+
+impl AppState {
+    /// State for a validated `config`, with no hooks. Call [`AppState::start`] before serving.
+    pub fn new(config: config::Config) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.limits.upstream_timeout_secs))
+            .build()
+            .expect("Failed to build HTTP client");
+        let chain_column = conversion::ChainColumn {
+            field: config.chain_filter.field.clone(),
+            kind: config.chain_filter.kind,
+        };
+        let chain_columns = config
+            .chain_filter
+            .entities
+            .iter()
+            .map(|(entity, column)| {
+                let column = conversion::ChainColumn {
+                    field: column.field.clone().unwrap_or(chain_column.field.clone()),
+                    kind: column.kind.unwrap_or(chain_column.kind),
+                };
+                (entity.clone(), column)
+            })
+            .collect();
+        let conversion_options = conversion::ConversionOptions {
+            entity_names: config.mappings.entities.clone(),
+            primary_keys: config.mappings.primary_keys.clone(),
+            address_case: config.ids.address_case,
+            interfaces: config.mappings.interfaces.clone(),
+            aggregates: config.extensions.aggregates,
+            total_count: false,
+            distinct_on: config.extensions.distinct_on,
+            mode: config.conversion.mode,
+            post_filters: config.conversion.post_filters.clone(),
+            post_filter_max_rows: config.conversion.post_filter_max_rows,
+            raw_like_patterns: config.conversion.raw_like_patterns,
+            numeric_literals: config.conversion.numeric_literals,
+            transforms: config.mappings.transforms.clone(),
+            redacted: config.redaction.fields.clone(),
+            pinned_blocks: BTreeMap::new(),
+            block_column: config.consistent_reads.column.clone(),
+            block_columns: config.consistent_reads.entities.clone(),
+            schema: config.schema.clone(),
+            forward_mutations: config.conversion.forward_mutations,
+            validate_schema: config.conversion.validate_schema,
+            minify_queries: config.conversion.minify_queries,
+            trace: false,
+            probe: false,
+            subscriptions: false,
+            chain_column,
+            chain_columns,
+            chain_agnostic_entities: config.chain_filter.skip_entities.clone(),
+            required_filters: HashMap::new(),
+            text_search_columns: config.mappings.text_search.clone(),
+            columns: config.mappings.columns.clone(),
+            relationships: config.mappings.relationships.clone(),
+            foreign_keys: config.mappings.foreign_keys.clone(),
+            computed: config
+                .mappings
+                .computed
+                .iter()
+                .map(|(entity, fields)| {
+                    let fields = fields
+                        .iter()
+                        .map(|(field, expr)| {
+                            (field.clone(), expr.parse().expect("validated at startup"))
+                        })
+                        .collect();
+                    (entity.clone(), fields)
+                })
+                .collect(),
+            max_query_bytes: config.conversion.max_query_bytes,
+            parse_check: config.conversion.parse_check,
+            inject_ids: config.conversion.inject_ids,
+            inline_variables: config.conversion.inline_variables,
+            default_order_by: config.conversion.default_order_by.clone().map(|field| {
+                let direction = config
+                    .conversion
+                    .default_order_direction
+                    .to_ascii_lowercase();
+                (field, direction)
+            }),
+            max_rows: config
+                .limits
+                .entities
+                .iter()
+                .filter_map(|(entity, limits)| Some((entity.clone(), limits.max_rows?)))
+                .collect(),
+            nested_first_default: config.conversion.nested_first_default,
+            nested_first_max: config.conversion.nested_first_max,
+            emitter: emitter(config.conversion.naming),
+            upstream_fields: None,
+        };
+        Self {
+            stats: Arc::new(stats::Stats::new(&config.stats)),
+            query_log: Arc::new(query_log::QueryLog::new(&config.query_log)),
+            sampler: Arc::new(logging::Sampler::new(config.logging.verbose_sample_rate)),
+            hooks: Arc::default(),
+            auth: auth::Authenticator::new(&config.auth, http.clone()).map(Arc::new),
+            canary: Arc::new(canary::Splitter::new(config.upstream.canary_percent)),
+            health: Arc::default(),
+            scheduler: Arc::new(scheduler::Scheduler::new(
+                config.limits.max_upstream_requests,
+            )),
+            persisted: Arc::default(),
+            config: Arc::new(config),
+            conversion_options: Arc::new(conversion_options),
+            http,
+        }
+    }
+
+    /// Runs `hooks` around conversion on every query, live query and subscription.
+    pub fn with_hooks(mut self, hooks: hooks::Hooks) -> Self {
+        self.hooks = Arc::new(hooks);
+        self
+    }
+
+    /// Does the configured startup work: fetches JWKS, introspects Hyperindex naming and
+    /// fields, loads the persisted operation manifest, runs the self-test and spawns the
+    /// background probe and stats flush. Returns the problems that must keep the service from
+    /// serving traffic.
+    pub async fn start(mut self) -> Result<Self, Vec<String>> {
+        if let Some(auth) = &self.auth {
+            auth.refresh().await;
+        }
+        if self.config.conversion.naming == config::Naming::Introspect {
+            match naming::detect(&self).await {
+                Ok(naming) => {
+                    tracing::info!("Hyperindex schema uses {:?} naming", naming);
+                    let mut options = (*self.conversion_options).clone();
+                    options.emitter = emitter(naming);
+                    self.conversion_options = Arc::new(options);
+                }
+                Err(e) => tracing::warn!("Could not detect Hyperindex naming, using pascal: {}", e),
+            }
+        }
+        if self.config.conversion.strip_missing_fields {
+            match naming::upstream_fields(&self).await {
+                Ok(fields) => {
+                    let mut options = (*self.conversion_options).clone();
+                    options.upstream_fields = Some(Arc::new(fields));
+                    self.conversion_options = Arc::new(options);
+                }
+                Err(e) => tracing::warn!(
+                    "Could not introspect Hyperindex fields, missing fields won't be stripped: {}",
+                    e
+                ),
+            }
+        }
+        if self.config.persisted.manifest.is_some() {
+            match persisted::Manifest::load(&self.config, &self.conversion_options) {
+                Ok(manifest) => {
+                    tracing::info!("Loaded {} persisted operations", manifest.len());
+                    self.persisted = Arc::new(manifest);
+                }
+                Err(problems) => {
+                    return Err(problems
+                        .into_iter()
+                        .map(|problem| format!("Invalid persisted operation manifest: {}", problem))
+                        .collect());
+                }
+            }
+        }
+        if !self.config.self_test.queries.is_empty() {
+            match self_test::run(&self).await {
+                Ok(()) => tracing::info!(
+                    "Self-test passed ({} queries)",
+                    self.config.self_test.queries.len()
+                ),
+                Err(failures) => {
+                    let failures = failures
+                        .iter()
+                        .map(|failure| format!("Self-test failed: {}", failure));
+                    if self.config.self_test.exit_on_failure {
+                        return Err(failures
+                            .chain(std::iter::once(
+                                "Refusing to serve traffic until the self-test passes".to_string(),
+                            ))
+                            .collect());
+                    }
+                    for failure in failures {
+                        tracing::error!("{}", failure);
+                    }
+                }
+            }
+        }
+        if self.config.health.probe_interval_secs > 0 {
+            health::spawn(self.clone());
+        }
+        if self.stats.persisted() {
+            let stats = self.stats.clone();
+            let interval = Duration::from_secs(self.config.stats.flush_interval_secs);
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    let stats = stats.clone();
+                    let _ = tokio::task::spawn_blocking(move || stats.flush()).await;
+                }
+            });
+        }
+        Ok(self)
+    }
+}
+
+/// The service's routes and middleware over a started `state`.
+pub fn router(state: AppState) -> Router {
+    let allow_origin = if state.config.cors.allowed_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            state
+                .config
+                .cors
+                .allowed_origins
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin).expect("validated at startup")),
+        )
+    };
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            axum::http::Method::GET,
+            axum::http::Method::POST,
+            axum::http::Method::OPTIONS,
+        ])
+        .allow_headers(Any)
+        // Lets frontends read the converted query from script, not only the network tab
+        .expose_headers([
+            HeaderName::from_static(CONVERTED_QUERY_HEADER),
+            HeaderName::from_static(canary::TARGET_HEADER),
+            HeaderName::from_static(logging::REQUEST_ID_HEADER),
+            HeaderName::from_static(health::BLOCK_LAG_HEADER),
+            HeaderName::from_static(indexed_block::INDEXED_BLOCK_HEADER),
+        ]);
+    let max_body_bytes = state.config.limits.max_body_bytes;
+    let max_concurrent_requests = state.config.limits.max_concurrent_requests;
+    // Kubernetes probes and Prometheus scrapers send no credentials, so these skip `auth`.
+    // They only report the cached upstream probe, never indexed data.
+    let probes = Router::new()
+        .route("/readyz", get(health::handle_readyz))
+        .route("/metrics", get(health::handle_metrics));
+
+    Router::new()
+        .route("/", post(handle_query).get(subscriptions::handle_ws))
+        .route("/debug", post(handle_debug))
+        .route("/explain", post(explain::handle_explain))
+        .route("/admin/stats", get(stats::handle_stats))
+        .route("/live", get(live::handle_live))
+        .route("/status", get(status::handle_status))
+        .route("/index-node/graphql", post(status::handle_index_node))
+        .route(
+            "/chainId/:chain_id",
+            post(handle_chain_query).get(subscriptions::handle_chain_ws),
+        )
+        .route("/chainId/:chain_id/debug", post(handle_chain_debug))
+        .route(
+            "/chainId/:chain_id/explain",
+            post(explain::handle_chain_explain),
+        )
+        .route("/chainId/:chain_id/live", get(live::handle_chain_live))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            health::guard_staleness,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            health::fail_fast,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), canary::route))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            check_url_override,
+        ))
+        .layer(middleware::from_fn(logging::assign_request_id))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ))
+        .merge(probes)
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        // Shed load instead of queueing once the global in-flight limit is reached, so bursts
+        // don't pile up on the upstream indexer
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_overload))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(max_concurrent_requests)),
+        )
+        // Entity lists compress well; negotiated via the client's Accept-Encoding
+        .layer(CompressionLayer::new().gzip(true).br(true))
+        .layer(cors)
+        .with_state(state)
+}
+
+async fn handle_query(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Json(mut payload): Json<Value>,
+) -> impl IntoResponse {
+    let verbose = state.sampler.sample();
+    if verbose {
+        tracing::info!("Received query: {:?}", payload);
+    }
+
+    let persisted = match state.persisted.resolve(&mut payload) {
+        Ok(persisted) => persisted,
+        Err(rejection) => return rejected(rejection),
+    };
+    let context = hooks::RequestContext {
+        chain_id: None,
+        headers: headers.clone(),
+    };
+    if let Err(rejection) = state.hooks.before_conversion(&context, &mut payload) {
+        return rejected(rejection);
+    }
+
+    let conversion_start = Instant::now();
+    let options = conversion_options(&state, &headers);
+    let options = indexed_block::pin(&state, None, &headers, options).await;
+    // Startup conversions were made with the shared options; requests with their own convert
+    let precompiled = persisted
+        .filter(|_| matches!(options, Cow::Borrowed(_)))
+        .and_then(|operation| operation.converted(&payload, None));
+    let result = match precompiled {
+        Some(converted) => Ok(converted),
+        None => conversion_span(&headers, None).in_scope(|| {
+            conversion::convert_subgraph_to_hyperindex_with_options(&payload, None, &options)
+        }),
+    };
+    state.stats.record(&result);
+    match result {
+        Ok(mut converted) => {
+            if let Err(rejection) = state.hooks.after_conversion(&context, &mut converted.body) {
+                return rejected(rejection);
+            }
+            if !converted.forwarded {
+                indexed_block::piggyback(state.config.response.indexed_block, &mut converted.body);
+            }
+            let converted_query = &converted.body;
+            let conversion_ms = elapsed_ms(conversion_start);
+            if verbose {
+                tracing::info!("Converted query: {:?}", converted_query);
+            }
+
+            let mut response_headers = converted_query_headers(&state, &headers, converted_query);
+
+            // Forward the converted query to Hyperindex
+            let upstream_start = Instant::now();
+            // Runs next to the Hyperindex request so failures don't wait on two round trips
+            let subgraph_debug = SubgraphDebugFetch::start(&state, &headers, &payload);
+            let timeout = entity_timeout(&state.config, &converted.usage.entities);
+            let upstream =
+                forward_with_timeout(&state, None, &headers, converted_query, timeout).await;
+            state.query_log.record(
+                None,
+                &payload,
+                query_log::Outcome::Forwarded {
+                    converted: &converted,
+                    upstream: &upstream,
+                },
+            );
+            match upstream {
+                Ok(mut response) => {
+                    let upstream_ms = elapsed_ms(upstream_start);
+                    if verbose {
+                        tracing::info!("Hyperindex response: {:?}", response);
+                    }
+                    // If upstream returned GraphQL errors, surface them with debug info
+                    if response.get("errors").is_some() {
+                        let hyperindex_url = hyperindex_url(&state, None, &headers);
+                        let subgraph_debug = subgraph_debug.finish().await;
+                        // Log both original and converted queries for debugging
+                        let original_query = payload
+                            .get("query")
+                            .and_then(|q| q.as_str())
+                            .unwrap_or_default();
+                        let converted_query_str = converted_query
+                            .get("query")
+                            .and_then(|q| q.as_str())
+                            .unwrap_or_default();
+                        tracing::error!(
+                            original_query = original_query,
+                            converted_query = converted_query_str,
+                            errors = ?response.get("errors"),
+                            "Upstream GraphQL returned errors for converted query"
+                        );
+                        let debug = serde_json::json!({
+                            "originalQuery": original_query,
+                            "convertedQuery": converted_query_str,
+                            "hyperindexUrl": hyperindex_url,
+                        });
+                        return (
+                            upstream_errors_status(&response["errors"]),
+                            response_headers,
+                            Json(serde_json::json!({
+                                "errors": response.get("errors").cloned().unwrap_or_default(),
+                                "debug": debug,
+                                "subgraphResponse": subgraph_debug,
+                            })),
+                        );
+                    }
+
+                    let indexed_blocks = indexed_block::take(&state, None, &headers, &mut response);
+                    let mode =
+                        response::requested_mode(&headers, &params, state.config.response.mode);
+                    let transformed =
+                        response::shape_response(response, mode, &converted, &state.config);
+                    let mut transformed = attach_extensions(
+                        transformed,
+                        conversion_ms,
+                        upstream_ms,
+                        converted_query,
+                        wants_converted_query(&headers),
+                    );
+                    if let Some(blocks) = &indexed_blocks {
+                        indexed_block::attach(blocks, &mut response_headers, &mut transformed);
+                    }
+                    state.hooks.rewrite_response(&context, &mut transformed);
+                    (StatusCode::OK, response_headers, Json(transformed))
+                }
+                Err(e) => {
+                    tracing::error!("Hyperindex request error: {}", e);
+                    let hyperindex_url = hyperindex_url(&state, None, &headers);
+                    let details = e.to_string();
+                    let subgraph_debug = subgraph_debug.finish().await;
+                    // Log both original and converted queries for debugging
+                    let original_query = payload
+                        .get("query")
+                        .and_then(|q| q.as_str())
+                        .unwrap_or_default();
+                    let converted_query_str = converted_query
+                        .get("query")
+                        .and_then(|q| q.as_str())
+                        .unwrap_or_default();
+                    tracing::error!(
+                        original_query = original_query,
+                        converted_query = converted_query_str,
+                        error = %details,
+                        "Error forwarding converted query to Hyperindex"
+                    );
+                    (
+                        upstream_failure_status(e.as_ref()),
+                        response_headers,
+                        Json(serde_json::json!({
+                            "error": "Hyperindex request failed",
+                            "details": details,
+                            "upstreamResponse": upstream_failure_response(e.as_ref()),
+                            "debug": {
+                                "originalQuery": original_query,
+                                "convertedQuery": converted_query_str,
+                                "hyperindexUrl": hyperindex_url,
+                            },
+                            "subgraphResponse": subgraph_debug,
+                        })),
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(payload = ?payload, "Conversion error: {}", e);
+            state
+                .query_log
+                .record(None, &payload, query_log::Outcome::Rejected(&e));
+            let reasoning = conversion_error_reasoning(&e);
+            let details = e.to_string();
+            let locations = e.locations(
+                payload
+                    .get("query")
+                    .and_then(|q| q.as_str())
+                    .unwrap_or_default(),
+            );
+            let subgraph_debug = maybe_fetch_subgraph_debug(&state, &headers, &payload).await;
+            (
+                conversion_error_status(&e),
+                HeaderMap::new(),
+                Json(serde_json::json!({
+                    "error": "Conversion failed",
+                    "details": details,
+                    "reasoning": reasoning,
+                    "locations": locations,
+                    "extensions": { "code": e.code() },
+                    "debug": {
+                        "inputQuery": payload.get("query").and_then(|q| q.as_str()).unwrap_or_default(),
+                        "convertedQuery": e.converted_query(),
+                        "chainId": serde_json::Value::Null,
+                    },
+                    "subgraphResponse": subgraph_debug,
+                })),
+            )
+        }
+    }
+}
+
+async fn handle_chain_query(
+    State(state): State<AppState>,
+    chains::ChainId(chain_id): chains::ChainId,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    Json(mut payload): Json<Value>,
+) -> impl IntoResponse {
+    let verbose = state.sampler.sample();
+    if verbose {
+        tracing::info!(
+            "Received chain query for chain_id: {}, payload: {:?}",
+            chain_id,
+            payload
+        );
+    }
+
+    let persisted = match state.persisted.resolve(&mut payload) {
+        Ok(persisted) => persisted,
+        Err(rejection) => return rejected(rejection),
+    };
+    let context = hooks::RequestContext {
+        chain_id: Some(chain_id.clone()),
+        headers: headers.clone(),
+    };
+    if let Err(rejection) = state.hooks.before_conversion(&context, &mut payload) {
+        return rejected(rejection);
+    }
+
+    let conversion_start = Instant::now();
+    let options = conversion_options(&state, &headers);
+    let options = indexed_block::pin(&state, Some(&chain_id), &headers, options).await;
+    // Startup conversions were made with the shared options; requests with their own convert
+    let precompiled = persisted
+        .filter(|_| matches!(options, Cow::Borrowed(_)))
+        .and_then(|operation| operation.converted(&payload, Some(&chain_id)));
+    let result = match precompiled {
+        Some(converted) => Ok(converted),
+        None => conversion_span(&headers, Some(&chain_id)).in_scope(|| {
+            conversion::convert_subgraph_to_hyperindex_with_options(
+                &payload,
+                Some(&chain_id),
+                &options,
+            )
+        }),
+    };
+    state.stats.record(&result);
+    match result {
+        Ok(mut converted) => {
+            if let Err(rejection) = state.hooks.after_conversion(&context, &mut converted.body) {
+                return rejected(rejection);
+            }
+            if !converted.forwarded {
+                indexed_block::piggyback(state.config.response.indexed_block, &mut converted.body);
+            }
+            let converted_query = &converted.body;
+            let conversion_ms = elapsed_ms(conversion_start);
+            if verbose {
+                tracing::info!("Converted chain query: {:?}", converted_query);
+            }
+
+            let mut response_headers = converted_query_headers(&state, &headers, converted_query);
+
+            // Forward the converted query to Hyperindex
+            let upstream_start = Instant::now();
+            // Runs next to the Hyperindex request so failures don't wait on two round trips
+            let subgraph_debug = SubgraphDebugFetch::start(&state, &headers, &payload);
+            let timeout = entity_timeout(&state.config, &converted.usage.entities);
+            let upstream =
+                forward_with_timeout(&state, Some(&chain_id), &headers, converted_query, timeout)
+                    .await;
+            state.query_log.record(
+                Some(&chain_id),
+                &payload,
+                query_log::Outcome::Forwarded {
+                    converted: &converted,
+                    upstream: &upstream,
+                },
+            );
+            match upstream {
+                Ok(mut response) => {
+                    let upstream_ms = elapsed_ms(upstream_start);
+                    if verbose {
+                        tracing::info!("Hyperindex response: {:?}", response);
+                    }
+                    if response.get("errors").is_some() {
+                        let hyperindex_url = hyperindex_url(&state, Some(&chain_id), &headers);
+                        let subgraph_debug = subgraph_debug.finish().await;
+                        // Log both original and converted queries for debugging
+                        let original_query = payload
+                            .get("query")
+                            .and_then(|q| q.as_str())
+                            .unwrap_or_default();
+                        let converted_query_str = converted_query
+                            .get("query")
+                            .and_then(|q| q.as_str())
+                            .unwrap_or_default();
+                        tracing::error!(
+                            original_query = original_query,
+                            converted_query = converted_query_str,
+                            chain_id = %chain_id,
+                            errors = ?response.get("errors"),
+                            "Upstream GraphQL returned errors for converted chain query"
+                        );
+                        let debug = serde_json::json!({
+                            "originalQuery": original_query,
+                            "convertedQuery": converted_query_str,
+                            "hyperindexUrl": hyperindex_url,
+                            "chainId": chain_id,
+                        });
+                        return (
+                            upstream_errors_status(&response["errors"]),
+                            response_headers,
+                            Json(serde_json::json!({
+                                "errors": response.get("errors").cloned().unwrap_or_default(),
+                                "debug": debug,
+                                "subgraphResponse": subgraph_debug,
+                            })),
+                        );
+                    }
+
+                    let indexed_blocks =
+                        indexed_block::take(&state, Some(&chain_id), &headers, &mut response);
+                    let mode =
+                        response::requested_mode(&headers, &params, state.config.response.mode);
+                    let transformed =
+                        response::shape_response(response, mode, &converted, &state.config);
+                    let mut transformed = attach_extensions(
+                        transformed,
+                        conversion_ms,
+                        upstream_ms,
+                        converted_query,
+                        wants_converted_query(&headers),
+                    );
+                    if let Some(blocks) = &indexed_blocks {
+                        indexed_block::attach(blocks, &mut response_headers, &mut transformed);
+                    }
+                    state.hooks.rewrite_response(&context, &mut transformed);
+                    (StatusCode::OK, response_headers, Json(transformed))
+                }
+                Err(e) => {
+                    tracing::error!("Hyperindex request error: {}", e);
+                    let hyperindex_url = hyperindex_url(&state, Some(&chain_id), &headers);
+                    let details = e.to_string();
+                    let subgraph_debug = subgraph_debug.finish().await;
+                    // Log both original and converted queries for debugging
+                    let original_query = payload
+                        .get("query")
+                        .and_then(|q| q.as_str())
+                        .unwrap_or_default();
+                    let converted_query_str = converted_query
+                        .get("query")
+                        .and_then(|q| q.as_str())
+                        .unwrap_or_default();
+                    tracing::error!(
+                        original_query = original_query,
+                        converted_query = converted_query_str,
+                        chain_id = %chain_id,
+                        error = %details,
+                        "Error forwarding converted chain query to Hyperindex"
+                    );
+                    (
+                        upstream_failure_status(e.as_ref()),
+                        response_headers,
+                        Json(serde_json::json!({
+                            "error": "Hyperindex request failed",
+                            "details": details,
+                            "upstreamResponse": upstream_failure_response(e.as_ref()),
+                            "debug": {
+                                "originalQuery": original_query,
+                                "convertedQuery": converted_query_str,
+                                "hyperindexUrl": hyperindex_url,
+                                "chainId": chain_id,
+                            },
+                            "subgraphResponse": subgraph_debug,
+                        })),
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(payload = ?payload, "Conversion error: {}", e);
+            state
+                .query_log
+                .record(Some(&chain_id), &payload, query_log::Outcome::Rejected(&e));
+            let reasoning = conversion_error_reasoning(&e);
+            let details = e.to_string();
+            let locations = e.locations(
+                payload
+                    .get("query")
+                    .and_then(|q| q.as_str())
+                    .unwrap_or_default(),
+            );
+            let subgraph_debug = maybe_fetch_subgraph_debug(&state, &headers, &payload).await;
+            (
+                conversion_error_status(&e),
+                HeaderMap::new(),
+                Json(serde_json::json!({
+                    "error": "Conversion failed",
+                    "details": details,
+                    "reasoning": reasoning,
+                    "locations": locations,
+                    "extensions": { "code": e.code() },
+                    "debug": {
+                        "inputQuery": payload.get("query").and_then(|q| q.as_str()).unwrap_or_default(),
+                        "convertedQuery": e.converted_query(),
+                        "chainId": chain_id,
+                    },
+                    "subgraphResponse": subgraph_debug,
+                })),
+            )
+        }
+    }
+}
+
+async fn handle_debug(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<Value>,
+) -> impl IntoResponse {
+    tracing::info!("Received debug query: {:?}", payload);
+    if let Err(rejection) = state.persisted.resolve(&mut payload) {
+        let (status, _, body) = rejected(rejection);
+        return (status, body);
+    }
+
+    match conversion_span(&headers, None).in_scope(|| {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            None,
+            &conversion_options(&state, &headers),
+        )
+    }) {
+        Ok(converted) => {
+            tracing::info!("Converted debug query: {:?}", converted.body);
+            (StatusCode::OK, Json(debug_body(converted)))
+        }
+        Err(e) => {
+            tracing::error!("Debug conversion error: {}", e);
+            let reasoning = conversion_error_reasoning(&e);
+            let details = e.to_string();
+            let locations = e.locations(
+                payload
+                    .get("query")
+                    .and_then(|q| q.as_str())
+                    .unwrap_or_default(),
+            );
+            let subgraph_debug = maybe_fetch_subgraph_debug(&state, &headers, &payload).await;
+            (
+                conversion_error_status(&e),
+                Json(serde_json::json!({
+                    "error": "Conversion failed",
+                    "details": details,
+                    "reasoning": reasoning,
+                    "locations": locations,
+                    "extensions": { "code": e.code() },
+                    "debug": {
+                        "inputQuery": payload.get("query").and_then(|q| q.as_str()).unwrap_or_default(),
+                        "convertedQuery": e.converted_query(),
+                        "chainId": serde_json::Value::Null,
+                    },
+                    "subgraphResponse": subgraph_debug,
+                })),
+            )
+        }
+    }
+}
+
+async fn handle_chain_debug(
+    State(state): State<AppState>,
+    chains::ChainId(chain_id): chains::ChainId,
+    headers: HeaderMap,
+    Json(mut payload): Json<Value>,
+) -> impl IntoResponse {
+    tracing::info!(
+        "Received chain debug for chain_id: {}, payload: {:?}",
+        chain_id,
+        payload
+    );
+    if let Err(rejection) = state.persisted.resolve(&mut payload) {
+        let (status, _, body) = rejected(rejection);
+        return (status, body);
+    }
+
+    match conversion_span(&headers, Some(&chain_id)).in_scope(|| {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            Some(&chain_id),
+            &conversion_options(&state, &headers),
+        )
+    }) {
+        Ok(converted) => {
+            tracing::info!("Converted chain debug query: {:?}", converted.body);
+            (StatusCode::OK, Json(debug_body(converted)))
+        }
+        Err(e) => {
+            tracing::error!("Chain debug conversion error: {}", e);
+            let reasoning = conversion_error_reasoning(&e);
+            let details = e.to_string();
+            let locations = e.locations(
+                payload
+                    .get("query")
+                    .and_then(|q| q.as_str())
+                    .unwrap_or_default(),
+            );
+            let subgraph_debug = maybe_fetch_subgraph_debug(&state, &headers, &payload).await;
+            (
+                conversion_error_status(&e),
+                Json(serde_json::json!({
+                    "error": "Conversion failed",
+                    "details": details,
+                    "reasoning": reasoning,
+                    "locations": locations,
+                    "extensions": { "code": e.code() },
+                    "debug": {
+                        "inputQuery": payload.get("query").and_then(|q| q.as_str()).unwrap_or_default(),
+                        "convertedQuery": e.converted_query(),
+                        "chainId": chain_id,
+                    },
+                    "subgraphResponse": subgraph_debug,
+                })),
+            )
+        }
+    }
+}
+
+async fn handle_overload(err: BoxError) -> (StatusCode, Json<Value>) {
+    if err.is::<Overloaded>() {
+        tracing::warn!("Concurrency limit reached, shedding request");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "errors": [{
+                    "message": "Server is at capacity, retry shortly",
+                    "extensions": { "code": "SERVICE_UNAVAILABLE" },
+                }],
+            })),
+        )
+    } else {
+        tracing::error!("Unhandled middleware error: {}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "errors": [{
+                    "message": err.to_string(),
+                    "extensions": { "code": "INTERNAL_SERVER_ERROR" },
+                }],
+            })),
+        )
+    }
+}
+
+async fn forward_to_hyperindex(
+    state: &AppState,
+    chain_id: Option<&str>,
+    headers: &HeaderMap,
+    query: &Value,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    forward_with_timeout(state, chain_id, headers, query, None).await
+}
+
+// `forward_to_hyperindex`, failing after `timeout` if that is shorter than the deadline the
+// request would otherwise get.
+async fn forward_with_timeout(
+    state: &AppState,
+    chain_id: Option<&str>,
+    headers: &HeaderMap,
+    query: &Value,
+    timeout: Option<Duration>,
+) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let hyperindex_url = hyperindex_url(state, chain_id, headers);
+
+    let body = serde_json::to_vec(query)?;
+    let mut request = state
+        .http
+        .post(hyperindex_url)
+        .header("Content-Type", "application/json")
+        .headers(upstream_headers(&state.config, headers));
+    if let Some(signing) = state
+        .config
+        .upstream
+        .signing
+        .as_ref()
+        .filter(|_| !is_overridden(&state.config, headers))
+    {
+        request = request.header(signing.header.as_str(), sign(&signing.secret, &body));
+    }
+    let deadline = match (request_timeout(&state.config, headers), timeout) {
+        (Some(requested), Some(timeout)) => requested.min(timeout),
+        (requested, timeout) => requested.or(timeout).unwrap_or(Duration::from_secs(
+            state.config.limits.upstream_timeout_secs,
+        )),
+    };
+    // Waiting for a slot counts towards the deadline, so queued work can't hold a request
+    // forever. The permit is held until the body is read.
+    let started = Instant::now();
+    let _permit = tokio::time::timeout(
+        deadline,
+        state.scheduler.acquire(scheduler::requested(headers)),
+    )
+    .await
+    .map_err(|_| UpstreamError::Queued { deadline })?;
+    request = request.timeout(deadline.saturating_sub(started.elapsed()));
+    let response = request.body(body).send().await?;
+
+    let status = response.status();
+    let body = read_capped(response, state.config.limits.max_upstream_response_bytes).await?;
+    match serde_json::from_slice::<Value>(&body) {
+        // GraphQL errors are answered as such whatever the status, so they can be classified
+        Ok(json) if status.is_success() || json.get("errors").is_some() => Ok(json),
+        Ok(_) => Err(UpstreamError::Status {
+            status,
+            body: truncated_body(&body),
+        }
+        .into()),
+        Err(_) if status.is_success() => Err(UpstreamError::InvalidJson {
+            status,
+            body: truncated_body(&body),
+        }
+        .into()),
+        Err(_) => Err(UpstreamError::Status {
+            status,
+            body: truncated_body(&body),
+        }
+        .into()),
+    }
+}
+
+// Reads the body a chunk at a time, giving up as soon as it grows past `limit` (0 for no limit)
+// rather than buffering a runaway response.
+async fn read_capped(
+    mut response: reqwest::Response,
+    limit: usize,
+) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let too_large = UpstreamError::TooLarge {
+        status: response.status(),
+        limit,
+    };
+    if limit == 0 {
+        return Ok(response.bytes().await?.to_vec());
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > limit as u64)
+    {
+        return Err(too_large.into());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Hyperindex answered, but not with a GraphQL response (an HTML error page from a proxy, a
+/// 401 from a secured Hasura, ...). The start of the body is kept for the error payload.
+#[derive(Debug, thiserror::Error)]
+enum UpstreamError {
+    #[error("Hyperindex responded with HTTP {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("Hyperindex responded with HTTP {status} but the body isn't JSON: {body}")]
+    InvalidJson { status: StatusCode, body: String },
+    #[error("Hyperindex responded with HTTP {status} and a body over the {limit} byte limit (limits.max_upstream_response_bytes)")]
+    TooLarge { status: StatusCode, limit: usize },
+    #[error(
+        "No upstream slot (limits.max_upstream_requests) freed up within the {deadline:?} deadline"
+    )]
+    Queued { deadline: Duration },
+}
+
+// Error pages can be large; the first KiB says what went wrong
+const MAX_UPSTREAM_ERROR_BODY_BYTES: usize = 1024;
+
+fn truncated_body(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    let body = body.trim();
+    if body.len() <= MAX_UPSTREAM_ERROR_BODY_BYTES {
+        return body.to_string();
+    }
+    let mut end = MAX_UPSTREAM_ERROR_BODY_BYTES;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &body[..end])
+}
+
+// The configured upstream for the route (the canary when `canary::route` picked it), or the
+// request's `x-hyperindex-url` when `upstream.allow_url_override` is on. Invalid overrides never
+// get here: `check_url_override` rejects them.
+fn hyperindex_url<'a>(
+    state: &'a AppState,
+    chain_id: Option<&str>,
+    headers: &'a HeaderMap,
+) -> &'a str {
+    let configured = match canary::requested(headers) {
+        Some(canary::Target::Canary) => state.config.canary_url(chain_id),
+        _ => None,
+    }
+    .unwrap_or_else(|| state.config.hyperindex_url(chain_id));
+    match url_override(&state.config, headers) {
+        Some(Ok(url)) => url,
+        _ => configured,
+    }
+}
+
+// The request's `x-hyperindex-url`, or why it can't be used: it isn't an http(s) URL or
+// `upstream.allow_url_override` is off.
+fn url_override<'a>(
+    config: &config::Config,
+    headers: &'a HeaderMap,
+) -> Option<Result<&'a str, String>> {
+    let value = headers.get(HYPERINDEX_URL_HEADER)?;
+    if !config.upstream.allow_url_override {
+        return Some(Err(format!(
+            "{} header is not accepted here (upstream.allow_url_override is off)",
+            HYPERINDEX_URL_HEADER
+        )));
+    }
+    Some(match value.to_str().map(str::trim) {
+        Ok(url) => config::validate_url(url)
+            .map(|()| url)
+            .map_err(|e| format!("{} header {}", HYPERINDEX_URL_HEADER, e)),
+        Err(_) => Err(format!("{} header is not ASCII", HYPERINDEX_URL_HEADER)),
+    })
+}
+
+// Whether the request goes to its own `x-hyperindex-url` rather than a configured upstream.
+fn is_overridden(config: &config::Config, headers: &HeaderMap) -> bool {
+    matches!(url_override(config, headers), Some(Ok(_)))
+}
+
+// Middleware answering 400 to an `x-hyperindex-url` that can't be used, instead of silently
+// querying the configured upstream the client meant to bypass.
+async fn check_url_override(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(Err(message)) = url_override(&state.config, request.headers()) {
+        tracing::info!("Rejected upstream override: {}", message);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "errors": [{
+                    "message": message,
+                    "extensions": { "code": "INVALID_UPSTREAM_URL" },
+                }],
+            })),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+// The upstream deadline the client asked for with `x-request-timeout-ms`, capped at
+// `limits.max_request_timeout_ms`, in place of `limits.upstream_timeout_secs`. Values that
+// aren't a positive number of milliseconds are ignored.
+fn request_timeout(config: &config::Config, headers: &HeaderMap) -> Option<Duration> {
+    let max = config.limits.max_request_timeout_ms;
+    let requested: u64 = headers
+        .get(REQUEST_TIMEOUT_HEADER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    (max > 0 && requested > 0).then(|| Duration::from_millis(requested.min(max)))
+}
+
+// The shortest `limits.entities` timeout of the entities a query selects, bounded by
+// `limits.upstream_timeout_secs`.
+fn entity_timeout(config: &config::Config, entities: &[String]) -> Option<Duration> {
+    entities
+        .iter()
+        .filter_map(|entity| config.limits.entities.get(entity)?.timeout_ms)
+        .min()
+        .map(|ms| {
+            Duration::from_millis(ms).min(Duration::from_secs(config.limits.upstream_timeout_secs))
+        })
+}
+
+// `upstream.headers`, plus the request's claim headers (which `auth::authenticate` only lets
+// through when set from a verified token), e.g. Hasura session variables. A client-chosen
+// `x-hyperindex-url` gets only the claim headers, never secrets such as an admin secret.
+fn upstream_headers(config: &config::Config, headers: &HeaderMap) -> HeaderMap {
+    let mut upstream = HeaderMap::new();
+    let configured = if is_overridden(config, headers) {
+        None
+    } else {
+        Some(&config.upstream.headers)
+    };
+    for (name, value) in configured.into_iter().flatten() {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            upstream.insert(name, value);
+        }
+    }
+    for name in config.auth.claim_headers.keys() {
+        if let Some(value) = headers.get(name.as_str()) {
+            if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                upstream.insert(name, value.clone());
+            }
+        }
+    }
+    upstream
+}
+
+// Hex-encoded HMAC-SHA256 of `body`, for `upstream.signing`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = ring::hmac::sign(&key, body);
+    tag.as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Request header that opts into echoing the converted query in `extensions` and the
+/// `x-converted-query` response header.
+const DEBUG_HEADER: &str = "x-debug";
+/// Response header carrying the converted query (see `converted_query_headers`).
+const CONVERTED_QUERY_HEADER: &str = "x-converted-query";
+// Proxies commonly cap all headers together at 8 KiB
+const MAX_CONVERTED_QUERY_HEADER_BYTES: usize = 4096;
+/// Request header that adds `extensions.totalCount` for each root list.
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+/// Request header choosing `strict` or `lenient` conversion for a single request.
+const CONVERSION_MODE_HEADER: &str = "x-conversion-mode";
+/// Request header that adds the step-by-step conversion trace to `/debug` output.
+const TRACE_HEADER: &str = "x-trace";
+/// Request header replacing the upstream endpoint for one request (see `hyperindex_url`).
+const HYPERINDEX_URL_HEADER: &str = "x-hyperindex-url";
+/// Request header opting a request into the `subgraph_debug` reference fetch on failure.
+const SUBGRAPH_DEBUG_HEADER: &str = "x-subgraph-debug";
+/// Request header setting the upstream deadline for one request (see `request_timeout`).
+const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+// Hasura `extensions.code`s (and the Apollo style equivalents) for queries rejected before
+// running because of their shape; retrying them can't succeed.
+const CLIENT_ERROR_CODES: &[&str] = &[
+    "validation-failed",
+    "parse-failed",
+    "bad-request",
+    "not-supported",
+    "GRAPHQL_VALIDATION_FAILED",
+    "GRAPHQL_PARSE_FAILED",
+    "BAD_USER_INPUT",
+];
+
+// 400 when every upstream GraphQL error is about the query's shape, 502 when any of them
+// happened while running it (or carries no code to tell).
+fn upstream_errors_status(errors: &Value) -> StatusCode {
+    let client_error = |error: &Value| {
+        error["extensions"]["code"]
+            .as_str()
+            .is_some_and(|code| CLIENT_ERROR_CODES.contains(&code))
+    };
+    match errors.as_array() {
+        Some(errors) if !errors.is_empty() && errors.iter().all(client_error) => {
+            StatusCode::BAD_REQUEST
+        }
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
+
+// 504 when Hyperindex didn't answer in time. Auth, rate limiting and unavailability statuses
+// from Hyperindex are passed on so clients can react to them; anything else is a 502.
+fn upstream_failure_status(e: &(dyn std::error::Error + Send + Sync + 'static)) -> StatusCode {
+    match e.downcast_ref::<UpstreamError>() {
+        Some(UpstreamError::Status { status, .. }) => {
+            return match *status {
+                StatusCode::UNAUTHORIZED
+                | StatusCode::FORBIDDEN
+                | StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT => *status,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+        }
+        Some(UpstreamError::Queued { .. }) => return StatusCode::SERVICE_UNAVAILABLE,
+        _ => {}
+    }
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
+
+// The upstream status and (truncated) body for the error payload, when Hyperindex answered.
+fn upstream_failure_response(e: &(dyn std::error::Error + Send + Sync + 'static)) -> Value {
+    match e.downcast_ref::<UpstreamError>() {
+        Some(
+            UpstreamError::Status { status, body } | UpstreamError::InvalidJson { status, body },
+        ) => {
+            serde_json::json!({ "status": status.as_u16(), "body": body })
+        }
+        Some(UpstreamError::TooLarge { status, .. }) => {
+            serde_json::json!({ "status": status.as_u16() })
+        }
+        Some(UpstreamError::Queued { .. }) | None => Value::Null,
+    }
+}
+
+// A request refused by a `QueryRewriter`.
+fn rejected(rejection: hooks::Rejection) -> (StatusCode, HeaderMap, Json<Value>) {
+    tracing::info!("Request rejected: {}", rejection);
+    (
+        rejection.status,
+        HeaderMap::new(),
+        Json(serde_json::json!({ "errors": rejection.errors() })),
+    )
+}
+
+fn conversion_error_reasoning(e: &conversion::ConversionError) -> &'static str {
+    match e {
+        conversion::ConversionError::InvalidQueryFormat =>
+            "The provided GraphQL query string could not be parsed. Ensure it is a valid single operation with balanced braces and proper syntax.",
+        conversion::ConversionError::MissingField(field) =>
+            if field == "query" { "The request body must include a 'query' string field." } else { "A required field is missing from the request." },
+        conversion::ConversionError::UnsupportedFilter(_filter) =>
+            "This filter is not currently supported by the converter. Consider a supported equivalent or remove it.",
+        conversion::ConversionError::InvalidArgument(_argument) =>
+            "An argument has an invalid value. Check the argument named in the details.",
+        conversion::ConversionError::UnsupportedBlockArgument(_argument) =>
+            "Hyperindex has no time-travel queries. Remove the block argument, or send x-conversion-mode: lenient to query the latest state with a warning.",
+        conversion::ConversionError::UnsupportedTextSearch(_argument) =>
+            "Hyperindex has no full-text index, and no text_search columns are configured for this entity. List the columns to search under mappings.text_search.<Entity>, or use a _contains_nocase filter instead.",
+        conversion::ConversionError::UnknownArgument { .. } =>
+            "The root field has an argument that is neither a subgraph argument (first, skip, orderBy, orderDirection, where, block, text, subgraphError) nor a filter on one of its fields. Move filters into where, or send x-conversion-mode: lenient to drop it with a warning.",
+        conversion::ConversionError::ComplexMetaQuery =>
+            "Only _meta { block { number } } is supported. Remove extra fields like hash, timestamp, etc.",
+        conversion::ConversionError::OperationNotSupported(_operation) =>
+            "Only queries are converted over HTTP. Send subscriptions over the WebSocket endpoint; mutations are forwarded only when conversion.forward_mutations is enabled.",
+        conversion::ConversionError::UnknownEntity(_field) =>
+            "The root field's entity is not declared in the configured schema. Check the field name or add a [schema.<Entity>] table.",
+        conversion::ConversionError::UnknownField { .. } =>
+            "The filter references a field the entity does not declare in the configured schema. Check the field name or add it to the schema.",
+        conversion::ConversionError::UnsupportedDirective(_directive) =>
+            "Directives such as @include and @skip are not converted. Remove them, or send x-conversion-mode: lenient to ignore them with a warning.",
+        conversion::ConversionError::VariableTypeMismatch { .. } =>
+            "A variable's value does not match how the query uses it. Check the variables sent with the query.",
+        conversion::ConversionError::MissingRequiredFilter { .. } =>
+            "The entity is scoped by a tenancy rule, and the request lacks the header that rule takes its value from.",
+        conversion::ConversionError::ConflictingFields(_field) =>
+            "Two root fields share a response key but have different arguments. Give each one its own alias, e.g. recent: streams(first: 5) and all: streams.",
+        conversion::ConversionError::ConvertedQueryTooLarge { .. } =>
+            "The converted query is larger than conversion.max_query_bytes allows. Ask for fewer fields or split the query.",
+        conversion::ConversionError::InvalidConvertedQuery { .. } =>
+            "The query was understood but converted into invalid GraphQL, so it was not forwarded. Please report it with the converted query under debug.convertedQuery.",
+    }
+}
+
+// 500 when the converter broke its own output, 400 for problems with the request.
+fn conversion_error_status(e: &conversion::ConversionError) -> StatusCode {
+    match e {
+        conversion::ConversionError::InvalidConvertedQuery { .. } => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// `/debug` output: the converted body in the canonical layout (whatever `minify_queries` is
+/// set to), plus the conversion steps when `x-trace` asked for them.
+fn debug_body(converted: conversion::ConvertedQuery) -> Value {
+    let mut body = converted.body;
+    if let Some(query) = body.get("query").and_then(|q| q.as_str()) {
+        body["query"] = Value::String(format::pretty(query));
+    }
+    if !converted.trace.is_empty() {
+        body["trace"] = serde_json::json!({
+            "entities": converted.trace,
+            "warnings": converted.warnings,
+        });
+    }
+    body
+}
+
+// `x-converted-query` for `/` and `/chainId/{chain_id}` responses. Queries that can't go in a
+// header (too long, or not plain ASCII) are sent as their hash, which the query log records.
+fn converted_query_headers(
+    state: &AppState,
+    headers: &HeaderMap,
+    converted_query: &Value,
+) -> HeaderMap {
+    let mut response_headers = HeaderMap::new();
+    if !(state.config.response.converted_query_header || wants_converted_query(headers)) {
+        return response_headers;
+    }
+    let query = converted_query
+        .get("query")
+        .and_then(|q| q.as_str())
+        .unwrap_or_default();
+    let minified = format::minify(query);
+    let value = Some(minified)
+        .filter(|minified| minified.len() <= MAX_CONVERTED_QUERY_HEADER_BYTES)
+        .and_then(|minified| HeaderValue::from_str(&minified).ok())
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&query_log::query_hash(query)).expect("hex digest")
+        });
+    response_headers.insert(CONVERTED_QUERY_HEADER, value);
+    response_headers
+}
+
+fn wants_converted_query(headers: &HeaderMap) -> bool {
+    header_flag(headers, DEBUG_HEADER)
+}
+
+fn header_flag(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+// The shared conversion options, adjusted by the request's total count and conversion mode
+// headers. Unknown modes are logged and ignored.
+fn conversion_options<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> Cow<'a, conversion::ConversionOptions> {
+    let shared = &state.conversion_options;
+    let total_count = header_flag(headers, TOTAL_COUNT_HEADER);
+    let trace = header_flag(headers, TRACE_HEADER);
+    let mode = match headers
+        .get(CONVERSION_MODE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.parse::<conversion::ConversionMode>())
+    {
+        Some(Ok(mode)) => mode,
+        Some(Err(e)) => {
+            tracing::warn!("Ignoring requested conversion mode: {}", e);
+            shared.mode
+        }
+        None => shared.mode,
+    };
+
+    if total_count == shared.total_count
+        && mode == shared.mode
+        && trace == shared.trace
+        && state.config.tenancy.rules.is_empty()
+    {
+        Cow::Borrowed(shared)
+    } else {
+        Cow::Owned(conversion::ConversionOptions {
+            total_count,
+            mode,
+            trace,
+            required_filters: required_filters(&state.config, headers),
+            ..(**shared).clone()
+        })
+    }
+}
+
+// `tenancy.rules` with their values taken from the request's headers.
+fn required_filters(
+    config: &config::Config,
+    headers: &HeaderMap,
+) -> HashMap<String, Vec<conversion::RequiredFilter>> {
+    let mut filters: HashMap<String, Vec<conversion::RequiredFilter>> = HashMap::new();
+    for rule in &config.tenancy.rules {
+        let value = headers
+            .get(&rule.header)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string);
+        filters
+            .entry(rule.entity.clone())
+            .or_default()
+            .push(conversion::RequiredFilter {
+                field: rule.field.clone(),
+                value,
+            });
+    }
+    filters
+}
+
+// Span around a conversion, so the converter's debug events carry the request they belong to.
+fn conversion_span(headers: &HeaderMap, chain_id: Option<&str>) -> tracing::Span {
+    tracing::info_span!(
+        "conversion",
+        request_id = logging::request_id(headers),
+        chain_id
+    )
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+// Adds proxy timings (and optionally the converted query) under the GraphQL `extensions` key,
+// merging with any extensions already returned by Hyperindex.
+fn attach_extensions(
+    resp: Value,
+    conversion_ms: f64,
+    upstream_ms: f64,
+    converted_query: &Value,
+    include_converted_query: bool,
+) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+
+    let extensions = root
+        .entry("extensions")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(ext) = extensions {
+        ext.insert("conversionMs".to_string(), serde_json::json!(conversion_ms));
+        ext.insert("upstreamMs".to_string(), serde_json::json!(upstream_ms));
+        if include_converted_query {
+            ext.insert(
+                "convertedQuery".to_string(),
+                converted_query.get("query").cloned().unwrap_or(Value::Null),
+            );
+        }
+    }
+
+    Value::Object(root)
+}
+
+/// A subgraph debug fetch started alongside the Hyperindex request. It only matters when the
+/// request fails; dropping it (on success) aborts the fetch.
+struct SubgraphDebugFetch(Option<tokio::task::JoinHandle<Option<Value>>>);
+
+impl SubgraphDebugFetch {
+    fn start(state: &AppState, headers: &HeaderMap, payload: &Value) -> Self {
+        if !wants_subgraph_debug(state, headers) {
+            return Self(None);
+        }
+        let state = state.clone();
+        let headers = headers.clone();
+        let payload = payload.clone();
+        Self(Some(tokio::spawn(async move {
+            maybe_fetch_subgraph_debug(&state, &headers, &payload).await
+        })))
+    }
+
+    async fn finish(mut self) -> Option<Value> {
+        self.0.take()?.await.ok().flatten()
+    }
+}
+
+impl Drop for SubgraphDebugFetch {
+    fn drop(&mut self) {
+        if let Some(task) = &self.0 {
+            task.abort();
+        }
+    }
+}
+
+// A configured subgraph is only sent queries the client opted into (or every failure with
+// `subgraph_debug.always`), since it's an outside party.
+fn wants_subgraph_debug(state: &AppState, headers: &HeaderMap) -> bool {
+    let debug_config = &state.config.subgraph_debug;
+    debug_config.url.is_some()
+        && (debug_config.always || header_flag(headers, SUBGRAPH_DEBUG_HEADER))
+}
+
+// Queries `subgraph_debug.url` with the original query and redacted variables, giving up
+// after `subgraph_debug.timeout_ms`.
+async fn maybe_fetch_subgraph_debug(
+    state: &AppState,
+    headers: &HeaderMap,
+    payload: &Value,
+) -> Option<Value> {
+    if !wants_subgraph_debug(state, headers) {
+        return None;
+    }
+    let debug_config = &state.config.subgraph_debug;
+    let url = debug_config.url.as_deref()?;
+    let mut payload = payload.clone();
+    if debug_config.redact_variables {
+        if let Some(variables) = payload.get_mut("variables") {
+            *variables = query_log::redact_variables(variables, &debug_config.keep_variables);
+        }
+    }
+    let timeout = Duration::from_millis(debug_config.timeout_ms);
+    match tokio::time::timeout(timeout, fetch_subgraph_debug(state, url, payload)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("Subgraph debug fetch timed out after {:?}", timeout);
+            None
+        }
+    }
+}
+
+async fn fetch_subgraph_debug(state: &AppState, url: &str, payload: Value) -> Option<Value> {
+    let debug_config = &state.config.subgraph_debug;
+
+    let mut req = state
+        .http
+        .post(url)
+        .header("Content-Type", "application/json")
+        .json(&payload);
+
+    // Optional auth headers for compatible subgraph endpoints
+    // Priority: explicit custom header/value → bearer token → x-api-key fallbacks
+    if let (Some(header_name), Some(header_value)) = (
+        debug_config.auth_header.as_deref(),
+        debug_config.auth_value.as_deref(),
+    ) {
+        req = req.header(header_name, header_value);
+    } else if let Some(token) = debug_config.bearer_token.as_deref() {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    } else if let Some(key) = debug_config.api_key.as_deref() {
+        req = req.header("x-api-key", key);
+    }
+
+    let resp = match req.send().await {
+        Ok(r) => r,
+        Err(_) => return None,
+    };
+
+    let status = resp.status().as_u16();
+    let body: Value = match resp.json().await {
+        Ok(b) => b,
+        Err(_) => return None,
+    };
+
+    Some(serde_json::json!({
+        "status": status,
+        "body": body,
+    }))
+}
+
+#[cfg(test)]
+mod response_shape_tests {
+    use super::*;
+
+    #[test]
+    fn test_attach_extensions() {
+        let resp = serde_json::json!({ "data": { "streams": [] } });
+        let converted = serde_json::json!({ "query": "query {\n  Stream {\n    id\n  }\n}" });
+
+        let out = attach_extensions(resp.clone(), 1.5, 20.0, &converted, false);
+        let ext = out.get("extensions").unwrap();
+        assert_eq!(ext.get("conversionMs").unwrap(), 1.5);
+        assert_eq!(ext.get("upstreamMs").unwrap(), 20.0);
+        assert!(ext.get("convertedQuery").is_none());
+
+        let out = attach_extensions(resp, 1.5, 20.0, &converted, true);
+        assert_eq!(out["extensions"]["convertedQuery"], converted["query"]);
+    }
+
+    #[test]
+    fn test_wants_converted_query_header() {
+        let mut headers = HeaderMap::new();
+        assert!(!wants_converted_query(&headers));
+        headers.insert(DEBUG_HEADER, "true".parse().unwrap());
+        assert!(wants_converted_query(&headers));
+        headers.insert(DEBUG_HEADER, "0".parse().unwrap());
+        assert!(!wants_converted_query(&headers));
+    }
+
+    #[test]
+    fn test_converted_query_response_header() {
+        let converted = serde_json::json!({ "query": "query {\n  Stream {\n    id\n  }\n}" });
+        let mut headers = HeaderMap::new();
+        let state = AppState::new(config::Config::default());
+        assert!(converted_query_headers(&state, &headers, &converted).is_empty());
+
+        headers.insert(DEBUG_HEADER, "true".parse().unwrap());
+        assert_eq!(
+            converted_query_headers(&state, &headers, &converted)[CONVERTED_QUERY_HEADER],
+            "query{Stream{id}}"
+        );
+
+        let mut config = config::Config::default();
+        config.response.converted_query_header = true;
+        let state = AppState::new(config);
+        let long = serde_json::json!({ "query": format!("query {{ Stream {{ {} }} }}", "id ".repeat(2000)) });
+        let value =
+            &converted_query_headers(&state, &HeaderMap::new(), &long)[CONVERTED_QUERY_HEADER];
+        assert_eq!(
+            value.to_str().unwrap(),
+            query_log::query_hash(long["query"].as_str().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_total_count_header_enables_counts() {
+        let state = AppState::new(config::Config::default());
+        let mut headers = HeaderMap::new();
+        assert!(!conversion_options(&state, &headers).total_count);
+        headers.insert(TOTAL_COUNT_HEADER, "true".parse().unwrap());
+        assert!(conversion_options(&state, &headers).total_count);
+    }
+
+    #[test]
+    fn test_conversion_mode_header() {
+        let state = AppState::new(config::Config::default());
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            conversion_options(&state, &headers),
+            Cow::Borrowed(_)
+        ));
+        headers.insert(CONVERSION_MODE_HEADER, "Lenient".parse().unwrap());
+        assert_eq!(
+            conversion_options(&state, &headers).mode,
+            conversion::ConversionMode::Lenient
+        );
+        headers.insert(CONVERSION_MODE_HEADER, "bogus".parse().unwrap());
+        assert_eq!(
+            conversion_options(&state, &headers).mode,
+            conversion::ConversionMode::Strict
+        );
+    }
+
+    #[test]
+    fn test_debug_body_trace() {
+        let state = AppState::new(config::Config::default());
+        let payload = serde_json::json!({ "query": "{ streams(first: 1) { id } }" });
+        let mut headers = HeaderMap::new();
+
+        let converted = conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            None,
+            &conversion_options(&state, &headers),
+        )
+        .unwrap();
+        assert!(debug_body(converted).get("trace").is_none());
+
+        headers.insert(TRACE_HEADER, "true".parse().unwrap());
+        let converted = conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            None,
+            &conversion_options(&state, &headers),
+        )
+        .unwrap();
+        let body = debug_body(converted);
+        assert_eq!(body["trace"]["entities"][0]["entity"], "Stream");
+        assert_eq!(body["trace"]["entities"][0]["params"]["first"], "1");
+        assert_eq!(body["trace"]["warnings"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_hyperindex_url_override_header() {
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = "http://prod/v1/graphql".to_string();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HYPERINDEX_URL_HEADER,
+            "http://staging/v1/graphql".parse().unwrap(),
+        );
+        let state = AppState::new(config.clone());
+        assert_eq!(
+            hyperindex_url(&state, None, &headers),
+            "http://prod/v1/graphql"
+        );
+
+        config.upstream.allow_url_override = true;
+        let state = AppState::new(config);
+        assert_eq!(
+            hyperindex_url(&state, None, &headers),
+            "http://staging/v1/graphql"
+        );
+        headers.insert(HYPERINDEX_URL_HEADER, "ftp://staging".parse().unwrap());
+        assert!(url_override(&state.config, &headers)
+            .unwrap()
+            .unwrap_err()
+            .contains("must use http or https"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_url_override_is_a_400() {
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = "http://127.0.0.1:9/v1/graphql".to_string();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(AppState::new(config.clone()));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        config.upstream.allow_url_override = true;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let allowed = listener.local_addr().unwrap();
+        let app = router(AppState::new(config));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let http = reqwest::Client::new();
+        for (addr, url, reason) in [
+            (
+                addr,
+                "http://staging/v1/graphql",
+                "allow_url_override is off",
+            ),
+            (allowed, "ftp://staging", "must use http or https"),
+        ] {
+            let response = http
+                .post(format!("http://{}/", addr))
+                .header(HYPERINDEX_URL_HEADER, url)
+                .json(&serde_json::json!({ "query": "{ streams { id } }" }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body: Value = response.json().await.unwrap();
+            let error = &body["errors"][0];
+            assert_eq!(error["extensions"]["code"], "INVALID_UPSTREAM_URL");
+            assert!(
+                error["message"].as_str().unwrap().contains(reason),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_canary_target_header() {
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = "http://prod/v1/graphql".to_string();
+        config.upstream.canary_url = Some("http://canary/v1/graphql".to_string());
+        config.chains.insert(
+            "137".to_string(),
+            config::ChainConfig {
+                hyperindex_url: Some("http://polygon/v1/graphql".to_string()),
+                ..Default::default()
+            },
+        );
+        let state = AppState::new(config);
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            hyperindex_url(&state, None, &headers),
+            "http://prod/v1/graphql"
+        );
+        headers.insert(canary::TARGET_HEADER, "canary".parse().unwrap());
+        assert_eq!(
+            hyperindex_url(&state, Some("1"), &headers),
+            "http://canary/v1/graphql"
+        );
+        // Chains with their own upstream aren't split
+        assert_eq!(
+            hyperindex_url(&state, Some("137"), &headers),
+            "http://polygon/v1/graphql"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_saturated_scheduler_times_out() {
+        let mut config = config::Config::default();
+        config.limits.max_upstream_requests = 1;
+        let state = AppState::new(config);
+        let _running = state
+            .scheduler
+            .acquire(scheduler::Priority::Background)
+            .await;
+
+        let query = serde_json::json!({ "query": "query { Stream { id } }" });
+        let started = Instant::now();
+        let error = forward_with_timeout(
+            &state,
+            None,
+            &HeaderMap::new(),
+            &query,
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            upstream_failure_status(error.as_ref()),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probes_skip_auth() {
+        let mut config = config::Config::default();
+        config.auth.jwks_url = Some("http://127.0.0.1:9/jwks.json".to_string());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router(AppState::new(config));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let http = reqwest::Client::new();
+        for path in ["/readyz", "/metrics"] {
+            let response = http
+                .get(format!("http://{}{}", addr, path))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        }
+        for path in ["/status", "/admin/stats"] {
+            let response = http
+                .get(format!("http://{}{}", addr, path))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overload_maps_to_503() {
+        let (status, Json(body)) = handle_overload(Box::new(Overloaded::new())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body["errors"][0]["extensions"]["code"],
+            "SERVICE_UNAVAILABLE"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subgraph_debug_fetch_is_bounded() {
+        use axum::{routing::post, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            // Echoes what the subgraph was sent
+            .route(
+                "/fast",
+                post(|Json(body): Json<Value>| async { Json(body) }),
+            )
+            .route(
+                "/slow",
+                post(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Json(Value::Null)
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = config::Config::default();
+        config.subgraph_debug.url = Some(format!("http://{}/fast", addr));
+        config.subgraph_debug.timeout_ms = 200;
+        config.subgraph_debug.keep_variables = vec!["first".to_string()];
+        let payload = serde_json::json!({
+            "query": "query($first: Int, $owner: String) { streams(first: $first, where: {owner: $owner}) { id } }",
+            "variables": { "first": 10, "owner": "0xabc" },
+        });
+        let mut headers = HeaderMap::new();
+        let state = AppState::new(config.clone());
+        assert!(SubgraphDebugFetch::start(&state, &headers, &payload)
+            .finish()
+            .await
+            .is_none());
+        headers.insert(SUBGRAPH_DEBUG_HEADER, "true".parse().unwrap());
+        let fetched = SubgraphDebugFetch::start(&state, &headers, &payload)
+            .finish()
+            .await
+            .unwrap();
+        assert_eq!(fetched["status"], 200);
+        assert_eq!(fetched["body"]["query"], payload["query"]);
+        assert_eq!(
+            fetched["body"]["variables"],
+            serde_json::json!({ "first": 10, "owner": "[REDACTED]" })
+        );
+
+        config.subgraph_debug.url = Some(format!("http://{}/slow", addr));
+        let start = Instant::now();
+        let fetched = SubgraphDebugFetch::start(&AppState::new(config), &headers, &payload)
+            .finish()
+            .await;
+        assert!(fetched.is_none());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_url_override_gets_no_upstream_secrets() {
+        use axum::{routing::post, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Answers with the credentials it was sent
+        let app = Router::new().route(
+            "/",
+            post(|headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string)
+                };
+                Json(serde_json::json!({
+                    "data": {
+                        "secret": header("x-hasura-admin-secret"),
+                        "signature": header("x-indexer-signature"),
+                    }
+                }))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = format!("http://{}/", addr);
+        config.upstream.allow_url_override = true;
+        config.upstream.headers =
+            HashMap::from([("x-hasura-admin-secret".to_string(), "admin".to_string())]);
+        config.upstream.signing = Some(config::SigningConfig {
+            secret: "shared".to_string(),
+            header: "x-indexer-signature".to_string(),
+        });
+        let state = AppState::new(config);
+        let query = serde_json::json!({ "query": "query { Stream { id } }" });
+
+        let configured = forward_to_hyperindex(&state, None, &HeaderMap::new(), &query)
+            .await
+            .unwrap();
+        assert_eq!(configured["data"]["secret"], "admin");
+        assert!(configured["data"]["signature"].is_string());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HYPERINDEX_URL_HEADER,
+            format!("http://{}/", addr).parse().unwrap(),
+        );
+        let overridden = forward_to_hyperindex(&state, None, &headers, &query)
+            .await
+            .unwrap();
+        assert_eq!(overridden["data"]["secret"], Value::Null);
+        assert_eq!(overridden["data"]["signature"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_signs_upstream_requests() {
+        use axum::{body::Bytes, routing::post, Router};
+
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Answers with the signature header and what it should be for the body received
+        let app = Router::new().route(
+            "/",
+            post(|headers: HeaderMap, body: Bytes| async move {
+                Json(serde_json::json!({
+                    "data": {
+                        "received": headers.get("x-indexer-signature").and_then(|v| v.to_str().ok()),
+                        "expected": sign("shared", &body),
+                    }
+                }))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = format!("http://{}/", addr);
+        config.upstream.signing = Some(config::SigningConfig {
+            secret: "shared".to_string(),
+            header: "x-indexer-signature".to_string(),
+        });
+        let query = serde_json::json!({ "query": "query { Stream { id } }" });
+        let response =
+            forward_to_hyperindex(&AppState::new(config), None, &HeaderMap::new(), &query)
+                .await
+                .unwrap();
+        assert_eq!(response["data"]["received"], response["data"]["expected"]);
+    }
+
+    #[test]
+    fn test_request_timeout_header() {
+        let mut config = config::Config::default();
+        let timeout = |config: &config::Config, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(REQUEST_TIMEOUT_HEADER, value.parse().unwrap());
+            request_timeout(config, &headers)
+        };
+        assert_eq!(timeout(&config, "250"), Some(Duration::from_millis(250)));
+        assert_eq!(
+            timeout(&config, "600000"),
+            Some(Duration::from_millis(config.limits.max_request_timeout_ms))
+        );
+        assert_eq!(timeout(&config, "0"), None);
+        assert_eq!(timeout(&config, "soon"), None);
+        assert_eq!(request_timeout(&config, &HeaderMap::new()), None);
+        config.limits.max_request_timeout_ms = 0;
+        assert_eq!(timeout(&config, "250"), None);
+    }
+
+    #[test]
+    fn test_entity_timeout() {
+        let mut config = config::Config::default();
+        let limits = |timeout_ms| config::EntityLimits {
+            max_rows: None,
+            timeout_ms,
+        };
+        config.limits.entities = HashMap::from([
+            ("Swap".to_string(), limits(Some(2000))),
+            ("Mint".to_string(), limits(Some(500))),
+            ("Pair".to_string(), limits(None)),
+        ]);
+        let entities = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            entity_timeout(&config, &entities(&["Pair", "Swap", "Mint"])),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(entity_timeout(&config, &entities(&["Pair", "Token"])), None);
+        config.limits.entities.get_mut("Swap").unwrap().timeout_ms = Some(600_000);
+        assert_eq!(
+            entity_timeout(&config, &entities(&["Swap"])),
+            Some(Duration::from_secs(config.limits.upstream_timeout_secs))
+        );
+    }
+
+    #[test]
+    fn test_upstream_error_statuses() {
+        let validation = serde_json::json!([{
+            "message": "field 'nope' not found in type: 'Stream'",
+            "extensions": { "code": "validation-failed", "path": "$.selectionSet.Stream" },
+        }]);
+        assert_eq!(upstream_errors_status(&validation), StatusCode::BAD_REQUEST);
+
+        let mixed = serde_json::json!([
+            { "message": "field 'nope' not found", "extensions": { "code": "validation-failed" } },
+            { "message": "database query error", "extensions": { "code": "unexpected" } },
+        ]);
+        assert_eq!(upstream_errors_status(&mixed), StatusCode::BAD_GATEWAY);
+        let uncoded = serde_json::json!([{ "message": "connection reset" }]);
+        assert_eq!(upstream_errors_status(&uncoded), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            upstream_errors_status(&Value::Null),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_graphql_upstream_responses() {
+        use axum::{routing::post, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route(
+                "/secured",
+                post(|| async {
+                    (
+                        StatusCode::UNAUTHORIZED,
+                        "<html><body>401 Authorization Required</body></html>",
+                    )
+                }),
+            )
+            .route("/html", post(|| async { "x".repeat(4000) }))
+            .route(
+                "/huge",
+                post(|| async {
+                    // Streamed without a Content-Length, so the cap applies while reading
+                    let chunks = (0..64).map(|_| Ok::<_, std::io::Error>("x".repeat(1024)));
+                    axum::body::Body::from_stream(futures_util::stream::iter(chunks))
+                }),
+            )
+            .route(
+                "/graphql-error",
+                post(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({ "errors": [{ "message": "bad query" }] })),
+                    )
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let state_for = |path: &str| {
+            let mut config = config::Config::default();
+            config.upstream.hyperindex_url = format!("http://{}/{}", addr, path);
+            AppState::new(config)
+        };
+        let query = serde_json::json!({ "query": "query { Stream { id } }" });
+
+        let e = forward_to_hyperindex(&state_for("secured"), None, &HeaderMap::new(), &query)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            upstream_failure_status(e.as_ref()),
+            StatusCode::UNAUTHORIZED
+        );
+        let upstream = upstream_failure_response(e.as_ref());
+        assert_eq!(upstream["status"], 401);
+        assert!(upstream["body"]
+            .as_str()
+            .unwrap()
+            .contains("401 Authorization Required"));
+
+        let e = forward_to_hyperindex(&state_for("html"), None, &HeaderMap::new(), &query)
+            .await
+            .unwrap_err();
+        assert_eq!(upstream_failure_status(e.as_ref()), StatusCode::BAD_GATEWAY);
+        let upstream = upstream_failure_response(e.as_ref());
+        assert_eq!(upstream["status"], 200);
+        assert_eq!(
+            upstream["body"].as_str().unwrap().len(),
+            MAX_UPSTREAM_ERROR_BODY_BYTES + 3
+        );
+
+        let mut config = config::Config::default();
+        config.upstream.hyperindex_url = format!("http://{}/huge", addr);
+        config.limits.max_upstream_response_bytes = 16 * 1024;
+        let capped = AppState::new(config);
+        let e = forward_to_hyperindex(&capped, None, &HeaderMap::new(), &query)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<UpstreamError>(),
+            Some(UpstreamError::TooLarge { limit: 16384, .. })
+        ));
+        assert_eq!(upstream_failure_status(e.as_ref()), StatusCode::BAD_GATEWAY);
+        assert_eq!(upstream_failure_response(e.as_ref())["status"], 200);
+
+        let response =
+            forward_to_hyperindex(&state_for("graphql-error"), None, &HeaderMap::new(), &query)
+                .await
+                .unwrap();
+        assert_eq!(response["errors"][0]["message"], "bad query");
+    }
+}
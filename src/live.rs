@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Json, Query, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use crate::chains::ChainId;
+use crate::hooks::{Rejection, RequestContext};
+use crate::response::{self, ResponseMode};
+use crate::{
+    conversion, conversion_error_status, conversion_span, forward_to_hyperindex, required_filters,
+    AppState,
+};
+
+pub async fn handle_live(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    live_response(state, headers, params, None)
+}
+
+pub async fn handle_chain_live(
+    State(state): State<AppState>,
+    ChainId(chain_id): ChainId,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    live_response(state, headers, params, Some(chain_id))
+}
+
+// Converts the `?query=` operation once, then polls Hyperindex on an interval and emits a
+// `next` event only when the reshaped `data` differs from the last one sent. Upstream failures
+// are reported as `error` events without ending the stream.
+fn live_response(
+    state: AppState,
+    headers: HeaderMap,
+    params: HashMap<String, String>,
+    chain_id: Option<String>,
+) -> Response {
+    let mut payload = serde_json::json!({});
+    if let Some(query) = params.get("query") {
+        payload["query"] = Value::String(query.clone());
+    }
+    if let Some(id) = params.get("documentId") {
+        payload["documentId"] = Value::String(id.clone());
+    }
+    let context = RequestContext {
+        chain_id: chain_id.clone(),
+        headers: headers.clone(),
+    };
+    if let Err(rejection) = state.persisted.resolve(&mut payload) {
+        return rejected(rejection);
+    }
+    if let Err(rejection) = state.hooks.before_conversion(&context, &mut payload) {
+        return rejected(rejection);
+    }
+
+    let result = conversion_span(&headers, chain_id.as_deref()).in_scope(|| {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            chain_id.as_deref(),
+            &conversion::ConversionOptions {
+                required_filters: required_filters(&state.config, &headers),
+                ..(*state.conversion_options).clone()
+            },
+        )
+    });
+    state.stats.record(&result);
+    let mut converted = match result {
+        Ok(converted) => converted,
+        Err(e) => {
+            tracing::error!("Live query conversion error: {}", e);
+            return (
+                conversion_error_status(&e),
+                Json(serde_json::json!({
+                    "error": "Conversion failed",
+                    "details": e.to_string(),
+                    "locations": e.locations(params.get("query").map(String::as_str).unwrap_or_default()),
+                    "extensions": { "code": e.code() },
+                    "debug": {
+                        "inputQuery": params.get("query").cloned().unwrap_or_default(),
+                        "chainId": chain_id,
+                    },
+                })),
+            )
+                .into_response();
+        }
+    };
+    if let Err(rejection) = state.hooks.after_conversion(&context, &mut converted.body) {
+        return rejected(rejection);
+    }
+    tracing::info!("Converted live query: {:?}", converted.body);
+
+    let poll = LivePoll {
+        ticker: tokio::time::interval(Duration::from_millis(state.config.live.poll_interval_ms)),
+        mode: response::requested_mode(&headers, &params, state.config.response.mode),
+        state,
+        converted,
+        context,
+        last_hash: None,
+    };
+    Sse::new(futures_util::stream::unfold(poll, next_event))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+struct LivePoll {
+    state: AppState,
+    converted: conversion::ConvertedQuery,
+    mode: ResponseMode,
+    context: RequestContext,
+    ticker: tokio::time::Interval,
+    last_hash: Option<u64>,
+}
+
+async fn next_event(mut poll: LivePoll) -> Option<(Result<Event, Infallible>, LivePoll)> {
+    loop {
+        poll.ticker.tick().await;
+        let event = match forward_to_hyperindex(
+            &poll.state,
+            poll.context.chain_id.as_deref(),
+            &poll.context.headers,
+            &poll.converted.body,
+        )
+        .await
+        {
+            Ok(response) => {
+                let mut transformed = response::shape_response(
+                    response,
+                    poll.mode,
+                    &poll.converted,
+                    &poll.state.config,
+                );
+                poll.state
+                    .hooks
+                    .rewrite_response(&poll.context, &mut transformed);
+                let hash = data_hash(&transformed);
+                if poll.last_hash == Some(hash) {
+                    continue;
+                }
+                poll.last_hash = Some(hash);
+                Event::default().event("next").data(transformed.to_string())
+            }
+            Err(e) => {
+                tracing::error!("Live query poll error: {}", e);
+                poll.last_hash = None;
+                Event::default()
+                    .event("error")
+                    .data(serde_json::json!({ "message": e.to_string() }).to_string())
+            }
+        };
+        return Some((Ok(event), poll));
+    }
+}
+
+fn rejected(rejection: Rejection) -> Response {
+    tracing::info!("Live query rejected: {}", rejection);
+    (
+        rejection.status,
+        Json(serde_json::json!({ "errors": rejection.errors() })),
+    )
+        .into_response()
+}
+
+fn data_hash(response: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    response
+        .get("data")
+        .unwrap_or(response)
+        .to_string()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_hash_ignores_extensions() {
+        let a = serde_json::json!({ "data": { "streams": [{ "id": "1" }] } });
+        let b = serde_json::json!({
+            "data": { "streams": [{ "id": "1" }] },
+            "extensions": { "upstreamMs": 12.0 },
+        });
+        let c = serde_json::json!({ "data": { "streams": [{ "id": "2" }] } });
+        assert_eq!(data_hash(&a), data_hash(&b));
+        assert_ne!(data_hash(&a), data_hash(&c));
+    }
+}
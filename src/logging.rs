@@ -0,0 +1,107 @@
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Request header identifying a request in the logs; taken from the client (or a load
+/// balancer) when sent, generated otherwise, and returned on the response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longer client ids are replaced rather than logged
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+pub fn init() {
+    tracing_subscriber::fmt::init();
+}
+
+/// Middleware giving every request an `x-request-id`, so its conversion span and log lines can
+/// be correlated with what the client saw.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let value = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN && v.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&next_request_id()).expect("ASCII id"));
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, value.clone());
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// The id `assign_request_id` gave the request, or an empty string outside a request.
+pub fn request_id(headers: &HeaderMap) -> &str {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+// `<process start in ms, hex>-<sequence>`: unique across restarts without a random source.
+fn next_request_id() -> String {
+    static START: OnceLock<u128> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let start = START.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default()
+    });
+    format!("{:x}-{}", start, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Picks the requests whose payload, converted query and upstream response are logged in full
+/// at info level. Failures are always logged in full at error level, whether sampled or not.
+pub struct Sampler {
+    every: u64,
+    seen: AtomicU64,
+}
+
+impl Sampler {
+    /// Samples one request in `every`; `0` samples none.
+    pub fn new(every: u64) -> Self {
+        Self {
+            every,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sample(&self) -> bool {
+        self.every != 0
+            && self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.every)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_one_in_n() {
+        let sampler = Sampler::new(3);
+        let sampled: Vec<bool> = (0..7).map(|_| sampler.sample()).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false, true]);
+        assert!((0..5).all(|_| Sampler::new(1).sample()));
+        assert!(!(0..5).any(|_| Sampler::new(0).sample()));
+    }
+
+    #[test]
+    fn test_generated_request_ids_are_unique() {
+        let first = next_request_id();
+        let second = next_request_id();
+        assert_ne!(first, second);
+        assert_eq!(
+            first.split_once('-').unwrap().0,
+            second.split_once('-').unwrap().0
+        );
+    }
+}
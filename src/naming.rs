@@ -0,0 +1,118 @@
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::config::Naming;
+use crate::schema::UpstreamFields;
+use crate::{forward_to_hyperindex, AppState};
+
+// Tables every Envio indexer has, snake_case whatever the entities are named.
+const INTERNAL_TABLES: &[&str] = &[
+    "chain_metadata",
+    "raw_events",
+    "dynamic_contract_registry",
+    "end_of_block_range_scanned_data",
+    "event_sync_state",
+    "persisted_state",
+];
+
+/// Asks Hyperindex for its root query fields and picks the naming convention they follow.
+pub async fn detect(state: &AppState) -> Result<Naming, String> {
+    let response = introspect(state, "{ __schema { queryType { fields { name } } } }").await?;
+    let fields: Vec<&str> = response
+        .pointer("/data/__schema/queryType/fields")
+        .and_then(Value::as_array)
+        .ok_or("introspection returned no query fields")?
+        .iter()
+        .filter_map(|field| field["name"].as_str())
+        .collect();
+    convention(&fields).ok_or_else(|| "no entity tables in the schema".to_string())
+}
+
+/// Asks Hyperindex for the fields of its object types.
+pub async fn upstream_fields(state: &AppState) -> Result<UpstreamFields, String> {
+    let response = introspect(
+        state,
+        "{ __schema { types { kind name fields { name } } } }",
+    )
+    .await?;
+    let types = response
+        .pointer("/data/__schema/types")
+        .and_then(Value::as_array)
+        .ok_or("introspection returned no types")?
+        .iter()
+        .filter(|t| t["kind"] == "OBJECT")
+        .filter_map(|t| {
+            let fields = t["fields"]
+                .as_array()?
+                .iter()
+                .filter_map(|field| Some(field["name"].as_str()?.to_string()))
+                .collect();
+            Some((t["name"].as_str()?.to_string(), fields))
+        })
+        .collect();
+    Ok(UpstreamFields::new(types))
+}
+
+async fn introspect(state: &AppState, query: &str) -> Result<Value, String> {
+    let query = serde_json::json!({ "query": query });
+    let response = forward_to_hyperindex(state, None, &HeaderMap::new(), &query)
+        .await
+        .map_err(|e| format!("introspection request failed: {}", e))?;
+    if let Some(errors) = response.get("errors") {
+        return Err(format!("introspection returned errors: {}", errors));
+    }
+    Ok(response)
+}
+
+// Entity tables are the root fields with a `_by_pk` or `_aggregate` sibling; they are
+// snake_case when none of them starts with an uppercase letter.
+fn convention(fields: &[&str]) -> Option<Naming> {
+    let tables: Vec<&str> = fields
+        .iter()
+        .copied()
+        .filter(|name| !INTERNAL_TABLES.contains(name))
+        .filter(|name| {
+            fields.contains(&format!("{}_by_pk", name).as_str())
+                || fields.contains(&format!("{}_aggregate", name).as_str())
+        })
+        .collect();
+    if tables.is_empty() {
+        return None;
+    }
+    if tables
+        .iter()
+        .any(|name| name.starts_with(|c: char| c.is_ascii_uppercase()))
+    {
+        Some(Naming::Pascal)
+    } else {
+        Some(Naming::SnakeCase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_convention_from_entity_tables() {
+        let pascal = [
+            "Stream",
+            "Stream_by_pk",
+            "Stream_aggregate",
+            "chain_metadata",
+            "chain_metadata_by_pk",
+        ];
+        assert_eq!(convention(&pascal), Some(Naming::Pascal));
+        let snake = [
+            "liquidity_position",
+            "liquidity_position_by_pk",
+            "raw_events",
+            "raw_events_by_pk",
+        ];
+        assert_eq!(convention(&snake), Some(Naming::SnakeCase));
+        assert_eq!(
+            convention(&["chain_metadata", "chain_metadata_by_pk"]),
+            None
+        );
+    }
+}
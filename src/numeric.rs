@@ -0,0 +1,264 @@
+//! Numeric literals in filter values, coerced to what each column type takes. Hyperindex stores
+//! `BigInt`/`BigDecimal` as `numeric`, so literals a float would round (`1e18`, `0.1`,
+//! 30-digit integers) are sent to it exact.
+
+use serde::{Deserialize, Serialize};
+
+/// The shape of a GraphQL numeric literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericLiteral {
+    /// An integer that fits in an `i64`, e.g. `-1`.
+    Int,
+    /// An integer too large for an `i64`, e.g. `100000000000000000000000`.
+    BigInt,
+    /// A literal with a fraction, e.g. `0.000001`.
+    Decimal,
+    /// A literal with an exponent, e.g. `1e18` or `-1.5E-3`.
+    Scientific,
+}
+
+// Exponents beyond this are left as written rather than expanded digit by digit.
+const MAX_EXPONENT: i64 = 1000;
+
+/// Classifies `token` as a numeric literal, or `None` if it isn't one.
+pub fn classify(token: &str) -> Option<NumericLiteral> {
+    let (integer, fraction, exponent) = split(token)?;
+    Some(if exponent.is_some() {
+        NumericLiteral::Scientific
+    } else if fraction.is_some() {
+        NumericLiteral::Decimal
+    } else if token.parse::<i64>().is_ok() {
+        NumericLiteral::Int
+    } else {
+        debug_assert!(!integer.is_empty());
+        NumericLiteral::BigInt
+    })
+}
+
+/// The value of a numeric literal in plain decimal notation, without an exponent or
+/// insignificant zeros: `1e18` becomes `1000000000000000000`, `-1.50E-3` becomes `-0.0015`.
+/// `None` for non-numeric tokens and exponents too large to expand.
+pub fn exact_decimal(token: &str) -> Option<String> {
+    let (integer, fraction, exponent) = split(token)?;
+    let exponent: i64 = match exponent {
+        Some(exponent) => exponent.parse().ok()?,
+        None => 0,
+    };
+    if exponent.abs() > MAX_EXPONENT {
+        return None;
+    }
+    let fraction = fraction.unwrap_or("");
+    let digits = format!("{}{}", integer, fraction);
+    // Position of the decimal point within `digits`, which may lie outside of them
+    let point = integer.len() as i64 + exponent;
+    let (whole, part) = if point <= 0 {
+        let zeros = "0".repeat(point.unsigned_abs() as usize);
+        (String::new(), format!("{}{}", zeros, digits))
+    } else if point as usize >= digits.len() {
+        let zeros = "0".repeat(point as usize - digits.len());
+        (format!("{}{}", digits, zeros), String::new())
+    } else {
+        let (whole, part) = digits.split_at(point as usize);
+        (whole.to_string(), part.to_string())
+    };
+    let whole = whole.trim_start_matches('0');
+    let part = part.trim_end_matches('0');
+    let magnitude = match (whole.is_empty(), part.is_empty()) {
+        (true, true) => return Some("0".to_string()),
+        (_, true) => whole.to_string(),
+        (true, false) => format!("0.{}", part),
+        (false, false) => format!("{}.{}", whole, part),
+    };
+    Some(if token.starts_with('-') {
+        format!("-{}", magnitude)
+    } else {
+        magnitude
+    })
+}
+
+/// How literals on `numeric` (`BigInt`/`BigDecimal`) columns are written. Hasura accepts both
+/// forms; every one of them is exact.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumericLiterals {
+    /// Integers that fit in an `i64` as numbers, every other value as a string.
+    #[default]
+    Auto,
+    /// Every value as a string, e.g. `"1000"`.
+    String,
+    /// Every value as a number in plain decimal notation, e.g. `1000000000000000000`.
+    Number,
+}
+
+/// Rewrites the literals of a filter value on a column of the subgraph scalar `type_name` to
+/// the form Hyperindex takes for it, whichever form the client sent:
+///
+/// - `BigInt`/`BigDecimal`: numbers and numeric strings become their exact decimal value,
+///   written as `numeric` says (`1e18` and `"1e18"` both become `1000000000000000000`).
+/// - `Int`/`Float`: numeric strings are unquoted (`"5"` becomes `5`).
+/// - `String`/`ID`/`Bytes`: numbers are quoted (`5` becomes `"5"`).
+///
+/// Other types, non-numeric strings, variables and enum values are left unchanged.
+pub fn coerce_literals(text: &str, type_name: &str, numeric: NumericLiterals) -> String {
+    map_literals(text, |value, quoted| {
+        classify(value)?;
+        match type_name {
+            "BigInt" | "BigDecimal" => {
+                let exact = exact_decimal(value)?;
+                let string = match numeric {
+                    NumericLiterals::Auto => exact.parse::<i64>().is_err(),
+                    NumericLiterals::String => true,
+                    NumericLiterals::Number => false,
+                };
+                Some(if string {
+                    format!("\"{}\"", exact)
+                } else {
+                    exact
+                })
+            }
+            "Int" if quoted && classify(value) == Some(NumericLiteral::Int) => {
+                Some(value.to_string())
+            }
+            "Float" if quoted => Some(value.to_string()),
+            "String" | "ID" | "Bytes" if !quoted => Some(format!("\"{}\"", value)),
+            _ => None,
+        }
+    })
+}
+
+/// Rewrites each literal of a GraphQL value with `f`, given the literal (a string's contents
+/// without quotes, or a number, enum value or `$variable` as written) and whether it was
+/// quoted. Literals `f` returns `None` for are kept.
+pub fn map_literals(text: &str, mut f: impl FnMut(&str, bool) -> Option<String>) -> String {
+    let bytes = text.as_bytes();
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let start = i;
+        let quoted = b == b'"';
+        if quoted {
+            i = string_end(bytes, i);
+        } else if b == b'-' || b == b'.' || b.is_ascii_alphanumeric() || b == b'_' || b == b'$' {
+            i += 1;
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric()
+                    || matches!(bytes[i], b'_' | b'.')
+                    || (matches!(bytes[i], b'+' | b'-') && matches!(bytes[i - 1], b'e' | b'E')))
+            {
+                i += 1;
+            }
+        } else {
+            let c = text[i..].chars().next().unwrap_or_default();
+            output.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+        let token = &text[start..i];
+        let value = if quoted {
+            token.strip_prefix('"').and_then(|t| t.strip_suffix('"'))
+        } else {
+            Some(token)
+        };
+        match value.and_then(|value| f(value, quoted)) {
+            Some(mapped) => output.push_str(&mapped),
+            None => output.push_str(token),
+        }
+    }
+    output
+}
+
+// The integer digits, fraction digits and exponent of a numeric literal.
+fn split(token: &str) -> Option<(&str, Option<&str>, Option<&str>)> {
+    let unsigned = token.strip_prefix('-').unwrap_or(token);
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(at) => (&unsigned[..at], Some(&unsigned[at + 1..])),
+        None => (unsigned, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let exponent_digits = exponent.map(|e| e.strip_prefix(['+', '-']).unwrap_or(e));
+    (digits(integer) && fraction.is_none_or(digits) && exponent_digits.is_none_or(digits))
+        .then_some((integer, fraction, exponent))
+}
+
+// Byte offset just past the string literal opening at `start`.
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let mut escaped = false;
+    for (i, &b) in bytes.iter().enumerate().skip(start + 1) {
+        match b {
+            b'\\' if !escaped => escaped = true,
+            b'"' if !escaped => return i + 1,
+            _ => escaped = false,
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("-1"), Some(NumericLiteral::Int));
+        assert_eq!(classify("42"), Some(NumericLiteral::Int));
+        assert_eq!(
+            classify("100000000000000000000000"),
+            Some(NumericLiteral::BigInt)
+        );
+        assert_eq!(classify("0.000001"), Some(NumericLiteral::Decimal));
+        assert_eq!(classify("1e18"), Some(NumericLiteral::Scientific));
+        assert_eq!(classify("-1.5E-3"), Some(NumericLiteral::Scientific));
+        for token in ["", "-", "1.", ".5", "1e", "0x10", "$min", "asc", "1e+"] {
+            assert_eq!(classify(token), None, "{}", token);
+        }
+    }
+
+    #[test]
+    fn test_exact_decimal() {
+        assert_eq!(exact_decimal("1e18").unwrap(), "1000000000000000000");
+        assert_eq!(exact_decimal("1E+18").unwrap(), "1000000000000000000");
+        assert_eq!(exact_decimal("-1.50E-3").unwrap(), "-0.0015");
+        assert_eq!(exact_decimal("12.345e1").unwrap(), "123.45");
+        assert_eq!(exact_decimal("0.000001").unwrap(), "0.000001");
+        assert_eq!(exact_decimal("-0.0").unwrap(), "0");
+        assert_eq!(exact_decimal("007").unwrap(), "7");
+        assert_eq!(exact_decimal("1e100000"), None);
+    }
+
+    #[test]
+    fn test_coerce_numeric_columns() {
+        let auto = |text| coerce_literals(text, "BigInt", NumericLiterals::Auto);
+        assert_eq!(auto("-1"), "-1");
+        assert_eq!(
+            auto(r#"[-1, 1e18, "0.5", "12345678901234567890123", $min]"#),
+            r#"[-1, 1000000000000000000, "0.5", "12345678901234567890123", $min]"#
+        );
+        assert_eq!(auto(r#""1000000000000000000""#), "1000000000000000000");
+        assert_eq!(auto(r#""lots""#), r#""lots""#);
+        assert_eq!(
+            coerce_literals("[7, 1e3]", "BigDecimal", NumericLiterals::String),
+            r#"["7", "1000"]"#
+        );
+        assert_eq!(
+            coerce_literals(r#"["7", 1e30]"#, "BigInt", NumericLiterals::Number),
+            "[7, 1000000000000000000000000000000]"
+        );
+    }
+
+    #[test]
+    fn test_coerce_other_columns() {
+        let auto = NumericLiterals::Auto;
+        assert_eq!(coerce_literals(r#""5""#, "Int", auto), "5");
+        assert_eq!(coerce_literals(r#"["1.5", 2]"#, "Float", auto), "[1.5, 2]");
+        assert_eq!(coerce_literals("[5, -1]", "ID", auto), r#"["5", "-1"]"#);
+        assert_eq!(coerce_literals(r#""five""#, "Int", auto), r#""five""#);
+        assert_eq!(coerce_literals(r#""1e3""#, "Int", auto), r#""1e3""#);
+        assert_eq!(coerce_literals("OPEN", "Status", auto), "OPEN");
+        assert_eq!(coerce_literals("5", "Boolean", auto), "5");
+    }
+}
@@ -0,0 +1,116 @@
+//! The converted operation as data: what conversion decided to ask Hyperindex for, before an
+//! [`Emitter`](crate::emit::Emitter) spells it out for the target.
+//!
+//! Selection sets and fragment definitions are kept as GraphQL source; conversion only narrows
+//! or rewrites them, and their layout carries through to the emitted document.
+
+use crate::where_expr::WhereExpr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    Query,
+    Subscription,
+}
+
+impl OperationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OperationKind::Query => "query",
+            OperationKind::Subscription => "subscription",
+        }
+    }
+}
+
+/// A whole converted document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub kind: OperationKind,
+    /// The client's operation name, kept so upstream logs can attribute the query.
+    pub name: Option<String>,
+    pub variables: Vec<VariableDefinition>,
+    /// Fragment definitions the selections spread, as source.
+    pub fragments: String,
+    pub fields: Vec<RootField>,
+}
+
+impl Operation {
+    pub fn new(kind: OperationKind) -> Self {
+        Self {
+            kind,
+            name: None,
+            variables: Vec::new(),
+            fragments: String::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn declares(&self, variable: &str) -> bool {
+        self.variables.iter().any(|v| v.name == variable)
+    }
+}
+
+/// `$name: Type` in the operation header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDefinition {
+    pub name: String,
+    /// Target type, e.g. `[String!]` or `Stream_bool_exp`.
+    pub type_name: String,
+}
+
+/// How a root field reads its entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKind {
+    /// Rows matching the arguments.
+    List,
+    /// One row by primary key.
+    ByPk,
+    /// Aggregates over the matching rows; the selection goes under `aggregate`.
+    Aggregate,
+}
+
+/// One root field of the converted operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootField {
+    pub alias: Option<String>,
+    /// Hyperindex entity (for `ByPk`, the subgraph field it was asked for under).
+    pub entity: String,
+    pub kind: RootKind,
+    pub arguments: Vec<Argument>,
+    /// Selection set source, braces included.
+    pub selection: String,
+}
+
+impl RootField {
+    pub fn new(entity: impl Into<String>, kind: RootKind, selection: impl Into<String>) -> Self {
+        Self {
+            alias: None,
+            entity: entity.into(),
+            kind,
+            arguments: Vec::new(),
+            selection: selection.into(),
+        }
+    }
+
+    pub fn aliased(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+}
+
+/// One argument of a root field. Values are GraphQL literal source or `$variable`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    Limit(String),
+    Offset(String),
+    /// `column: value` of a primary key lookup.
+    PrimaryKey(String, String),
+    DistinctOn(Vec<String>),
+    /// (column, `asc`/`desc`) terms; `list` keeps a single term in list form.
+    OrderBy {
+        terms: Vec<(String, String)>,
+        list: bool,
+    },
+    Where(WhereExpr),
+    /// A `where` passed as a forwarded bool_exp variable.
+    WhereVariable(String),
+}
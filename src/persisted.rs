@@ -0,0 +1,288 @@
+//! Persisted operations: documents known ahead of time, listed by hash in a manifest. Clients
+//! can send the hash instead of the document, and with `persisted.enforce` only listed
+//! operations are accepted. Listed operations are converted once at startup, so requests for
+//! them skip conversion.
+
+use axum::http::StatusCode;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::conversion::{self, ConversionOptions, ConvertedQuery};
+use crate::hooks::Rejection;
+
+/// The operations of `persisted.manifest`, empty when none is configured.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    operations: HashMap<String, Operation>,
+    /// Hash of each listed document, for clients that send the document itself.
+    hashes: HashMap<String, String>,
+    enforce: bool,
+}
+
+#[derive(Debug)]
+pub struct Operation {
+    document: String,
+    /// Conversions without variables, for `/` (`None`) and each configured chain.
+    converted: HashMap<Option<String>, ConvertedQuery>,
+}
+
+impl Manifest {
+    /// Reads `persisted.manifest` and converts every operation in it with `options` (the shared
+    /// options after startup introspection). Returns one message per problem, including each
+    /// operation that fails to convert, so a bad manifest is caught before serving traffic.
+    pub fn load(config: &Config, options: &ConversionOptions) -> Result<Self, Vec<String>> {
+        let Some(path) = &config.persisted.manifest else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| vec![format!("can't read {:?}: {}", path, e)])?;
+        let manifest: Value = serde_json::from_str(&text)
+            .map_err(|e| vec![format!("{:?} is not valid JSON: {}", path, e)])?;
+        let documents = documents(&manifest).map_err(|e| vec![format!("{:?} {}", path, e)])?;
+
+        let mut problems = Vec::new();
+        let mut operations = HashMap::new();
+        let mut hashes = HashMap::new();
+        let chains = std::iter::once(None).chain(config.chains.keys().map(|id| Some(id.clone())));
+        for (hash, document) in documents {
+            let payload = serde_json::json!({ "query": document });
+            let mut converted = HashMap::new();
+            for chain_id in chains.clone() {
+                match conversion::convert_subgraph_to_hyperindex_with_options(
+                    &payload,
+                    chain_id.as_deref(),
+                    options,
+                ) {
+                    Ok(query) => {
+                        converted.insert(chain_id, query);
+                    }
+                    // Operations that can't run without variables are converted per request
+                    Err(conversion::ConversionError::VariableTypeMismatch { .. }) => break,
+                    Err(e) => {
+                        problems.push(format!(
+                            "operation {} failed to convert ({}): {}",
+                            hash,
+                            e.code(),
+                            e
+                        ));
+                        break;
+                    }
+                }
+            }
+            hashes.insert(document.clone(), hash.clone());
+            operations.insert(
+                hash,
+                Operation {
+                    document,
+                    converted,
+                },
+            );
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Self {
+            operations,
+            hashes,
+            enforce: config.persisted.enforce,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Fills in the document of a request that sends a hash (`documentId`, or Apollo's
+    /// `extensions.persistedQuery.sha256Hash`) and returns the listed operation the request
+    /// runs, if any. Unknown hashes are rejected, as are unlisted documents with `enforce`.
+    pub fn resolve(&self, payload: &mut Value) -> Result<Option<&Operation>, Rejection> {
+        if let Some(hash) = requested_hash(payload) {
+            let operation = self
+                .operations
+                .get(hash)
+                .or_else(|| self.operations.get(hash.strip_prefix("sha256:")?))
+                .ok_or_else(|| Rejection {
+                    status: StatusCode::BAD_REQUEST,
+                    message: format!("Persisted operation {} is not in the manifest", hash),
+                })?;
+            payload["query"] = Value::String(operation.document.clone());
+            return Ok(Some(operation));
+        }
+        let listed = payload
+            .get("query")
+            .and_then(Value::as_str)
+            .and_then(|query| self.operations.get(self.hashes.get(query)?));
+        if listed.is_none() && self.enforce {
+            return Err(Rejection {
+                status: StatusCode::FORBIDDEN,
+                message:
+                    "Only persisted operations are accepted, and this one is not in the manifest"
+                        .to_string(),
+            });
+        }
+        Ok(listed)
+    }
+}
+
+impl Operation {
+    /// The conversion made at startup, when it is the one `payload` would get with the shared
+    /// conversion options: the document is unchanged (e.g. by hooks) and no variables are sent.
+    pub fn converted(&self, payload: &Value, chain_id: Option<&str>) -> Option<ConvertedQuery> {
+        let variables = payload.get("variables");
+        let unchanged = payload.get("query").and_then(Value::as_str) == Some(&self.document);
+        let without_variables = variables
+            .is_none_or(|v| v.is_null() || v.as_object().is_some_and(serde_json::Map::is_empty));
+        if !unchanged || !without_variables {
+            return None;
+        }
+        self.converted.get(&chain_id.map(str::to_string)).cloned()
+    }
+}
+
+fn requested_hash(payload: &Value) -> Option<&str> {
+    payload
+        .get("documentId")
+        .or_else(|| payload.pointer("/extensions/persistedQuery/sha256Hash"))
+        .and_then(Value::as_str)
+}
+
+// Hash -> document pairs of a graphql-codegen manifest (`{"<hash>": "<document>"}`) or an
+// Apollo persisted query manifest (`{"operations": [{"id": ..., "body": ...}]}`).
+fn documents(manifest: &Value) -> Result<Vec<(String, String)>, String> {
+    if let Some(operations) = manifest.get("operations").and_then(Value::as_array) {
+        return operations
+            .iter()
+            .map(|operation| {
+                match (
+                    operation.get("id").and_then(Value::as_str),
+                    operation.get("body").and_then(Value::as_str),
+                ) {
+                    (Some(id), Some(body)) => Ok((id.to_string(), body.to_string())),
+                    _ => Err("has an operation without an id and a body".to_string()),
+                }
+            })
+            .collect();
+    }
+    let Some(entries) = manifest.as_object() else {
+        return Err("must be an object of hashes to documents".to_string());
+    };
+    entries
+        .iter()
+        .map(|(hash, document)| match document.as_str() {
+            Some(document) => Ok((hash.clone(), document.to_string())),
+            None => Err(format!("has a non-string document for {}", hash)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(enforce: bool) -> Manifest {
+        let dir =
+            std::env::temp_dir().join(format!("persisted-{}-{}", std::process::id(), enforce));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("persisted-documents.json");
+        std::fs::write(
+            &path,
+            r#"{ "abc123": "query Streams { streams(first: 2) { id } }" }"#,
+        )
+        .unwrap();
+        let mut config = Config::default();
+        config.persisted.manifest = Some(path.to_string_lossy().into_owned());
+        config.persisted.enforce = enforce;
+        Manifest::load(&config, &ConversionOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_resolves_hashes() {
+        let manifest = manifest(false);
+        let mut payload = serde_json::json!({ "documentId": "sha256:abc123" });
+        let operation = manifest.resolve(&mut payload).unwrap().unwrap();
+        assert_eq!(
+            payload["query"],
+            "query Streams { streams(first: 2) { id } }"
+        );
+        let converted = operation.converted(&payload, None).unwrap();
+        assert!(converted.body["query"]
+            .as_str()
+            .unwrap()
+            .contains("Stream(limit: 2)"));
+        assert!(operation.converted(&payload, Some("1")).is_none());
+        payload["variables"] = serde_json::json!({ "n": 1 });
+        assert!(operation.converted(&payload, None).is_none());
+
+        let mut apollo = serde_json::json!({
+            "extensions": { "persistedQuery": { "version": 1, "sha256Hash": "missing" } }
+        });
+        let rejection = manifest.resolve(&mut apollo).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::BAD_REQUEST);
+
+        // Without enforcement, other documents pass through
+        let mut other = serde_json::json!({ "query": "{ streams { id } }" });
+        assert!(manifest.resolve(&mut other).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_enforce_rejects_unlisted_documents() {
+        let manifest = manifest(true);
+        let mut listed =
+            serde_json::json!({ "query": "query Streams { streams(first: 2) { id } }" });
+        assert!(manifest.resolve(&mut listed).unwrap().is_some());
+        let mut other = serde_json::json!({ "query": "{ streams { id } }" });
+        let rejection = manifest.resolve(&mut other).unwrap_err();
+        assert_eq!(rejection.status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_variables_cannot_extend_listed_operations() {
+        let dir = std::env::temp_dir().join(format!("persisted-{}-inline", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("persisted-documents.json");
+        let document = "query Page($w: Stream_filter) { streams(where: $w) { id } }";
+        std::fs::write(&path, serde_json::json!({ "abc123": document }).to_string()).unwrap();
+        let mut config = Config::default();
+        config.persisted.manifest = Some(path.to_string_lossy().into_owned());
+        config.persisted.enforce = true;
+        let options = ConversionOptions {
+            inline_variables: true,
+            ..Default::default()
+        };
+        let manifest = Manifest::load(&config, &options).unwrap();
+
+        let key = r#"id: "1"}) { id } secrets: users { id } z: streams(where: {id"#;
+        let mut payload = serde_json::json!({
+            "documentId": "abc123",
+            "variables": { "w": { key: "1" } }
+        });
+        let operation = manifest.resolve(&mut payload).unwrap().unwrap();
+        assert!(operation.converted(&payload, None).is_none());
+        let result =
+            conversion::convert_subgraph_to_hyperindex_with_options(&payload, None, &options);
+        assert!(
+            matches!(
+                result,
+                Err(conversion::ConversionError::VariableTypeMismatch { .. })
+            ),
+            "got: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_manifest_formats() {
+        let apollo = serde_json::json!({
+            "format": "apollo-persisted-query-manifest",
+            "version": 1,
+            "operations": [{ "id": "h1", "name": "Streams", "type": "query", "body": "{ streams { id } }" }]
+        });
+        assert_eq!(
+            documents(&apollo).unwrap(),
+            [("h1".to_string(), "{ streams { id } }".to_string())]
+        );
+        assert!(documents(&serde_json::json!({ "h1": 1 })).is_err());
+        assert!(documents(&serde_json::json!(["{ streams { id } }"])).is_err());
+    }
+}
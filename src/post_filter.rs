@@ -0,0 +1,193 @@
+//! Filters the proxy applies to response rows, for root conditions Hyperindex can't evaluate
+//! (e.g. `_containsAny` on a column not declared as a list) that lenient mode would otherwise
+//! drop. The filtered roots are fetched without the client's pagination, which is applied after
+//! filtering, and with at most `conversion.post_filter_max_rows` rows.
+
+use serde_json::{Map, Value};
+
+use crate::computed::INPUT_PREFIX;
+
+/// Subgraph filter operators the proxy can evaluate.
+pub const OPERATORS: &[&str] = &[
+    "_containsAny",
+    "_containsAll",
+    "_contains",
+    "_not_contains",
+    "_contains_nocase",
+    "_not_contains_nocase",
+    "_in",
+    "_not_in",
+];
+
+/// One condition on the rows of a root field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostFilter {
+    /// Hyperindex column, selected under a `computed::INPUT_PREFIX` alias.
+    pub column: String,
+    /// Subgraph operator, one of `OPERATORS`.
+    pub operator: String,
+    pub value: Value,
+}
+
+/// A root list filtered by the proxy, paginated once its rows are filtered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostFilteredRoot {
+    /// Hyperindex root response key.
+    pub root: String,
+    pub filters: Vec<PostFilter>,
+    pub skip: usize,
+    pub first: Option<usize>,
+    /// Rows Hyperindex was asked for; `None` when unbounded.
+    pub limit: Option<usize>,
+}
+
+impl PostFilter {
+    /// Response key the column is read from.
+    pub fn input(&self) -> String {
+        format!("{}{}", INPUT_PREFIX, self.column)
+    }
+
+    /// Whether `row` passes the condition. A `null` or missing column passes none, as in SQL.
+    pub fn matches(&self, row: &Map<String, Value>) -> bool {
+        let Some(actual) = row.get(&self.input()).filter(|v| !v.is_null()) else {
+            return false;
+        };
+        let items = |value: &Value| match value {
+            Value::Array(items) => items.iter().map(text).collect(),
+            other => vec![text(other)],
+        };
+        let expected = items(&self.value);
+        match (self.operator.as_str(), actual) {
+            ("_containsAny", Value::Array(_)) => {
+                let actual = items(actual);
+                expected.iter().any(|item| actual.contains(item))
+            }
+            ("_contains" | "_containsAll", Value::Array(_)) => {
+                let actual = items(actual);
+                expected.iter().all(|item| actual.contains(item))
+            }
+            ("_not_contains", Value::Array(_)) => {
+                let actual = items(actual);
+                !expected.iter().all(|item| actual.contains(item))
+            }
+            ("_contains", Value::String(s)) => s.contains(&text(&self.value)),
+            ("_not_contains", Value::String(s)) => !s.contains(&text(&self.value)),
+            ("_contains_nocase", Value::String(s)) => {
+                s.to_lowercase().contains(&text(&self.value).to_lowercase())
+            }
+            ("_not_contains_nocase", Value::String(s)) => {
+                !s.to_lowercase().contains(&text(&self.value).to_lowercase())
+            }
+            ("_in", actual) => expected.contains(&text(actual)),
+            ("_not_in", actual) => !expected.contains(&text(actual)),
+            _ => false,
+        }
+    }
+}
+
+/// Keeps the rows of each root in `roots` that pass all its filters, then applies its
+/// pagination. The selected columns stay in the rows, for computed fields that read them too.
+pub fn apply(mut resp: Value, roots: &[PostFilteredRoot]) -> Value {
+    for root in roots {
+        let Some(Value::Array(rows)) = resp
+            .get_mut("data")
+            .and_then(|data| data.get_mut(&root.root))
+        else {
+            continue;
+        };
+        let kept: Vec<Value> = std::mem::take(rows)
+            .into_iter()
+            .filter(|row| {
+                row.as_object()
+                    .is_some_and(|row| root.filters.iter().all(|filter| filter.matches(row)))
+            })
+            .skip(root.skip)
+            .take(root.first.unwrap_or(usize::MAX))
+            .collect();
+        *rows = kept;
+    }
+    resp
+}
+
+/// The roots in `roots` that Hyperindex returned as many rows for as it was asked, so matching
+/// rows past them may be missing from the filtered page.
+pub fn truncated<'a>(resp: &Value, roots: &'a [PostFilteredRoot]) -> Vec<&'a PostFilteredRoot> {
+    roots
+        .iter()
+        .filter(|root| {
+            let rows = resp
+                .get("data")
+                .and_then(|data| data.get(&root.root))
+                .and_then(Value::as_array);
+            root.limit.is_some() && rows.map(Vec::len) == root.limit
+        })
+        .collect()
+}
+
+// Values compare as text, so `"5"` (a `BigInt`) equals `5`.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(operator: &str, value: Value) -> PostFilter {
+        PostFilter {
+            column: "tags".to_string(),
+            operator: operator.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let row = |tags: Value| {
+            json!({ "__computed_tags": tags })
+                .as_object()
+                .unwrap()
+                .clone()
+        };
+        let listed = row(json!(["a", "b"]));
+        assert!(filter("_containsAny", json!(["b", "z"])).matches(&listed));
+        assert!(!filter("_containsAny", json!(["y", "z"])).matches(&listed));
+        assert!(filter("_containsAll", json!(["a", "b"])).matches(&listed));
+        assert!(!filter("_containsAll", json!(["a", "z"])).matches(&listed));
+        assert!(filter("_not_contains", json!(["z"])).matches(&listed));
+        assert!(filter("_contains_nocase", json!("OO")).matches(&row(json!("Foo"))));
+        assert!(filter("_in", json!([5, 6])).matches(&row(json!("5"))));
+        assert!(filter("_not_in", json!(["6"])).matches(&row(json!(5))));
+        // Nulls pass no condition, negated or not
+        assert!(!filter("_not_in", json!(["6"])).matches(&row(Value::Null)));
+        assert!(!filter("_in", json!(["6"])).matches(&Map::new()));
+    }
+
+    #[test]
+    fn test_apply_paginates_after_filtering() {
+        let resp = json!({ "data": { "Stream": [
+            { "id": "1", "__computed_tags": ["x"] },
+            { "id": "2", "__computed_tags": ["a"] },
+            { "id": "3", "__computed_tags": ["a", "b"] },
+            { "id": "4", "__computed_tags": ["b"] },
+        ] } });
+        let roots = [PostFilteredRoot {
+            root: "Stream".to_string(),
+            filters: vec![filter("_containsAny", json!(["a", "b"]))],
+            skip: 1,
+            first: Some(1),
+            limit: Some(4),
+        }];
+        assert_eq!(truncated(&resp, &roots).len(), 1);
+        let resp = apply(resp, &roots);
+        assert!(truncated(&resp, &roots).is_empty());
+        assert_eq!(
+            resp["data"]["Stream"],
+            json!([{ "id": "3", "__computed_tags": ["a", "b"] }])
+        );
+    }
+}
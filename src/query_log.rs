@@ -0,0 +1,388 @@
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::config::{QueryLogConfig, QueryLogSink};
+use crate::conversion::{ConversionError, ConvertedQuery};
+
+/// Written in place of variable values that aren't kept (`query_log.keep_variables`,
+/// `subgraph_debug.keep_variables`).
+const REDACTED: &str = "[REDACTED]";
+
+/// What happened to a logged query.
+pub enum Outcome<'a> {
+    /// The converter rejected it; nothing was sent upstream.
+    Rejected(&'a ConversionError),
+    /// It was converted and sent to Hyperindex.
+    Forwarded {
+        converted: &'a ConvertedQuery,
+        upstream: &'a Result<Value, Box<dyn std::error::Error + Send + Sync>>,
+    },
+}
+
+/// Records every query to `/` and `/chainId/{chain_id}` with its conversion outcome and
+/// upstream status. Entries are written on a background thread so requests never wait on disk.
+pub struct QueryLog {
+    sender: Option<Mutex<Sender<Value>>>,
+    redact_variables: bool,
+    keep_variables: Vec<String>,
+}
+
+impl QueryLog {
+    pub fn new(config: &QueryLogConfig) -> Self {
+        let sender = config.enabled.then(|| match open_sink(config) {
+            Ok(mut sink) => {
+                let (sender, receiver) = mpsc::channel::<Value>();
+                std::thread::spawn(move || {
+                    for entry in receiver {
+                        if let Err(e) = sink.write(&entry) {
+                            tracing::error!("Failed to write query log entry: {}", e);
+                        }
+                    }
+                });
+                Some(Mutex::new(sender))
+            }
+            Err(e) => {
+                tracing::error!("Failed to open query log {}: {}", config.path, e);
+                None
+            }
+        });
+        Self {
+            sender: sender.flatten(),
+            redact_variables: config.redact_variables,
+            keep_variables: config.keep_variables.clone(),
+        }
+    }
+
+    pub fn record(&self, chain_id: Option<&str>, payload: &Value, outcome: Outcome<'_>) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let entry = self.entry(chain_id, payload, outcome);
+        let _ = sender.lock().unwrap().send(entry);
+    }
+
+    fn entry(&self, chain_id: Option<&str>, payload: &Value, outcome: Outcome<'_>) -> Value {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let mut entry = serde_json::json!({
+            "timestampMs": timestamp_ms,
+            "chainId": chain_id,
+            "query": payload.get("query").cloned().unwrap_or(Value::Null),
+            "variables": self.redact(payload.get("variables")),
+        });
+        match outcome {
+            Outcome::Rejected(e) => {
+                entry["outcome"] = Value::from("rejected");
+                entry["errorCode"] = Value::from(e.code());
+                entry["error"] = Value::from(e.to_string());
+            }
+            Outcome::Forwarded {
+                converted,
+                upstream,
+            } => {
+                entry["outcome"] = Value::from("forwarded");
+                let converted_query = converted.body.get("query").cloned().unwrap_or_default();
+                entry["convertedQueryHash"] =
+                    Value::from(query_hash(converted_query.as_str().unwrap_or_default()));
+                entry["convertedQuery"] = converted_query;
+                entry["upstream"] = match upstream {
+                    Ok(response) if response.get("errors").is_some() => {
+                        Value::from("graphql_errors")
+                    }
+                    Ok(_) => Value::from("ok"),
+                    Err(e) => {
+                        entry["error"] = Value::from(e.to_string());
+                        Value::from("request_failed")
+                    }
+                };
+            }
+        }
+        entry
+    }
+
+    // Redaction hook: variable values are the part of a request most likely to carry user
+    // data, so each one is replaced unless it's explicitly kept.
+    fn redact(&self, variables: Option<&Value>) -> Value {
+        match variables {
+            Some(variables) if self.redact_variables => {
+                redact_variables(variables, &self.keep_variables)
+            }
+            Some(variables) => variables.clone(),
+            None => Value::Null,
+        }
+    }
+}
+
+/// Replaces the value of every variable not named in `keep` with a placeholder.
+pub fn redact_variables(variables: &Value, keep: &[String]) -> Value {
+    match variables {
+        Value::Object(variables) => Value::Object(
+            variables
+                .iter()
+                .map(|(name, value)| {
+                    let value = if keep.contains(name) {
+                        value.clone()
+                    } else {
+                        Value::from(REDACTED)
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// `keccak256:<hex>` of a converted query, recorded with each forwarded entry so the query can
+/// be looked up from the `x-converted-query` response header.
+pub fn query_hash(query: &str) -> String {
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(query.as_bytes());
+    keccak.finalize(&mut hash);
+    let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("keccak256:{}", hex)
+}
+
+fn open_sink(config: &QueryLogConfig) -> io::Result<Box<dyn Sink>> {
+    match config.sink {
+        QueryLogSink::File => Ok(Box::new(RotatingFile::open(
+            PathBuf::from(&config.path),
+            config.max_bytes,
+            config.max_files,
+        )?)),
+        #[cfg(feature = "sqlite")]
+        QueryLogSink::Sqlite => Ok(Box::new(
+            sqlite::Table::open(&config.path).map_err(io::Error::other)?,
+        )),
+        #[cfg(not(feature = "sqlite"))]
+        QueryLogSink::Sqlite => Err(io::Error::other(
+            "the sqlite sink requires building with `--features sqlite`",
+        )),
+    }
+}
+
+trait Sink: Send {
+    fn write(&mut self, entry: &Value) -> io::Result<()>;
+}
+
+// JSON lines, rolled over to `<path>.1` .. `<path>.<max_files>` once `max_bytes` is reached.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| -> PathBuf {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            name.into()
+        };
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Sink for RotatingFile {
+    fn write(&mut self, entry: &Value) -> io::Result<()> {
+        let mut line = entry.to_string();
+        line.push('\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use rusqlite::{params, Connection};
+    use serde_json::Value;
+    use std::io;
+
+    use super::Sink;
+
+    pub struct Table {
+        connection: Connection,
+    }
+
+    impl Table {
+        pub fn open(path: &str) -> rusqlite::Result<Self> {
+            let connection = Connection::open(path)?;
+            connection.execute(
+                "CREATE TABLE IF NOT EXISTS query_log (
+                    timestamp_ms INTEGER NOT NULL,
+                    chain_id TEXT,
+                    outcome TEXT NOT NULL,
+                    error_code TEXT,
+                    upstream TEXT,
+                    entry TEXT NOT NULL
+                )",
+                [],
+            )?;
+            Ok(Self { connection })
+        }
+    }
+
+    impl Sink for Table {
+        fn write(&mut self, entry: &Value) -> io::Result<()> {
+            self.connection
+                .execute(
+                    "INSERT INTO query_log (timestamp_ms, chain_id, outcome, error_code, upstream, entry)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        entry["timestampMs"].as_i64(),
+                        entry["chainId"].as_str(),
+                        entry["outcome"].as_str(),
+                        entry["errorCode"].as_str(),
+                        entry["upstream"].as_str(),
+                        entry.to_string(),
+                    ],
+                )
+                .map(|_| ())
+                .map_err(io::Error::other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversion::{self, ConversionOptions};
+    use std::path::Path;
+
+    fn query_log(config: QueryLogConfig) -> QueryLog {
+        QueryLog {
+            sender: None,
+            redact_variables: config.redact_variables,
+            keep_variables: config.keep_variables,
+        }
+    }
+
+    #[test]
+    fn test_variables_are_redacted_unless_kept() {
+        let log = query_log(QueryLogConfig {
+            keep_variables: vec!["first".to_string()],
+            ..Default::default()
+        });
+        let payload = serde_json::json!({
+            "query": "query($first: Int, $owner: String) { streams(first: $first, where: {owner: $owner}) { id } }",
+            "variables": { "first": 10, "owner": "0xabc" }
+        });
+        let error = ConversionError::InvalidQueryFormat;
+        let entry = log.entry(Some("1"), &payload, Outcome::Rejected(&error));
+        assert_eq!(
+            entry["variables"],
+            serde_json::json!({ "first": 10, "owner": REDACTED })
+        );
+        assert_eq!(entry["outcome"], "rejected");
+        assert_eq!(entry["errorCode"], "INVALID_QUERY_FORMAT");
+        assert_eq!(entry["chainId"], "1");
+
+        let log = query_log(QueryLogConfig {
+            redact_variables: false,
+            ..Default::default()
+        });
+        let entry = log.entry(None, &payload, Outcome::Rejected(&error));
+        assert_eq!(entry["variables"]["owner"], "0xabc");
+    }
+
+    #[test]
+    fn test_forwarded_entry_records_upstream_status() {
+        let log = query_log(QueryLogConfig::default());
+        let payload = serde_json::json!({ "query": "{ streams { id } }" });
+        let converted = conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            None,
+            &ConversionOptions::default(),
+        )
+        .unwrap();
+
+        let upstream = Ok(serde_json::json!({ "errors": [{ "message": "boom" }] }));
+        let entry = log.entry(
+            None,
+            &payload,
+            Outcome::Forwarded {
+                converted: &converted,
+                upstream: &upstream,
+            },
+        );
+        assert_eq!(entry["outcome"], "forwarded");
+        assert_eq!(entry["upstream"], "graphql_errors");
+        assert_eq!(entry["convertedQuery"], converted.body["query"]);
+        assert_eq!(
+            entry["convertedQueryHash"],
+            query_hash(converted.body["query"].as_str().unwrap())
+        );
+
+        let upstream = Err("connection refused".into());
+        let entry = log.entry(
+            None,
+            &payload,
+            Outcome::Forwarded {
+                converted: &converted,
+                upstream: &upstream,
+            },
+        );
+        assert_eq!(entry["upstream"], "request_failed");
+        assert_eq!(entry["error"], "connection refused");
+    }
+
+    #[test]
+    fn test_file_rotates_at_max_bytes() {
+        let dir = std::env::temp_dir().join(format!("query-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("queries.log");
+        let mut file = RotatingFile::open(path.clone(), 40, 2).unwrap();
+        for i in 0..4 {
+            file.write(&serde_json::json!({ "entry": i, "padding": "xxxxxxxxxx" }))
+                .unwrap();
+        }
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert!(read(&path).contains("\"entry\":3"));
+        assert!(read(&dir.join("queries.log.1")).contains("\"entry\":2"));
+        assert!(read(&dir.join("queries.log.2")).contains("\"entry\":1"));
+        assert!(!dir.join("queries.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,986 @@
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::computed;
+use crate::config::Config;
+use crate::conversion::{ConversionWarning, ConvertedQuery, InterfaceMerge};
+use crate::ids::{self, AddressCase};
+use crate::post_filter;
+
+/// Request header selecting the response mode for a single request.
+pub const RESPONSE_MODE_HEADER: &str = "x-response-mode";
+/// Query parameter selecting the response mode; takes precedence over the header.
+pub const RESPONSE_MODE_PARAM: &str = "responseMode";
+/// Request header choosing between the subgraph shape (`subgraph`, the configured mode) and the
+/// raw Hyperindex response (`hyperindex`). An explicit response mode takes precedence.
+pub const RESPONSE_SHAPE_HEADER: &str = "x-response-shape";
+
+/// How Hyperindex responses are mapped back to the subgraph shape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseMode {
+    /// Rename root keys using the field map recorded during conversion.
+    Exact,
+    /// Infer subgraph keys from Hyperindex names (`Stream` -> `streams`).
+    #[default]
+    Heuristic,
+    /// Return the raw Hyperindex response untouched.
+    Passthrough,
+}
+
+impl FromStr for ResponseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "exact" => Ok(ResponseMode::Exact),
+            "heuristic" => Ok(ResponseMode::Heuristic),
+            "passthrough" => Ok(ResponseMode::Passthrough),
+            other => Err(format!("unknown response mode: {}", other)),
+        }
+    }
+}
+
+/// What to return for a single-entity (`_by_pk`) lookup that found nothing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingEntity {
+    /// `{"stream": null}`, as subgraphs respond.
+    #[default]
+    Null,
+    /// Leave the field out of `data`.
+    Omit,
+}
+
+/// Resolves the mode for a request: query param, then mode header, then shape header, then the
+/// configured default. Unknown values are logged and ignored.
+pub fn requested_mode(
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    default: ResponseMode,
+) -> ResponseMode {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let requested = params
+        .get(RESPONSE_MODE_PARAM)
+        .cloned()
+        .or_else(|| header(RESPONSE_MODE_HEADER));
+    match requested.map(|v| v.parse::<ResponseMode>()) {
+        Some(Ok(mode)) => return mode,
+        Some(Err(e)) => {
+            tracing::warn!("Ignoring requested response mode: {}", e);
+            return default;
+        }
+        None => {}
+    }
+    match header(RESPONSE_SHAPE_HEADER).map(|v| v.trim().to_ascii_lowercase()) {
+        Some(shape) if shape == "hyperindex" => ResponseMode::Passthrough,
+        // Keeps the configured mapping, unless that is to pass responses through
+        Some(shape) if shape == "subgraph" && default == ResponseMode::Passthrough => {
+            ResponseMode::Heuristic
+        }
+        Some(shape) if shape == "subgraph" => default,
+        Some(shape) => {
+            tracing::warn!("Ignoring requested response shape: {}", shape);
+            default
+        }
+        None => default,
+    }
+}
+
+/// Maps a Hyperindex response back to the subgraph shape according to `mode`.
+pub fn shape_response(
+    resp: Value,
+    mode: ResponseMode,
+    converted: &ConvertedQuery,
+    config: &Config,
+) -> Value {
+    // Post-filtered roots that came back with as many rows as were read may be missing rows
+    let mut warnings = converted.warnings.clone();
+    for root in post_filter::truncated(&resp, &converted.post_filtered) {
+        let warning = ConversionWarning {
+            code: "POST_FILTER_TRUNCATED",
+            message: format!(
+                "{} was post-filtered from its first {} rows only; matching rows after them are missing",
+                root.root,
+                root.limit.unwrap_or_default()
+            ),
+        };
+        tracing::warn!("{}", warning.message);
+        warnings.push(warning);
+    }
+    // Computed fields are filled in while their inputs are still under Hyperindex's keys
+    let resp = if converted.forwarded {
+        resp
+    } else {
+        let resp = post_filter::apply(resp, &converted.post_filtered);
+        let mut resp = computed::apply(resp, &converted.computed);
+        for (root, path) in &converted.injected_ids {
+            computed::remove(&mut resp, root, path, "id");
+        }
+        for root in &converted.post_filtered {
+            for filter in &root.filters {
+                computed::remove(&mut resp, &root.root, &[], &filter.input());
+            }
+        }
+        resp
+    };
+    // Root fields the conversion split up or aliased are put back together before renaming
+    let prepare = |resp| {
+        let resp = normalize_single_entities(resp, config.response.missing_entity);
+        let resp = merge_interfaces(resp, &converted.interfaces);
+        let resp = unwrap_aggregates(resp, &converted.aggregates);
+        let resp = collect_total_counts(resp, &converted.total_counts);
+        rename_root_aliases(resp, &converted.root_aliases)
+    };
+    // Forwarded operations (mutations) were never converted, so there is nothing to map back
+    let mode = if converted.forwarded {
+        ResponseMode::Passthrough
+    } else {
+        mode
+    };
+    let shaped = match mode {
+        ResponseMode::Exact => apply_field_map(prepare(resp), &converted.field_map),
+        ResponseMode::Heuristic => transform_response_to_subgraph_shape(
+            prepare(resp),
+            &config.mappings.entities,
+            &converted.root_aliases,
+        ),
+        ResponseMode::Passthrough => resp,
+    };
+    let shaped = match mode {
+        ResponseMode::Passthrough => shaped,
+        _ => transform_nested_data(shaped, config),
+    };
+    attach_warnings(shaped, &warnings)
+}
+
+// Reports what the conversion dropped or changed under `extensions.warnings`, in every
+// response mode.
+fn attach_warnings(resp: Value, warnings: &[ConversionWarning]) -> Value {
+    if warnings.is_empty() {
+        return resp;
+    }
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    let extensions = root
+        .entry("extensions")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(ext) = extensions {
+        ext.insert("warnings".to_string(), serde_json::json!(warnings));
+    }
+    Value::Object(root)
+}
+
+// Collects the per-entity rows of each interface query into the field the client asked for,
+// then re-applies ordering (by `id` when none was given) and pagination across the merged list.
+fn merge_interfaces(resp: Value, interfaces: &[InterfaceMerge]) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for merge in interfaces {
+            let mut rows = Vec::new();
+            for (alias, entity) in &merge.aliases {
+                if let Some(Value::Array(entity_rows)) = data_obj.remove(alias) {
+                    rows.extend(entity_rows.into_iter().map(|mut row| {
+                        if let Value::Object(fields) = &mut row {
+                            fields
+                                .entry("__typename")
+                                .or_insert_with(|| Value::String(entity.clone()));
+                        }
+                        row
+                    }));
+                }
+            }
+
+            let order_by = merge.order_by.as_deref().unwrap_or("id");
+            rows.sort_by(|a, b| {
+                let ordering = compare_values(&a[order_by], &b[order_by]);
+                if merge.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+            let rows = rows
+                .into_iter()
+                .skip(merge.skip)
+                .take(merge.first.unwrap_or(usize::MAX));
+            data_obj.insert(merge.field.clone(), Value::Array(rows.collect()));
+        }
+    }
+    Value::Object(root)
+}
+
+// `{"Stream_aggregate": {"aggregate": {"count": 3}}}` -> `{"Stream_aggregate": {"count": 3}}`,
+// before the key is renamed to the aggregate field
+fn unwrap_aggregates(resp: Value, aggregates: &[String]) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for field in aggregates {
+            if let Some(value) = data_obj.get_mut(field) {
+                if let Some(aggregate) = value.get_mut("aggregate") {
+                    *value = aggregate.take();
+                }
+            }
+        }
+    }
+    Value::Object(root)
+}
+
+// Moves the count queries added for total counts out of `data` into `extensions.totalCount`.
+fn collect_total_counts(resp: Value, total_counts: &[(String, String)]) -> Value {
+    if total_counts.is_empty() {
+        return resp;
+    }
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    let mut counts = serde_json::Map::new();
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for (alias, field) in total_counts {
+            if let Some(value) = data_obj.remove(alias) {
+                counts.insert(field.clone(), value["aggregate"]["count"].clone());
+            }
+        }
+    }
+    let extensions = root
+        .entry("extensions")
+        .or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(ext) = extensions {
+        ext.insert("totalCount".to_string(), Value::Object(counts));
+    }
+    Value::Object(root)
+}
+
+// Renames the keys of aliased root fields to the client's alias, once the keys derived from
+// them (interface parts, total counts) have been folded in.
+fn rename_root_aliases(resp: Value, root_aliases: &[(String, String)]) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for (key, alias) in root_aliases {
+            if let Some(value) = data_obj.remove(key) {
+                data_obj.insert(alias.clone(), value);
+            }
+        }
+    }
+    Value::Object(root)
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .partial_cmp(&b.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
+// Unwraps one-row `where` fallbacks aliased as `_by_pk` and drops null lookups when asked to.
+// Runs before renaming, while single-entity fields can still be told apart.
+fn normalize_single_entities(resp: Value, missing_entity: MissingEntity) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for (key, value) in data_obj.iter_mut() {
+            if key.ends_with("_by_pk") {
+                if let Value::Array(rows) = value {
+                    let first = rows.drain(..).next();
+                    *value = first.unwrap_or(Value::Null);
+                }
+            }
+        }
+        if missing_entity == MissingEntity::Omit {
+            data_obj.retain(|key, value| !(key.ends_with("_by_pk") && value.is_null()));
+        }
+    }
+    Value::Object(root)
+}
+
+// Applies the nested field/typename mappings and id casing below the root fields. Skipped
+// entirely when none of them are configured.
+fn transform_nested_data(resp: Value, config: &Config) -> Value {
+    let mappings = &config.mappings;
+    if mappings.fields.is_empty()
+        && mappings.typenames.is_empty()
+        && !lowercases_address_ids(config)
+        && !config.response.stringify_numbers
+    {
+        return resp;
+    }
+
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        for value in data_obj.values_mut() {
+            *value = transform_nested(value.take(), None, config);
+        }
+    }
+    Value::Object(root)
+}
+
+// `field` is the key the value sits under, so array items of a list field are treated like
+// the field itself.
+fn transform_nested(value: Value, field: Option<&str>, config: &Config) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| transform_nested(item, field, config))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = match (key.as_str(), value) {
+                        ("__typename", Value::String(name)) => Value::String(
+                            config
+                                .mappings
+                                .typenames
+                                .get(&name)
+                                .cloned()
+                                .unwrap_or(name),
+                        ),
+                        ("id", Value::String(id)) if lowercases_address_ids(config) => {
+                            Value::String(ids::recase_address(&id, AddressCase::Lower))
+                        }
+                        (_, value) => transform_nested(value, Some(&key), config),
+                    };
+                    let key = config.mappings.fields.get(&key).cloned().unwrap_or(key);
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Number(n) if is_big_number(field, &n, config) => Value::String(n.to_string()),
+        other => other,
+    }
+}
+
+// Subgraph `Int` is 32-bit and there is no float type, so any other number must have been a
+// `BigInt` or `BigDecimal`.
+fn is_big_number(field: Option<&str>, n: &serde_json::Number, config: &Config) -> bool {
+    let response = &config.response;
+    if !response.stringify_numbers {
+        return false;
+    }
+    if field.is_some_and(|field| response.big_number_fields.iter().any(|f| f == field)) {
+        return true;
+    }
+    match n.as_i64() {
+        Some(i) => i32::try_from(i).is_err(),
+        None => true,
+    }
+}
+
+// Subgraph ids are lowercase, so address ids are lowercased when asked for or when Hyperindex
+// stores them in another case.
+fn lowercases_address_ids(config: &Config) -> bool {
+    config.response.lowercase_address_ids || config.ids.address_case != AddressCase::Preserve
+}
+
+// Renames root data keys recorded by the conversion; keys it didn't produce (e.g. chain_metadata)
+// are left as they are.
+fn apply_field_map(resp: Value, field_map: &HashMap<String, String>) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        let renamed = std::mem::take(data_obj)
+            .into_iter()
+            .map(|(key, value)| match field_map.get(&key) {
+                Some(field) => (field.clone(), value),
+                None => (key, value),
+            })
+            .collect();
+        *data_obj = renamed;
+    }
+
+    Value::Object(root)
+}
+
+// Client aliases in `root_aliases` are kept as they are.
+fn transform_response_to_subgraph_shape(
+    resp: Value,
+    entity_names: &HashMap<String, String>,
+    root_aliases: &[(String, String)],
+) -> Value {
+    let mut root = match resp {
+        Value::Object(map) => map,
+        other => return other,
+    };
+
+    if let Some(Value::Object(data_obj)) = root.get_mut("data") {
+        let mut new_data = serde_json::Map::new();
+        for (key, value) in data_obj.clone().into_iter() {
+            if root_aliases.iter().any(|(_, alias)| *alias == key) {
+                new_data.insert(key, value);
+                continue;
+            }
+            // Configured entity mappings are exact, so prefer them over the plural heuristics
+            let mapped = entity_names
+                .iter()
+                .find(|(_, entity)| **entity == key)
+                .map(|(field, _)| field.clone());
+            let new_key = if let Some(field) = mapped {
+                field
+            } else if let Some(entity) = key.strip_suffix("_aggregate") {
+                // `Stream_aggregate` answers the `streamsAggregate` extension field
+                let collection = entity_names
+                    .iter()
+                    .find(|(_, mapped)| *mapped == entity)
+                    .map(|(field, _)| field.clone())
+                    .unwrap_or_else(|| pluralize_lowercase(entity));
+                format!("{}Aggregate", collection)
+            } else if let Some(field) = key.strip_suffix("_by_pk") {
+                // The conversion keeps the client's field name, so only the suffix differs
+                field.to_string()
+            } else if is_pascal_case(&key) {
+                pluralize_lowercase(&key)
+            } else {
+                key
+            };
+            new_data.insert(new_key, value);
+        }
+        *data_obj = new_data;
+    }
+
+    Value::Object(root)
+}
+
+fn is_pascal_case(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_uppercase() => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphabetic())
+}
+
+fn pluralize_lowercase(name: &str) -> String {
+    let lower = name.to_ascii_lowercase();
+    if lower.ends_with('y') {
+        let pre = lower.chars().rev().nth(1).unwrap_or('a');
+        if !matches!(pre, 'a' | 'e' | 'i' | 'o' | 'u') {
+            return format!("{}ies", &lower[..lower.len() - 1]);
+        }
+    }
+    if lower.ends_with("ch")
+        || lower.ends_with("sh")
+        || lower.ends_with('x')
+        || lower.ends_with('z')
+        || lower.ends_with('s')
+        || lower.ends_with('o')
+    {
+        return format!("{}es", lower);
+    }
+    format!("{}s", lower)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pluralize_lowercase_basic() {
+        assert_eq!(pluralize_lowercase("Stream"), "streams");
+        assert_eq!(pluralize_lowercase("Batch"), "batches");
+        assert_eq!(pluralize_lowercase("Asset"), "assets");
+        assert_eq!(pluralize_lowercase("Action"), "actions");
+    }
+
+    #[test]
+    fn test_transform_data_keys() {
+        let resp = serde_json::json!({
+            "data": {
+                "Stream": [ {"id": 1} ],
+                "Batch": [ {"id": 2} ],
+                "stream_by_pk": {"id": 3}
+            }
+        });
+        let out = transform_response_to_subgraph_shape(resp, &HashMap::new(), &[]);
+        let data = out.get("data").unwrap();
+        assert!(data.get("streams").is_some());
+        assert!(data.get("batches").is_some());
+        assert!(data.get("stream").is_some());
+        assert!(data.get("Stream").is_none());
+        assert!(data.get("Batch").is_none());
+        assert!(data.get("stream_by_pk").is_none());
+    }
+
+    #[test]
+    fn test_transform_uses_entity_mappings() {
+        let resp = serde_json::json!({ "data": { "Person": [ {"id": 1} ] } });
+        let mappings = HashMap::from([("people".to_string(), "Person".to_string())]);
+        let out = transform_response_to_subgraph_shape(resp, &mappings, &[]);
+        assert!(out["data"].get("people").is_some());
+        assert!(out["data"].get("persons").is_none());
+    }
+
+    #[test]
+    fn test_shape_response_modes() {
+        let config = Config::default();
+        let resp = serde_json::json!({
+            "data": { "Tranche": [ {"id": 1} ], "chain_metadata": [] }
+        });
+        let converted = ConvertedQuery {
+            field_map: HashMap::from([("Tranche".to_string(), "tranches".to_string())]),
+            ..Default::default()
+        };
+
+        let exact = shape_response(resp.clone(), ResponseMode::Exact, &converted, &config);
+        assert!(exact["data"].get("tranches").is_some());
+        assert!(exact["data"].get("chain_metadata").is_some());
+
+        let heuristic = shape_response(resp.clone(), ResponseMode::Heuristic, &converted, &config);
+        assert!(heuristic["data"].get("tranches").is_some());
+
+        let passthrough =
+            shape_response(resp.clone(), ResponseMode::Passthrough, &converted, &config);
+        assert_eq!(passthrough, resp);
+    }
+
+    #[test]
+    fn test_nested_fields_and_typenames() {
+        let mut config = Config::default();
+        config.mappings.fields =
+            HashMap::from([("streamActions".to_string(), "actions".to_string())]);
+        config.mappings.typenames =
+            HashMap::from([("Stream_Action".to_string(), "Action".to_string())]);
+        let resp = serde_json::json!({
+            "data": { "Stream": [{
+                "id": "1",
+                "__typename": "Stream",
+                "streamActions": [{ "id": "a", "__typename": "Stream_Action" }],
+            }] }
+        });
+
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        let stream = &out["data"]["streams"][0];
+        assert_eq!(stream["__typename"], "Stream");
+        assert!(stream.get("streamActions").is_none());
+        assert_eq!(stream["actions"][0]["__typename"], "Action");
+    }
+
+    #[test]
+    fn test_lowercase_address_ids() {
+        let mut config = Config::default();
+        config.response.lowercase_address_ids = true;
+        let address = "0xAbCdEf0123456789aBcDeF0123456789ABCDEF01";
+        let resp = serde_json::json!({
+            "data": { "Stream": [{ "id": address, "asset": { "id": format!("{}-1", address) } }] }
+        });
+
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        let stream = &out["data"]["streams"][0];
+        assert_eq!(stream["id"], address.to_ascii_lowercase());
+        assert_eq!(
+            stream["asset"]["id"],
+            format!("{}-1", address.to_ascii_lowercase())
+        );
+    }
+
+    #[test]
+    fn test_address_ids_are_lowercased() {
+        let mut config = Config::default();
+        config.ids.address_case = AddressCase::Checksum;
+        let address = "0xAbCdEf0123456789aBcDeF0123456789ABCDEF01";
+        let resp = serde_json::json!({
+            "data": { "Stream": [{
+                "id": format!("{}-42", address),
+                "asset": { "id": address },
+                "batch": { "id": "Batch-0xABC" },
+            }] }
+        });
+
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        let stream = &out["data"]["streams"][0];
+        assert_eq!(stream["id"], format!("{}-42", address.to_ascii_lowercase()));
+        assert_eq!(stream["asset"]["id"], address.to_ascii_lowercase());
+        assert_eq!(stream["batch"]["id"], "Batch-0xABC");
+    }
+
+    #[test]
+    fn test_passthrough_skips_nested_transforms() {
+        let mut config = Config::default();
+        config.ids.address_case = AddressCase::Checksum;
+        let resp = serde_json::json!({
+            "data": { "Stream": [{ "id": "0xAbCdEf0123456789aBcDeF0123456789ABCDEF01" }] }
+        });
+        let out = shape_response(
+            resp.clone(),
+            ResponseMode::Passthrough,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        assert_eq!(out, resp);
+    }
+
+    #[test]
+    fn test_forwarded_mutation_is_passed_through() {
+        let resp = serde_json::json!({ "data": { "cancel_Stream": { "id": "1", "amount": 1.5 } } });
+        let converted = ConvertedQuery {
+            forwarded: true,
+            ..Default::default()
+        };
+        let out = shape_response(
+            resp.clone(),
+            ResponseMode::Heuristic,
+            &converted,
+            &Config::default(),
+        );
+        assert_eq!(out, resp);
+    }
+
+    #[test]
+    fn test_stringify_big_numbers() {
+        let mut config = Config::default();
+        config.response.big_number_fields = vec!["amount".to_string()];
+        let resp = serde_json::json!({
+            "data": { "Stream": [{
+                "chainId": 1,
+                "amount": 5,
+                "deposit": 10_000_000_000_i64,
+                "ratio": 0.25,
+                "amounts": [1, 2],
+            }] }
+        });
+
+        let out = shape_response(
+            resp.clone(),
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        let stream = &out["data"]["streams"][0];
+        assert_eq!(stream["chainId"], 1);
+        assert_eq!(stream["amount"], "5");
+        assert_eq!(stream["deposit"], "10000000000");
+        assert_eq!(stream["ratio"], "0.25");
+        assert_eq!(stream["amounts"], serde_json::json!([1, 2]));
+
+        config.response.stringify_numbers = false;
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        assert_eq!(out["data"]["streams"][0]["deposit"], 10_000_000_000_i64);
+    }
+
+    #[test]
+    fn test_missing_by_pk_entity() {
+        let mut config = Config::default();
+        let resp = serde_json::json!({
+            "data": { "streamAction_by_pk": null, "Stream": [] }
+        });
+        let converted = ConvertedQuery {
+            field_map: HashMap::from([(
+                "streamAction_by_pk".to_string(),
+                "streamAction".to_string(),
+            )]),
+            ..Default::default()
+        };
+
+        for mode in [ResponseMode::Heuristic, ResponseMode::Exact] {
+            let out = shape_response(resp.clone(), mode, &converted, &config);
+            let data = out["data"].as_object().unwrap();
+            assert!(data.contains_key("streamAction"));
+            assert!(data["streamAction"].is_null());
+        }
+
+        config.response.missing_entity = MissingEntity::Omit;
+        let out = shape_response(resp, ResponseMode::Heuristic, &converted, &config);
+        let data = out["data"].as_object().unwrap();
+        assert!(!data.contains_key("streamAction"));
+        assert!(data.contains_key("streams"));
+    }
+
+    #[test]
+    fn test_post_filtered_rows() {
+        let config = Config::default();
+        let resp = serde_json::json!({ "data": { "stream_by_pk": [
+            { "id": "1", "__computed_tags": ["x"] },
+            { "id": "2", "__computed_tags": ["a"] },
+        ] } });
+        let converted = ConvertedQuery {
+            post_filtered: vec![post_filter::PostFilteredRoot {
+                root: "stream_by_pk".to_string(),
+                filters: vec![post_filter::PostFilter {
+                    column: "tags".to_string(),
+                    operator: "_containsAny".to_string(),
+                    value: serde_json::json!(["a"]),
+                }],
+                skip: 0,
+                first: Some(1),
+                limit: Some(2),
+            }],
+            ..Default::default()
+        };
+        let out = shape_response(resp, ResponseMode::Heuristic, &converted, &config);
+        assert_eq!(out["data"]["stream"], serde_json::json!({ "id": "2" }));
+        assert_eq!(
+            out["extensions"]["warnings"][0]["code"],
+            "POST_FILTER_TRUNCATED"
+        );
+    }
+
+    #[test]
+    fn test_aliased_roots_are_renamed() {
+        let config = Config::default();
+        let resp = serde_json::json!({ "data": {
+            "Recent__Stream": [{ "id": "1" }],
+            "one__stream_by_pk": [{ "id": "2" }],
+            "Stream": [],
+        } });
+        let converted = ConvertedQuery {
+            field_map: HashMap::from([
+                ("Recent__Stream".to_string(), "Recent".to_string()),
+                ("one__stream_by_pk".to_string(), "one".to_string()),
+                ("Stream".to_string(), "streams".to_string()),
+            ]),
+            root_aliases: vec![
+                ("Recent__Stream".to_string(), "Recent".to_string()),
+                ("one__stream_by_pk".to_string(), "one".to_string()),
+            ],
+            ..Default::default()
+        };
+        for mode in [ResponseMode::Heuristic, ResponseMode::Exact] {
+            let out = shape_response(resp.clone(), mode, &converted, &config);
+            assert_eq!(
+                out["data"],
+                serde_json::json!({ "Recent": [{ "id": "1" }], "one": { "id": "2" }, "streams": [] })
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_row_fallback_is_unwrapped() {
+        let config = Config::default();
+        let resp = serde_json::json!({
+            "data": { "stream_by_pk": [{ "id": "1" }], "batch_by_pk": [] }
+        });
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &config,
+        );
+        assert_eq!(out["data"]["stream"]["id"], "1");
+        assert!(out["data"]["batch"].is_null());
+    }
+
+    #[test]
+    fn test_interface_rows_are_merged_and_paginated() {
+        let converted = ConvertedQuery {
+            interfaces: vec![InterfaceMerge {
+                field: "positions".to_string(),
+                aliases: vec![
+                    ("positions__Lend".to_string(), "Lend".to_string()),
+                    ("positions__Borrow".to_string(), "Borrow".to_string()),
+                ],
+                order_by: Some("amount".to_string()),
+                descending: true,
+                skip: 1,
+                first: Some(2),
+            }],
+            ..Default::default()
+        };
+        let resp = serde_json::json!({ "data": {
+            "positions__Lend": [{ "id": "a", "amount": 9 }, { "id": "b", "amount": 3 }],
+            "positions__Borrow": [{ "id": "c", "amount": 7, "__typename": "Borrow" }],
+        } });
+
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &converted,
+            &Config::default(),
+        );
+        let data = out["data"].as_object().unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(
+            data["positions"],
+            serde_json::json!([
+                { "id": "c", "amount": 7, "__typename": "Borrow" },
+                { "id": "b", "amount": 3, "__typename": "Lend" },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_aggregates_are_unwrapped() {
+        let converted = ConvertedQuery {
+            field_map: HashMap::from([(
+                "Stream_aggregate".to_string(),
+                "streamsAggregate".to_string(),
+            )]),
+            aggregates: vec!["Stream_aggregate".to_string()],
+            ..Default::default()
+        };
+        let resp = serde_json::json!({ "data": {
+            "Stream_aggregate": { "aggregate": { "count": 3, "sum": { "amount": 12 } } }
+        } });
+        for mode in [ResponseMode::Exact, ResponseMode::Heuristic] {
+            let out = shape_response(resp.clone(), mode, &converted, &Config::default());
+            assert_eq!(out["data"]["streamsAggregate"]["count"], 3, "{:?}", mode);
+            assert_eq!(out["data"]["streamsAggregate"]["sum"]["amount"], 12);
+            assert!(out["data"].get("Stream_aggregate").is_none());
+        }
+        let out = shape_response(
+            resp.clone(),
+            ResponseMode::Passthrough,
+            &converted,
+            &Config::default(),
+        );
+        assert_eq!(out, resp);
+    }
+
+    #[test]
+    fn test_total_counts_move_to_extensions() {
+        let converted = ConvertedQuery {
+            total_counts: vec![("streams__totalCount".to_string(), "streams".to_string())],
+            ..Default::default()
+        };
+        let resp = serde_json::json!({ "data": {
+            "Stream": [{ "id": "1" }],
+            "streams__totalCount": { "aggregate": { "count": 42 } },
+        } });
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &converted,
+            &Config::default(),
+        );
+        assert!(out["data"].get("streams__totalCount").is_none());
+        assert_eq!(out["data"]["streams"][0]["id"], "1");
+        assert_eq!(out["extensions"]["totalCount"]["streams"], 42);
+    }
+
+    #[test]
+    fn test_warnings_are_attached_in_every_mode() {
+        let converted = ConvertedQuery {
+            warnings: vec![ConversionWarning {
+                code: "DROPPED",
+                message: "Dropped: Unsupported filter: tags_containsAny".to_string(),
+            }],
+            ..Default::default()
+        };
+        let resp = serde_json::json!({ "data": { "Stream": [] } });
+        for mode in [
+            ResponseMode::Exact,
+            ResponseMode::Heuristic,
+            ResponseMode::Passthrough,
+        ] {
+            let out = shape_response(resp.clone(), mode, &converted, &Config::default());
+            assert_eq!(out["extensions"]["warnings"][0]["code"], "DROPPED");
+        }
+        let out = shape_response(
+            resp,
+            ResponseMode::Heuristic,
+            &ConvertedQuery::default(),
+            &Config::default(),
+        );
+        assert!(out.get("extensions").is_none());
+    }
+
+    #[test]
+    fn test_requested_mode_precedence() {
+        let mut headers = HeaderMap::new();
+        let mut params = HashMap::new();
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Heuristic),
+            ResponseMode::Heuristic
+        );
+
+        headers.insert(RESPONSE_MODE_HEADER, "passthrough".parse().unwrap());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Heuristic),
+            ResponseMode::Passthrough
+        );
+
+        params.insert(RESPONSE_MODE_PARAM.to_string(), "Exact".to_string());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Heuristic),
+            ResponseMode::Exact
+        );
+
+        params.insert(RESPONSE_MODE_PARAM.to_string(), "bogus".to_string());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Heuristic),
+            ResponseMode::Heuristic
+        );
+    }
+
+    #[test]
+    fn test_requested_response_shape() {
+        let mut headers = HeaderMap::new();
+        let params = HashMap::new();
+        headers.insert(RESPONSE_SHAPE_HEADER, "Hyperindex".parse().unwrap());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Exact),
+            ResponseMode::Passthrough
+        );
+
+        headers.insert(RESPONSE_SHAPE_HEADER, "subgraph".parse().unwrap());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Exact),
+            ResponseMode::Exact
+        );
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Passthrough),
+            ResponseMode::Heuristic
+        );
+
+        // An explicit mode wins over the shape
+        headers.insert(RESPONSE_MODE_HEADER, "exact".parse().unwrap());
+        headers.insert(RESPONSE_SHAPE_HEADER, "hyperindex".parse().unwrap());
+        assert_eq!(
+            requested_mode(&headers, &params, ResponseMode::Heuristic),
+            ResponseMode::Exact
+        );
+    }
+}
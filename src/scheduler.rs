@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use axum::http::HeaderMap;
+use tokio::sync::oneshot;
+
+/// Request header marking a request's priority class. Migration tooling replaying traffic
+/// sends `background` so it yields to user-facing queries.
+pub const PRIORITY_HEADER: &str = "x-request-priority";
+
+/// Which queue a request waits in when every upstream slot is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// User-facing queries; the default.
+    Interactive,
+    /// Tooling traffic (`/explain`, requests sending `x-request-priority: background`), only
+    /// given a slot when no interactive request is waiting.
+    Background,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "interactive" => Ok(Priority::Interactive),
+            "background" => Ok(Priority::Background),
+            other => Err(format!(
+                "unknown {} {:?}, expected interactive or background",
+                PRIORITY_HEADER, other
+            )),
+        }
+    }
+}
+
+/// The priority a request asked for; interactive unless it sent a valid header.
+pub fn requested(headers: &HeaderMap) -> Priority {
+    headers
+        .get(PRIORITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(Priority::Interactive)
+}
+
+/// Bounds the requests in flight to Hyperindex at `limit` (0 for no limit). A finished request
+/// hands its slot to the oldest waiting interactive request, or else the oldest background one.
+pub struct Scheduler {
+    limit: usize,
+    state: Mutex<Queues>,
+}
+
+#[derive(Default)]
+struct Queues {
+    running: usize,
+    interactive: VecDeque<oneshot::Sender<Permit>>,
+    background: VecDeque<oneshot::Sender<Permit>>,
+}
+
+/// A slot for one upstream request, handed on when dropped.
+pub struct Permit {
+    // `None` without a limit, and once the slot has been released
+    scheduler: Option<Arc<Scheduler>>,
+}
+
+impl Scheduler {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(Queues::default()),
+        }
+    }
+
+    /// Waits for a slot in `priority`'s queue.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        if self.limit == 0 {
+            return Permit { scheduler: None };
+        }
+        let waiting = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.limit {
+                state.running += 1;
+                return Permit {
+                    scheduler: Some(self.clone()),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                Priority::Interactive => state.interactive.push_back(sender),
+                Priority::Background => state.background.push_back(sender),
+            }
+            receiver
+        };
+        // The sender is only dropped with the scheduler, which this request holds
+        waiting.await.expect("scheduler dropped a waiting request")
+    }
+
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let next = match state.interactive.pop_front() {
+                Some(sender) => sender,
+                None => match state.background.pop_front() {
+                    Some(sender) => sender,
+                    None => break,
+                },
+            };
+            let permit = Permit {
+                scheduler: Some(self.clone()),
+            };
+            // A waiter that gave up returns the permit; it must not release the slot again
+            match next.send(permit) {
+                Ok(()) => return,
+                Err(mut permit) => permit.scheduler = None,
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_interactive_requests_go_first() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let running = scheduler.acquire(Priority::Interactive).await;
+
+        let (order, mut finished) = tokio::sync::mpsc::unbounded_channel();
+        let mut waiting = Vec::new();
+        for (name, priority) in [
+            ("background", Priority::Background),
+            ("abandoned", Priority::Interactive),
+            ("interactive", Priority::Interactive),
+        ] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            waiting.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await;
+                order.send(name).unwrap();
+            }));
+            // Queue them in this order
+            tokio::task::yield_now().await;
+        }
+        waiting.remove(1).abort();
+        tokio::task::yield_now().await;
+
+        drop(running);
+        assert_eq!(finished.recv().await, Some("interactive"));
+        assert_eq!(finished.recv().await, Some("background"));
+        for task in waiting {
+            task.await.unwrap();
+        }
+        assert_eq!(scheduler.state.lock().unwrap().running, 0);
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(requested(&headers), Priority::Interactive);
+        headers.insert(PRIORITY_HEADER, " Background ".parse().unwrap());
+        assert_eq!(requested(&headers), Priority::Background);
+        headers.insert(PRIORITY_HEADER, "urgent".parse().unwrap());
+        assert_eq!(requested(&headers), Priority::Interactive);
+    }
+}
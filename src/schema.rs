@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Column types of Hyperindex entities, declared as `[schema.<Entity>]` tables mapping each
+/// field to its GraphQL type (e.g. `tags = "[String!]!"`, `pair = "Pair"`). Entities and fields
+/// that aren't declared are converted without type information.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Schema {
+    entities: HashMap<String, HashMap<String, String>>,
+}
+
+/// A field's GraphQL type with non-null markers removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldType<'a> {
+    /// Named type, e.g. `String` for `[String!]!`.
+    pub name: &'a str,
+    /// Whether the field is a list.
+    pub list: bool,
+}
+
+impl Schema {
+    pub fn field_type(&self, entity: &str, field: &str) -> Option<FieldType<'_>> {
+        let declared = self.entities.get(entity)?.get(field)?;
+        let trimmed = declared.trim().trim_end_matches('!');
+        let list = trimmed.starts_with('[');
+        let name = trimmed
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('!')
+            .trim();
+        Some(FieldType { name, list })
+    }
+
+    pub fn declares(&self, entity: &str) -> bool {
+        self.entities.contains_key(entity)
+    }
+
+    pub fn is_list(&self, entity: &str, field: &str) -> bool {
+        self.field_type(entity, field).is_some_and(|t| t.list)
+    }
+
+    /// Entity a relationship field points at, when it's declared with an entity type.
+    pub fn related_entity(&self, entity: &str, field: &str) -> Option<&str> {
+        let name = self.field_type(entity, field)?.name;
+        self.declares(name).then_some(name)
+    }
+}
+
+/// Fields of each Hyperindex object type, as introspected from the running indexer and named
+/// the way it names them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpstreamFields {
+    types: HashMap<String, HashSet<String>>,
+}
+
+impl UpstreamFields {
+    pub fn new(types: HashMap<String, HashSet<String>>) -> Self {
+        Self { types }
+    }
+
+    /// Whether `type_name` has `field`; types introspection didn't return are assumed to.
+    pub fn has_field(&self, type_name: &str, field: &str) -> bool {
+        self.types
+            .get(type_name)
+            .is_none_or(|fields| fields.contains(field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema {
+            entities: HashMap::from([
+                (
+                    "Stream".to_string(),
+                    HashMap::from([
+                        ("tags".to_string(), "[String!]!".to_string()),
+                        ("name".to_string(), "String!".to_string()),
+                        ("pair".to_string(), "Pair".to_string()),
+                    ]),
+                ),
+                ("Pair".to_string(), HashMap::new()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_field_type_strips_non_null_markers() {
+        let schema = schema();
+        assert_eq!(
+            schema.field_type("Stream", "tags"),
+            Some(FieldType {
+                name: "String",
+                list: true
+            })
+        );
+        assert!(!schema.is_list("Stream", "name"));
+        assert!(!schema.is_list("Stream", "unknown"));
+        assert!(!schema.is_list("Unknown", "tags"));
+    }
+
+    #[test]
+    fn test_related_entity() {
+        let schema = schema();
+        assert_eq!(schema.related_entity("Stream", "pair"), Some("Pair"));
+        assert_eq!(schema.related_entity("Stream", "name"), None);
+    }
+}
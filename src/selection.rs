@@ -0,0 +1,192 @@
+//! Normalizing converted selection sets: a field selected more than once (e.g. explicitly and
+//! again through a narrowed fragment or a computed field's inputs) is selected once, with the
+//! selection sets of its copies merged.
+
+use std::borrow::Cow;
+
+use crate::format::{tokenize, Token};
+
+// One selection: a field, or a fragment spread or inline fragment. `head` is everything before
+// its selection set (alias, name, arguments, directives, or `... on Type`).
+#[derive(Debug)]
+struct Item<'a> {
+    head: Vec<Token<'a>>,
+    set: Option<Vec<Item<'a>>>,
+}
+
+/// The selection set (braces included) with duplicate selections merged; borrowed when there
+/// are none, so the source layout is kept.
+pub fn dedupe(selection: &str) -> Cow<'_, str> {
+    let tokens = tokenize(selection);
+    if tokens.first() != Some(&Token::Punct('{')) {
+        return Cow::Borrowed(selection);
+    }
+    let mut pos = 1;
+    let mut merged = false;
+    let items = parse_set(&tokens, &mut pos, &mut merged);
+    if !merged || pos != tokens.len() {
+        return Cow::Borrowed(selection);
+    }
+    let mut output = String::with_capacity(selection.len());
+    write_set(&items, &mut output);
+    Cow::Owned(output)
+}
+
+// The items of a selection set whose `{` is just before `pos`, up to and past its `}`. Items
+// with the same head are merged into the first; `merged` is set when any were.
+fn parse_set<'a>(tokens: &[Token<'a>], pos: &mut usize, merged: &mut bool) -> Vec<Item<'a>> {
+    let mut items: Vec<Item<'a>> = Vec::new();
+    while let Some(token) = tokens.get(*pos) {
+        if *token == Token::Punct('}') {
+            *pos += 1;
+            break;
+        }
+        let head = parse_head(tokens, pos);
+        if head.is_empty() {
+            // Not a selection; leave the set as it was
+            *pos = tokens.len() + 1;
+            break;
+        }
+        let set = (tokens.get(*pos) == Some(&Token::Punct('{'))).then(|| {
+            *pos += 1;
+            parse_set(tokens, pos, merged)
+        });
+        match items.iter_mut().find(|item| item.head == head) {
+            Some(existing) => {
+                *merged = true;
+                if let (Some(existing), Some(set)) = (&mut existing.set, set) {
+                    merge(existing, set);
+                }
+            }
+            None => items.push(Item { head, set }),
+        }
+    }
+    items
+}
+
+fn merge<'a>(into: &mut Vec<Item<'a>>, items: Vec<Item<'a>>) {
+    for item in items {
+        match into.iter_mut().find(|existing| existing.head == item.head) {
+            Some(existing) => {
+                if let (Some(existing), Some(set)) = (&mut existing.set, item.set) {
+                    merge(existing, set);
+                }
+            }
+            None => into.push(item),
+        }
+    }
+}
+
+// The tokens of one selection up to its selection set: `alias: name(args) @directive(args)`,
+// `...Name @directive` or `... on Type @directive`. Empty when `pos` isn't at a selection.
+fn parse_head<'a>(tokens: &[Token<'a>], pos: &mut usize) -> Vec<Token<'a>> {
+    let mut head = Vec::new();
+    let take = |head: &mut Vec<Token<'a>>, pos: &mut usize| {
+        head.push(tokens[*pos]);
+        *pos += 1;
+    };
+    match tokens.get(*pos) {
+        Some(Token::Spread) => {
+            take(&mut head, pos);
+            if tokens.get(*pos) == Some(&Token::Word("on")) {
+                take(&mut head, pos);
+            }
+            if let Some(Token::Word(_)) = tokens.get(*pos) {
+                take(&mut head, pos);
+            }
+        }
+        Some(Token::Word(_)) => {
+            take(&mut head, pos);
+            if tokens.get(*pos) == Some(&Token::Punct(':')) {
+                take(&mut head, pos);
+                if let Some(Token::Word(_)) = tokens.get(*pos) {
+                    take(&mut head, pos);
+                }
+            }
+        }
+        _ => return head,
+    }
+    loop {
+        match tokens.get(*pos) {
+            Some(Token::Punct('(')) => {
+                let mut depth = 0;
+                while let Some(token) = tokens.get(*pos) {
+                    match token {
+                        Token::Punct('(' | '[' | '{') => depth += 1,
+                        Token::Punct(')' | ']' | '}') => depth -= 1,
+                        _ => {}
+                    }
+                    take(&mut head, pos);
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            Some(Token::Punct('@')) => {
+                take(&mut head, pos);
+                if let Some(Token::Word(_)) = tokens.get(*pos) {
+                    take(&mut head, pos);
+                }
+            }
+            _ => return head,
+        }
+    }
+}
+
+fn write_set(items: &[Item], output: &mut String) {
+    output.push('{');
+    for item in items {
+        output.push(' ');
+        let mut previous: Option<Token> = None;
+        for token in &item.head {
+            let space = match (previous, token) {
+                (None, _) => false,
+                (_, Token::Punct(':' | '(' | ')' | ']' | '}' | '!')) => false,
+                (Some(Token::Punct('(' | '[' | '{' | '@')), _) => false,
+                (Some(Token::Spread), token) => *token == Token::Word("on"),
+                _ => true,
+            };
+            if space {
+                output.push(' ');
+            }
+            match token {
+                Token::Word(text) | Token::Str(text) => output.push_str(text),
+                Token::Spread => output.push_str("..."),
+                Token::Punct(c) => output.push(*c),
+            }
+            previous = Some(*token);
+        }
+        if let Some(set) = &item.set {
+            output.push(' ');
+            write_set(set, output);
+        }
+    }
+    output.push_str(" }");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merges_duplicate_selections() {
+        assert_eq!(
+            dedupe("{ id pair { id token0 { symbol } } amount: amountUSD id pair { token0 { id } fee } }"),
+            "{ id pair { id token0 { symbol id } fee } amount: amountUSD }"
+        );
+        assert_eq!(
+            dedupe("{ ...F id ... on Swap { sender } ...F ... on Swap { sender to } }"),
+            "{ ...F id ... on Swap { sender to } }"
+        );
+        assert_eq!(
+            dedupe("{ a: id a: name items(first: 2) { id } items(first: 3) { id } }"),
+            "{ a: id a: name items(first: 2) { id } items(first: 3) { id } }"
+        );
+    }
+
+    #[test]
+    fn test_keeps_layout_without_duplicates() {
+        let selection = "{\n    id\n    pair { id }\n  }";
+        assert!(matches!(dedupe(selection), Cow::Borrowed(s) if s == selection));
+    }
+}
@@ -0,0 +1,108 @@
+use axum::http::HeaderMap;
+use serde_json::Value;
+
+use crate::config::SelfTestQuery;
+use crate::{conversion, conversion_options, forward_to_hyperindex, AppState};
+
+/// Converts and runs every `self_test.queries` entry against Hyperindex, returning one message
+/// per query that failed to convert, couldn't be sent, or came back with GraphQL errors.
+pub async fn run(state: &AppState) -> Result<(), Vec<String>> {
+    let mut failures = Vec::new();
+    for (i, test) in state.config.self_test.queries.iter().enumerate() {
+        if let Err(e) = run_one(state, test).await {
+            failures.push(format!("{}: {}", name(i, test), e));
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+fn name(i: usize, test: &SelfTestQuery) -> String {
+    if test.name.is_empty() {
+        format!("self_test.queries[{}]", i)
+    } else {
+        test.name.clone()
+    }
+}
+
+async fn run_one(state: &AppState, test: &SelfTestQuery) -> Result<(), String> {
+    let headers = HeaderMap::new();
+    let mut payload = serde_json::json!({ "query": test.query });
+    if let Some(variables) = &test.variables {
+        payload["variables"] = variables.clone();
+    }
+    let converted = conversion::convert_subgraph_to_hyperindex_with_options(
+        &payload,
+        test.chain_id.as_deref(),
+        &conversion_options(state, &headers),
+    )
+    .map_err(|e| format!("conversion failed ({}): {}", e.code(), e))?;
+
+    let response =
+        forward_to_hyperindex(state, test.chain_id.as_deref(), &headers, &converted.body)
+            .await
+            .map_err(|e| format!("Hyperindex request failed: {}", e))?;
+    match response.get("errors") {
+        Some(errors) => Err(format!("Hyperindex returned errors: {}", errors)),
+        None if response.get("data").is_none_or(Value::is_null) => {
+            Err("Hyperindex returned no data".to_string())
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::{routing::post, Json, Router};
+
+    // Answers like Hasura: an error for queries on `Missing`, otherwise an empty result.
+    async fn upstream(Json(body): Json<Value>) -> Json<Value> {
+        let query = body["query"].as_str().unwrap_or_default();
+        if query.contains("Missing") {
+            Json(serde_json::json!({
+                "errors": [{ "message": "field 'Missing' not found in type: 'query_root'" }]
+            }))
+        } else {
+            Json(serde_json::json!({ "data": { "Stream": [] } }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_failing_queries() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(upstream)))
+                .await
+                .unwrap();
+        });
+
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = format!("http://{}/", addr);
+        config.self_test.queries = vec![
+            SelfTestQuery {
+                name: "streams".to_string(),
+                query: "{ streams(first: 1) { id } }".to_string(),
+                ..Default::default()
+            },
+            SelfTestQuery {
+                query: "{ missings(first: 1) { id } }".to_string(),
+                ..Default::default()
+            },
+            SelfTestQuery {
+                name: "block".to_string(),
+                query: "{ streams(block: {number: 1}) { id } }".to_string(),
+                ..Default::default()
+            },
+        ];
+        let failures = run(&AppState::new(config)).await.unwrap_err();
+        assert_eq!(failures.len(), 2);
+        assert!(failures[0].starts_with("self_test.queries[1]: Hyperindex returned errors"));
+        assert!(failures[1].starts_with("block: conversion failed (UNSUPPORTED_BLOCK_ARGUMENT)"));
+    }
+}
@@ -0,0 +1,325 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::config::StatsConfig;
+use crate::conversion::{ConversionError, ConvertedQuery};
+use crate::AppState;
+
+/// Distinct names kept per counter map; further names are counted under `OTHER`, so clients
+/// sending arbitrary entity names can't grow the store without bound.
+const MAX_KEYS: usize = 1000;
+const OTHER: &str = "(other)";
+
+/// Usage counters served at `/admin/stats`: how often each entity, filter operator and
+/// unsupported construct shows up in converted traffic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Counters {
+    /// Queries converted successfully.
+    pub conversions: u64,
+    /// Queries rejected by the converter.
+    pub failures: u64,
+    /// Root fields per Hyperindex entity.
+    pub entities: BTreeMap<String, u64>,
+    /// Filter conditions per subgraph operator (`_eq` for plain equality).
+    pub filters: BTreeMap<String, u64>,
+    /// Rejected queries per error code.
+    pub errors: BTreeMap<String, u64>,
+    /// Constructs dropped in lenient mode per error code.
+    pub dropped: BTreeMap<String, u64>,
+}
+
+impl Counters {
+    fn record(&mut self, result: &Result<ConvertedQuery, ConversionError>) {
+        match result {
+            Ok(converted) => {
+                self.conversions += 1;
+                let usage = &converted.usage;
+                for entity in &usage.entities {
+                    increment(&mut self.entities, entity, 1);
+                }
+                for operator in &usage.filters {
+                    increment(&mut self.filters, operator, 1);
+                }
+                for code in &usage.dropped {
+                    increment(&mut self.dropped, code, 1);
+                }
+            }
+            Err(e) => {
+                self.failures += 1;
+                increment(&mut self.errors, e.code(), 1);
+            }
+        }
+    }
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, name: &str, by: u64) {
+    let name = if counts.len() >= MAX_KEYS && !counts.contains_key(name) {
+        OTHER
+    } else {
+        name
+    };
+    *counts.entry(name.to_string()).or_default() += by;
+}
+
+pub struct Stats {
+    enabled: bool,
+    counters: Mutex<Counters>,
+    #[cfg(feature = "sqlite")]
+    store: Option<sqlite::Store>,
+}
+
+impl Stats {
+    /// Starts from the counters saved at `sqlite_path`, if configured.
+    pub fn new(config: &StatsConfig) -> Self {
+        let stats = Self {
+            enabled: config.enabled,
+            counters: Mutex::default(),
+            #[cfg(feature = "sqlite")]
+            store: config.sqlite_path.as_deref().and_then(|path| {
+                sqlite::Store::open(path)
+                    .map_err(|e| tracing::error!("Failed to open stats database {}: {}", path, e))
+                    .ok()
+            }),
+        };
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &stats.store {
+            match store.load() {
+                Ok(counters) => *stats.counters.lock().unwrap() = counters,
+                Err(e) => tracing::error!("Failed to load saved stats: {}", e),
+            }
+        }
+        stats
+    }
+
+    pub fn record(&self, result: &Result<ConvertedQuery, ConversionError>) {
+        if self.enabled {
+            self.counters.lock().unwrap().record(result);
+        }
+    }
+
+    pub fn snapshot(&self) -> Counters {
+        self.counters.lock().unwrap().clone()
+    }
+
+    /// Writes the counters to the SQLite store, if there is one.
+    pub fn flush(&self) {
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &self.store {
+            if let Err(e) = store.save(&self.snapshot()) {
+                tracing::error!("Failed to save stats: {}", e);
+            }
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn persisted(&self) -> bool {
+        self.store.is_some()
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    pub fn persisted(&self) -> bool {
+        false
+    }
+}
+
+pub async fn handle_stats(State(state): State<AppState>) -> impl IntoResponse {
+    if !state.config.stats.expose {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "Stats are not exposed (stats.expose = false)" })),
+        );
+    }
+    if !state.stats.enabled {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "Stats are disabled (stats.enabled = false)" })),
+        );
+    }
+    (
+        StatusCode::OK,
+        Json(serde_json::to_value(state.stats.snapshot()).unwrap_or_default()),
+    )
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use rusqlite::{params, Connection};
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use super::{increment, Counters};
+
+    // One row per counter: `kind` is `total` (conversions/failures) or a counter map's name.
+    pub struct Store {
+        connection: Mutex<Connection>,
+    }
+
+    impl Store {
+        pub fn open(path: &str) -> rusqlite::Result<Self> {
+            let connection = Connection::open(path)?;
+            connection.execute(
+                "CREATE TABLE IF NOT EXISTS stats (
+                    kind TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    count INTEGER NOT NULL,
+                    PRIMARY KEY (kind, name)
+                )",
+                [],
+            )?;
+            Ok(Self {
+                connection: Mutex::new(connection),
+            })
+        }
+
+        pub fn load(&self) -> rusqlite::Result<Counters> {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection.prepare("SELECT kind, name, count FROM stats")?;
+            let rows = statement.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                ))
+            })?;
+            let mut counters = Counters::default();
+            for row in rows {
+                let (kind, name, count) = row?;
+                match (kind.as_str(), name.as_str()) {
+                    ("total", "conversions") => counters.conversions = count,
+                    ("total", "failures") => counters.failures = count,
+                    ("entities", _) => increment(&mut counters.entities, &name, count),
+                    ("filters", _) => increment(&mut counters.filters, &name, count),
+                    ("errors", _) => increment(&mut counters.errors, &name, count),
+                    ("dropped", _) => increment(&mut counters.dropped, &name, count),
+                    _ => {}
+                }
+            }
+            Ok(counters)
+        }
+
+        pub fn save(&self, counters: &Counters) -> rusqlite::Result<()> {
+            let mut connection = self.connection.lock().unwrap();
+            let transaction = connection.transaction()?;
+            {
+                let mut upsert = transaction.prepare(
+                    "INSERT OR REPLACE INTO stats (kind, name, count) VALUES (?1, ?2, ?3)",
+                )?;
+                upsert.execute(params!["total", "conversions", counters.conversions as i64])?;
+                upsert.execute(params!["total", "failures", counters.failures as i64])?;
+                let maps: [(&str, &BTreeMap<String, u64>); 4] = [
+                    ("entities", &counters.entities),
+                    ("filters", &counters.filters),
+                    ("errors", &counters.errors),
+                    ("dropped", &counters.dropped),
+                ];
+                for (kind, counts) in maps {
+                    for (name, count) in counts {
+                        upsert.execute(params![kind, name, *count as i64])?;
+                    }
+                }
+            }
+            transaction.commit()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversion::{self, ConversionMode, ConversionOptions};
+
+    fn convert(
+        query: &str,
+        options: &ConversionOptions,
+    ) -> Result<ConvertedQuery, ConversionError> {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &serde_json::json!({ "query": query }),
+            Some("1"),
+            options,
+        )
+    }
+
+    #[test]
+    fn test_counts_entities_filters_and_failures() {
+        let stats = Stats::new(&StatsConfig::default());
+        let options = ConversionOptions::default();
+        stats.record(&convert(
+            "{ streams(where: {name: \"a\", amount_gt: 1}) { id } batches(where: {size_gt: 2}) { id } }",
+            &options,
+        ));
+        stats.record(&convert(
+            "{ streams(where: {tags_containsAny: [\"a\"]}) { id } }",
+            &options,
+        ));
+
+        let lenient = ConversionOptions {
+            mode: ConversionMode::Lenient,
+            ..Default::default()
+        };
+        stats.record(&convert("{ streams(block: {number: 1}) { id } }", &lenient));
+
+        let counters = stats.snapshot();
+        assert_eq!((counters.conversions, counters.failures), (2, 1));
+        assert_eq!(counters.entities["Stream"], 2);
+        assert_eq!(counters.entities["Batch"], 1);
+        assert_eq!(counters.filters["_gt"], 2);
+        assert_eq!(counters.filters["_eq"], 1);
+        assert_eq!(counters.errors["UNSUPPORTED_FILTER"], 1);
+        assert_eq!(counters.dropped["UNSUPPORTED_BLOCK_ARGUMENT"], 1);
+    }
+
+    #[test]
+    fn test_disabled_stats_record_nothing() {
+        let stats = Stats::new(&StatsConfig {
+            enabled: false,
+            ..Default::default()
+        });
+        stats.record(&convert(
+            "{ streams { id } }",
+            &ConversionOptions::default(),
+        ));
+        assert_eq!(stats.snapshot(), Counters::default());
+    }
+
+    #[tokio::test]
+    async fn test_stats_are_served_only_when_exposed() {
+        let mut config = crate::config::Config::default();
+        let hidden = handle_stats(State(AppState::new(config.clone()))).await;
+        assert_eq!(hidden.into_response().status(), StatusCode::NOT_FOUND);
+        config.stats.expose = true;
+        let served = handle_stats(State(AppState::new(config))).await;
+        assert_eq!(served.into_response().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_distinct_names_are_capped() {
+        let mut counts = BTreeMap::new();
+        for i in 0..MAX_KEYS + 5 {
+            increment(&mut counts, &format!("Entity{}", i), 1);
+        }
+        assert_eq!(counts.len(), MAX_KEYS + 1);
+        assert_eq!(counts[OTHER], 5);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_counters_survive_restart() {
+        let path = std::env::temp_dir().join(format!("stats-{}.sqlite", std::process::id()));
+        let config = StatsConfig {
+            sqlite_path: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let stats = Stats::new(&config);
+        stats.record(&convert(
+            "{ streams { id } }",
+            &ConversionOptions::default(),
+        ));
+        stats.flush();
+
+        let reopened = Stats::new(&config);
+        assert_eq!(reopened.snapshot(), stats.snapshot());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,264 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::conversion;
+use crate::{forward_to_hyperindex, AppState};
+
+const CHAIN_METADATA_QUERY: &str = "query { chain_metadata { chain_id block_height latest_fetched_block_number timestamp_caught_up_to_head_or_endblock } }";
+
+/// A deployment's indexing status as Graph Node reports it, synthesized from Hyperindex
+/// `chain_metadata`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexingStatus {
+    pub subgraph: String,
+    /// Every chain has caught up with its head (or end block).
+    pub synced: bool,
+    /// `healthy`, or `failed` when Hyperindex couldn't be asked.
+    pub health: &'static str,
+    pub fatal_error: Option<FatalError>,
+    pub chains: Vec<ChainStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FatalError {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainStatus {
+    /// The chain id; Graph Node reports a network name here.
+    pub network: String,
+    pub chain_head_block: Option<Block>,
+    pub latest_block: Option<Block>,
+}
+
+/// Block numbers are strings, like Graph Node's `BigInt`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Block {
+    pub number: String,
+}
+
+/// `GET /status`
+pub async fn handle_status(State(state): State<AppState>) -> Json<IndexingStatus> {
+    Json(indexing_status(&state).await)
+}
+
+/// `POST /index-node/graphql`: answers the indexing status root fields of Graph Node's index
+/// node API with the whole status object, whatever the selection.
+pub async fn handle_index_node(
+    State(state): State<AppState>,
+    Json(payload): Json<Value>,
+) -> Json<Value> {
+    let query = payload["query"].as_str().unwrap_or_default();
+    let requested = |field: &str| conversion::name_location(query, field).is_some();
+    let lists = ["indexingStatuses", "indexingStatusesForSubgraphName"];
+    let singles = [
+        "indexingStatusForCurrentVersion",
+        "indexingStatusForPendingVersion",
+    ];
+    if !lists.into_iter().chain(singles).any(requested) {
+        return Json(serde_json::json!({
+            "errors": [{ "message": "Only indexing status queries are supported" }]
+        }));
+    }
+
+    let status = serde_json::to_value(indexing_status(&state).await).unwrap_or_default();
+    let mut data = serde_json::Map::new();
+    for field in lists.into_iter().filter(|f| requested(f)) {
+        data.insert(field.to_string(), Value::Array(vec![status.clone()]));
+    }
+    if requested("indexingStatusForCurrentVersion") {
+        data.insert("indexingStatusForCurrentVersion".to_string(), status);
+    }
+    // Hyperindex has no pending versions
+    if requested("indexingStatusForPendingVersion") {
+        data.insert("indexingStatusForPendingVersion".to_string(), Value::Null);
+    }
+    Json(serde_json::json!({ "data": data }))
+}
+
+/// Asks every configured upstream for its `chain_metadata`: the default one for all chains
+/// without their own URL, and each chain's own URL for that chain.
+pub async fn indexing_status(state: &AppState) -> IndexingStatus {
+    let config = &state.config;
+    let own_url = |chain_id: &str| {
+        config
+            .chains
+            .get(chain_id)
+            .is_some_and(|chain| chain.hyperindex_url.is_some())
+    };
+    let mut upstreams: Vec<Option<&str>> = vec![None];
+    let mut with_url: Vec<&str> = config
+        .chains
+        .keys()
+        .map(String::as_str)
+        .filter(|id| own_url(id))
+        .collect();
+    with_url.sort();
+    upstreams.extend(with_url.into_iter().map(Some));
+
+    // Each chain with whether it has caught up
+    let mut chains: Vec<(ChainStatus, bool)> = Vec::new();
+    let mut errors = Vec::new();
+    for chain_id in upstreams {
+        match chain_metadata(state, chain_id).await {
+            Ok(rows) => {
+                chains.extend(
+                    rows.iter()
+                        .map(chain_status)
+                        .filter(|(chain, _)| match chain_id {
+                            Some(id) => chain.network == id,
+                            None => !own_url(&chain.network),
+                        }),
+                )
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    IndexingStatus {
+        subgraph: config.status.deployment.clone(),
+        synced: errors.is_empty() && !chains.is_empty() && chains.iter().all(|(_, synced)| *synced),
+        health: if errors.is_empty() {
+            "healthy"
+        } else {
+            "failed"
+        },
+        fatal_error: errors
+            .into_iter()
+            .next()
+            .map(|message| FatalError { message }),
+        chains: chains.into_iter().map(|(chain, _)| chain).collect(),
+    }
+}
+
+fn chain_status(row: &Value) -> (ChainStatus, bool) {
+    let text = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+    let block = |key: &str| text(&row[key]).map(|number| Block { number });
+    let status = ChainStatus {
+        network: text(&row["chain_id"]).unwrap_or_default(),
+        chain_head_block: block("block_height"),
+        latest_block: block("latest_fetched_block_number"),
+    };
+    (
+        status,
+        !row["timestamp_caught_up_to_head_or_endblock"].is_null(),
+    )
+}
+
+async fn chain_metadata(state: &AppState, chain_id: Option<&str>) -> Result<Vec<Value>, String> {
+    let body = serde_json::json!({ "query": CHAIN_METADATA_QUERY });
+    let response = forward_to_hyperindex(state, chain_id, &HeaderMap::new(), &body)
+        .await
+        .map_err(|e| format!("Hyperindex request failed: {}", e))?;
+    if let Some(errors) = response.get("errors") {
+        return Err(format!("Hyperindex returned errors: {}", errors));
+    }
+    match &response["data"]["chain_metadata"] {
+        Value::Array(rows) => Ok(rows.clone()),
+        _ => Err("Hyperindex returned no chain_metadata".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChainConfig, Config};
+    use axum::{routing::post, Router};
+
+    // Serves `chain_metadata` rows for chains 1 and 10, the second still syncing.
+    async fn upstream(Json(_): Json<Value>) -> Json<Value> {
+        Json(serde_json::json!({ "data": { "chain_metadata": [
+            {
+                "chain_id": 1,
+                "block_height": 200,
+                "latest_fetched_block_number": 199,
+                "timestamp_caught_up_to_head_or_endblock": "2026-01-01T00:00:00Z"
+            },
+            {
+                "chain_id": 10,
+                "block_height": 500,
+                "latest_fetched_block_number": 20,
+                "timestamp_caught_up_to_head_or_endblock": null
+            }
+        ] } }))
+    }
+
+    async fn serve() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(upstream)))
+                .await
+                .unwrap();
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_status_from_chain_metadata() {
+        let mut config = Config::default();
+        config.upstream.hyperindex_url = serve().await;
+        let state = AppState::new(config);
+
+        let status = indexing_status(&state).await;
+        assert_eq!(status.health, "healthy");
+        assert!(!status.synced);
+        assert_eq!(status.chains.len(), 2);
+        assert_eq!(status.chains[0].network, "1");
+        assert_eq!(
+            status.chains[0].chain_head_block.as_ref().unwrap().number,
+            "200"
+        );
+        assert_eq!(
+            status.chains[0].latest_block.as_ref().unwrap().number,
+            "199"
+        );
+
+        let Json(response) = handle_index_node(
+            State(state),
+            Json(serde_json::json!({
+                "query": "{ indexingStatusForCurrentVersion(subgraphName: \"x\") { synced health } }"
+            })),
+        )
+        .await;
+        assert_eq!(
+            response["data"]["indexingStatusForCurrentVersion"]["subgraph"],
+            "hyperindex"
+        );
+        assert_eq!(
+            response["data"]["indexingStatusForCurrentVersion"]["synced"],
+            false
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_upstreams_and_failures() {
+        let mut config = Config::default();
+        // Chain 10 has its own upstream, which is down
+        config.upstream.hyperindex_url = serve().await;
+        config.chains.insert(
+            "10".to_string(),
+            ChainConfig {
+                hyperindex_url: Some("http://127.0.0.1:1/".to_string()),
+                ..Default::default()
+            },
+        );
+        let status = indexing_status(&AppState::new(config)).await;
+        assert_eq!(status.health, "failed");
+        assert!(status
+            .fatal_error
+            .unwrap()
+            .message
+            .starts_with("Hyperindex request failed"));
+        let networks: Vec<&str> = status.chains.iter().map(|c| c.network.as_str()).collect();
+        assert_eq!(networks, ["1"]);
+    }
+}
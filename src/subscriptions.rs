@@ -0,0 +1,455 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::{
+    client::IntoClientRequest, http::HeaderValue, Message as UpstreamMessage,
+};
+
+use crate::chains::ChainId;
+use crate::hooks::{Hooks, RequestContext};
+use crate::persisted::Manifest;
+use crate::response::{self, ResponseMode};
+use crate::{
+    conversion, conversion_span, hyperindex_url, required_filters, upstream_headers, AppState,
+};
+
+/// Subprotocol spoken on both sides of the bridge (graphql-ws library, supported by Hasura).
+const GRAPHQL_TRANSPORT_WS: &str = "graphql-transport-ws";
+
+/// Converted operations per subscription id, used to reshape their `next` frames.
+type Subscriptions = HashMap<String, conversion::ConvertedQuery>;
+
+pub async fn handle_ws(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let mode = response::requested_mode(&headers, &params, state.config.response.mode);
+    let context = RequestContext {
+        chain_id: None,
+        headers,
+    };
+    ws.protocols([GRAPHQL_TRANSPORT_WS])
+        .on_upgrade(move |socket| bridge(socket, state, context, mode))
+}
+
+pub async fn handle_chain_ws(
+    State(state): State<AppState>,
+    ChainId(chain_id): ChainId,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let mode = response::requested_mode(&headers, &params, state.config.response.mode);
+    let context = RequestContext {
+        chain_id: Some(chain_id),
+        headers,
+    };
+    ws.protocols([GRAPHQL_TRANSPORT_WS])
+        .on_upgrade(move |socket| bridge(socket, state, context, mode))
+}
+
+// Relays frames between the client and a dedicated upstream Hyperindex socket until either
+// side closes. `subscribe` payloads are converted on the way up and `next` payloads are
+// reshaped on the way down; everything else (init/ack, ping/pong, complete, error) passes through.
+async fn bridge(client: WebSocket, state: AppState, context: RequestContext, mode: ResponseMode) {
+    let upstream_url = to_ws_url(hyperindex_url(
+        &state,
+        context.chain_id.as_deref(),
+        &context.headers,
+    ));
+    let mut request = match upstream_url.as_str().into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            tracing::error!("Invalid upstream websocket URL {}: {}", upstream_url, e);
+            return;
+        }
+    };
+    request
+        .headers_mut()
+        .extend(upstream_headers(&state.config, &context.headers));
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        HeaderValue::from_static(GRAPHQL_TRANSPORT_WS),
+    );
+
+    let upstream = match tokio_tungstenite::connect_async(request).await {
+        Ok((upstream, _)) => upstream,
+        Err(e) => {
+            tracing::error!(
+                "Failed to connect to upstream websocket {}: {}",
+                upstream_url,
+                e
+            );
+            let mut client = client;
+            let _ = client.send(Message::Close(None)).await;
+            return;
+        }
+    };
+
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let mut subscriptions = Subscriptions::new();
+    let options = subscription_options(&state, &context.headers);
+
+    loop {
+        tokio::select! {
+            msg = client_rx.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    match translate_client_frame(&text, &options, &state.persisted, &state.hooks, &context, &mut subscriptions) {
+                        Ok(frame) => {
+                            if upstream_tx.send(UpstreamMessage::Text(frame)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(error_frame) => {
+                            if client_tx.send(Message::Text(error_frame)).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            msg = upstream_rx.next() => match msg {
+                Some(Ok(UpstreamMessage::Text(text))) => {
+                    let frame = translate_upstream_frame(&text, &state, mode, &context, &mut subscriptions);
+                    if client_tx.send(Message::Text(frame)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(UpstreamMessage::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    let _ = upstream_tx.send(UpstreamMessage::Close(None)).await;
+    let _ = client_tx.send(Message::Close(None)).await;
+}
+
+fn to_ws_url(http_url: &str) -> String {
+    if let Some(rest) = http_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = http_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        http_url.to_string()
+    }
+}
+
+// The shared conversion options with subscription operations allowed.
+fn subscription_options(state: &AppState, headers: &HeaderMap) -> conversion::ConversionOptions {
+    conversion::ConversionOptions {
+        subscriptions: true,
+        required_filters: required_filters(&state.config, headers),
+        ..(*state.conversion_options).clone()
+    }
+}
+
+// Converts the query inside a client `subscribe` frame. Returns the frame to forward upstream,
+// or an `error` frame for the client when the operation can't be converted.
+fn translate_client_frame(
+    text: &str,
+    options: &conversion::ConversionOptions,
+    persisted: &Manifest,
+    hooks: &Hooks,
+    context: &RequestContext,
+    subscriptions: &mut Subscriptions,
+) -> Result<String, String> {
+    let mut frame: Value = match serde_json::from_str(text) {
+        Ok(frame) => frame,
+        Err(_) => return Ok(text.to_string()),
+    };
+    let id = frame
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(str::to_string);
+    match frame.get("type").and_then(|t| t.as_str()) {
+        Some("subscribe") => {}
+        Some("complete") => {
+            if let Some(id) = id {
+                subscriptions.remove(&id);
+            }
+            return Ok(text.to_string());
+        }
+        _ => return Ok(text.to_string()),
+    }
+
+    let rejected = |rejection: crate::hooks::Rejection| {
+        tracing::info!("Subscription rejected: {}", rejection);
+        serde_json::json!({
+            "id": frame.get("id").cloned().unwrap_or(Value::Null),
+            "type": "error",
+            "payload": rejection.errors(),
+        })
+        .to_string()
+    };
+    let mut payload = frame.get("payload").cloned().unwrap_or(Value::Null);
+    persisted.resolve(&mut payload).map_err(rejected)?;
+    hooks
+        .before_conversion(context, &mut payload)
+        .map_err(rejected)?;
+    match conversion_span(&context.headers, context.chain_id.as_deref()).in_scope(|| {
+        conversion::convert_subgraph_to_hyperindex_with_options(
+            &payload,
+            context.chain_id.as_deref(),
+            options,
+        )
+    }) {
+        Ok(mut converted) => {
+            hooks
+                .after_conversion(context, &mut converted.body)
+                .map_err(rejected)?;
+            tracing::info!("Converted subscription: {:?}", converted.body);
+            frame["payload"]["query"] = converted.body["query"].clone();
+            if let Some(variables) = converted.body.get("variables") {
+                frame["payload"]["variables"] = variables.clone();
+            }
+            if let Some(id) = id {
+                subscriptions.insert(id, converted);
+            }
+            Ok(frame.to_string())
+        }
+        Err(e) => {
+            tracing::error!("Subscription conversion error: {}", e);
+            let query = payload.get("query").and_then(|q| q.as_str());
+            Err(serde_json::json!({
+                "id": frame.get("id").cloned().unwrap_or(Value::Null),
+                "type": "error",
+                "payload": [{
+                    "message": e.to_string(),
+                    "locations": e.locations(query.unwrap_or_default()),
+                    "extensions": { "code": e.code() },
+                }],
+            })
+            .to_string())
+        }
+    }
+}
+
+// Reshapes `next` payloads and forgets subscriptions the server has finished.
+fn translate_upstream_frame(
+    text: &str,
+    state: &AppState,
+    mode: ResponseMode,
+    context: &RequestContext,
+    subscriptions: &mut Subscriptions,
+) -> String {
+    let mut frame: Value = match serde_json::from_str(text) {
+        Ok(frame) => frame,
+        Err(_) => return text.to_string(),
+    };
+    let id = frame
+        .get("id")
+        .and_then(|id| id.as_str())
+        .unwrap_or_default()
+        .to_string();
+    match frame.get("type").and_then(|t| t.as_str()) {
+        Some("next") => {}
+        Some("complete") | Some("error") => {
+            subscriptions.remove(&id);
+            return text.to_string();
+        }
+        _ => return text.to_string(),
+    }
+
+    let unknown = conversion::ConvertedQuery::default();
+    let converted = subscriptions.get(&id).unwrap_or(&unknown);
+    if let Some(payload) = frame.get_mut("payload") {
+        *payload = response::shape_response(payload.take(), mode, converted, &state.config);
+        state.hooks.rewrite_response(context, payload);
+    }
+    frame.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> AppState {
+        let mut config = crate::config::Config::default();
+        config.upstream.hyperindex_url = "https://indexer.example/v1/graphql".to_string();
+        AppState::new(config)
+    }
+
+    fn context(chain_id: Option<&str>) -> RequestContext {
+        RequestContext {
+            chain_id: chain_id.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_to_ws_url() {
+        assert_eq!(
+            to_ws_url("https://indexer.example/v1/graphql"),
+            "wss://indexer.example/v1/graphql"
+        );
+        assert_eq!(
+            to_ws_url("http://localhost:8080/v1/graphql"),
+            "ws://localhost:8080/v1/graphql"
+        );
+    }
+
+    #[test]
+    fn test_subscribe_frame_is_converted() {
+        let state = test_state();
+        let frame = r#"{"id":"1","type":"subscribe","payload":{"query":"subscription { streams(first: 2) { id } }"}}"#;
+        let out: Value = serde_json::from_str(
+            &translate_client_frame(
+                frame,
+                &subscription_options(&state, &HeaderMap::new()),
+                &state.persisted,
+                &state.hooks,
+                &context(Some("1")),
+                &mut Subscriptions::new(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(out["id"], "1");
+        assert_eq!(
+            out["payload"]["query"],
+            "subscription {\n  Stream(limit: 2, where: {chainId: {_eq: \"1\"}}) {\n    id\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_unconvertible_subscribe_frame_returns_error() {
+        let state = test_state();
+        let frame = r#"{"id":"7","type":"subscribe","payload":{}}"#;
+        let err: Value = serde_json::from_str(
+            &translate_client_frame(
+                frame,
+                &subscription_options(&state, &HeaderMap::new()),
+                &state.persisted,
+                &state.hooks,
+                &context(None),
+                &mut Subscriptions::new(),
+            )
+            .unwrap_err(),
+        )
+        .unwrap();
+        assert_eq!(err["id"], "7");
+        assert_eq!(err["type"], "error");
+    }
+
+    #[test]
+    fn test_error_frame_reports_locations() {
+        let state = test_state();
+        let frame = r#"{"id":"8","type":"subscribe","payload":{"query":"subscription {\n  streams(where: {name_containsAny: [\"a\"]}) { id }\n}"}}"#;
+        let err: Value = serde_json::from_str(
+            &translate_client_frame(
+                frame,
+                &subscription_options(&state, &HeaderMap::new()),
+                &state.persisted,
+                &state.hooks,
+                &context(None),
+                &mut Subscriptions::new(),
+            )
+            .unwrap_err(),
+        )
+        .unwrap();
+        assert_eq!(
+            err["payload"][0]["locations"],
+            serde_json::json!([{ "line": 2, "column": 19 }])
+        );
+        assert_eq!(
+            err["payload"][0]["extensions"]["code"],
+            "UNSUPPORTED_FILTER"
+        );
+    }
+
+    #[test]
+    fn test_control_frames_pass_through() {
+        let state = test_state();
+        let init = r#"{"type":"connection_init","payload":{}}"#;
+        assert_eq!(
+            translate_client_frame(
+                init,
+                &subscription_options(&state, &HeaderMap::new()),
+                &state.persisted,
+                &state.hooks,
+                &context(None),
+                &mut Subscriptions::new()
+            )
+            .unwrap(),
+            init
+        );
+        let ack = r#"{"type":"connection_ack"}"#;
+        assert_eq!(
+            translate_upstream_frame(
+                ack,
+                &state,
+                ResponseMode::Heuristic,
+                &context(None),
+                &mut Subscriptions::new()
+            ),
+            ack
+        );
+    }
+
+    #[test]
+    fn test_next_frame_is_reshaped() {
+        let state = test_state();
+        let frame = r#"{"id":"1","type":"next","payload":{"data":{"Stream":[{"id":"a"}]}}}"#;
+        let out: Value = serde_json::from_str(&translate_upstream_frame(
+            frame,
+            &state,
+            ResponseMode::Heuristic,
+            &context(None),
+            &mut Subscriptions::new(),
+        ))
+        .unwrap();
+        assert_eq!(out["payload"]["data"]["streams"][0]["id"], "a");
+    }
+
+    #[test]
+    fn test_exact_mode_uses_subscription_field_map() {
+        let state = test_state();
+        let mut subscriptions = Subscriptions::new();
+        let subscribe = r#"{"id":"1","type":"subscribe","payload":{"query":"subscription { tranches { id } }"}}"#;
+        translate_client_frame(
+            subscribe,
+            &subscription_options(&state, &HeaderMap::new()),
+            &state.persisted,
+            &state.hooks,
+            &context(None),
+            &mut subscriptions,
+        )
+        .unwrap();
+        assert_eq!(subscriptions["1"].field_map["Tranche"], "tranches");
+
+        let next = r#"{"id":"1","type":"next","payload":{"data":{"Tranche":[{"id":"a"}]}}}"#;
+        let out: Value = serde_json::from_str(&translate_upstream_frame(
+            next,
+            &state,
+            ResponseMode::Exact,
+            &context(None),
+            &mut subscriptions,
+        ))
+        .unwrap();
+        assert_eq!(out["payload"]["data"]["tranches"][0]["id"], "a");
+
+        let complete = r#"{"id":"1","type":"complete"}"#;
+        translate_upstream_frame(
+            complete,
+            &state,
+            ResponseMode::Exact,
+            &context(None),
+            &mut subscriptions,
+        );
+        assert!(subscriptions.is_empty());
+    }
+}
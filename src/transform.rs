@@ -0,0 +1,264 @@
+//! Unit conversions for fields a subgraph and Hyperindex store differently, e.g. timestamps the
+//! subgraph kept as Unix seconds and Hyperindex stores as ISO 8601. Filter values are converted
+//! to Hyperindex's unit on the way in, response values back to the subgraph's on the way out.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::numeric;
+
+/// How a field's subgraph values map to Hyperindex's, named subgraph unit first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueTransform {
+    /// Unix seconds in the subgraph, ISO 8601 timestamps in Hyperindex.
+    SecondsToIso8601,
+    /// Unix seconds in the subgraph, milliseconds in Hyperindex.
+    SecondsToMilliseconds,
+    /// Milliseconds in the subgraph, Unix seconds in Hyperindex. Filter values are rounded
+    /// down to whole seconds.
+    MillisecondsToSeconds,
+}
+
+impl ValueTransform {
+    /// Converts the literals of a subgraph filter value (`1700000000`, `["1", "2"]`) to
+    /// Hyperindex's unit, keeping numbers numbers and strings strings. `None` when a literal
+    /// isn't an integer, e.g. a `$variable`.
+    pub fn inbound(self, text: &str) -> Option<String> {
+        let mut convertible = true;
+        let converted = numeric::map_literals(text, |literal, quoted| {
+            if !quoted && literal == "null" {
+                return None;
+            }
+            let converted = integer(literal).and_then(|n| match self {
+                ValueTransform::SecondsToIso8601 => Some(format!("\"{}\"", iso8601(n)?)),
+                ValueTransform::SecondsToMilliseconds => Some(quote(n.checked_mul(1000)?, quoted)),
+                ValueTransform::MillisecondsToSeconds => Some(quote(n.div_euclid(1000), quoted)),
+            });
+            convertible &= converted.is_some();
+            converted
+        });
+        convertible.then_some(converted)
+    }
+
+    /// Converts a Hyperindex response value back to the subgraph's unit. Seconds from ISO 8601
+    /// timestamps are strings, as subgraphs serialize `BigInt`s; other values keep their JSON
+    /// type. Values that can't be converted are returned unchanged.
+    pub fn outbound(self, value: &Value) -> Value {
+        let converted = match (self, value) {
+            (ValueTransform::SecondsToIso8601, Value::String(s)) => {
+                parse_iso8601(s).map(|seconds| Value::String(seconds.to_string()))
+            }
+            (ValueTransform::SecondsToMilliseconds, value) => {
+                scale(value, |ms| Some(ms.div_euclid(1000)))
+            }
+            (ValueTransform::MillisecondsToSeconds, value) => {
+                scale(value, |seconds| seconds.checked_mul(1000))
+            }
+            _ => None,
+        };
+        converted.unwrap_or_else(|| value.clone())
+    }
+}
+
+fn integer(literal: &str) -> Option<i64> {
+    numeric::exact_decimal(literal)?.parse().ok()
+}
+
+fn quote(n: i64, quoted: bool) -> String {
+    if quoted {
+        format!("\"{}\"", n)
+    } else {
+        n.to_string()
+    }
+}
+
+// An integer number or digit string, rescaled with `f` and kept as the same JSON type.
+fn scale(value: &Value, f: impl Fn(i64) -> Option<i64>) -> Option<Value> {
+    match value {
+        Value::Number(n) => Some(Value::from(f(n.as_i64()?)?)),
+        Value::String(s) => Some(Value::String(f(s.parse().ok()?)?.to_string())),
+        _ => None,
+    }
+}
+
+// Unix seconds as a UTC `2023-11-14T22:13:20Z` timestamp, for years 0 through 9999.
+fn iso8601(seconds: i64) -> Option<String> {
+    let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    (0..=9999).contains(&year).then(|| {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    })
+}
+
+// Unix seconds of an ISO 8601 timestamp as Postgres writes them: `2023-11-14T22:13:20`, with a
+// space or `T`, optional fractional seconds (dropped), and an optional `Z` or `+HH[:MM]`
+// offset (UTC without one).
+fn parse_iso8601(timestamp: &str) -> Option<i64> {
+    let bytes = timestamp.as_bytes();
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = timestamp.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    if bytes.len() < 19
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let mut rest = &timestamp[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let digits = rest[1..].replace(':', "");
+            if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let hours: i64 = digits[..2].parse().ok()?;
+            let minutes: i64 = if digits.len() == 4 {
+                digits[2..].parse().ok()?
+            } else {
+                0
+            };
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+    let days = days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_iso8601_round_trip() {
+        assert_eq!(iso8601(0).unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(iso8601(1_700_000_000).unwrap(), "2023-11-14T22:13:20Z");
+        assert_eq!(iso8601(951_782_400).unwrap(), "2000-02-29T00:00:00Z");
+        assert_eq!(iso8601(-1).unwrap(), "1969-12-31T23:59:59Z");
+        for seconds in [0, 1_700_000_000, 951_782_400, -86_401, 4_102_444_800] {
+            assert_eq!(parse_iso8601(&iso8601(seconds).unwrap()), Some(seconds));
+        }
+        assert_eq!(
+            parse_iso8601("2023-11-14 22:13:20.123456"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            parse_iso8601("2023-11-15T00:13:20+02:00"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            parse_iso8601("2023-11-14T17:13:20-0500"),
+            Some(1_700_000_000)
+        );
+        for invalid in [
+            "2023-11-14",
+            "2023-13-14T00:00:00",
+            "2023-11-14T00:00:00+2",
+            "yesterday",
+        ] {
+            assert_eq!(parse_iso8601(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_inbound() {
+        let iso = ValueTransform::SecondsToIso8601;
+        assert_eq!(
+            iso.inbound("1700000000").unwrap(),
+            "\"2023-11-14T22:13:20Z\""
+        );
+        assert_eq!(
+            iso.inbound("[0, \"1700000000\"]").unwrap(),
+            "[\"1970-01-01T00:00:00Z\", \"2023-11-14T22:13:20Z\"]"
+        );
+        assert_eq!(iso.inbound("$since"), None);
+        assert_eq!(iso.inbound("\"soon\""), None);
+        assert_eq!(iso.inbound("null").unwrap(), "null");
+        let ms = ValueTransform::SecondsToMilliseconds;
+        assert_eq!(ms.inbound("[1, \"2\"]").unwrap(), "[1000, \"2000\"]");
+        let seconds = ValueTransform::MillisecondsToSeconds;
+        assert_eq!(seconds.inbound("1700000000999").unwrap(), "1700000000");
+    }
+
+    #[test]
+    fn test_outbound() {
+        let iso = ValueTransform::SecondsToIso8601;
+        assert_eq!(
+            iso.outbound(&json!("2023-11-14T22:13:20+00:00")),
+            json!("1700000000")
+        );
+        assert_eq!(iso.outbound(&json!(null)), json!(null));
+        assert_eq!(iso.outbound(&json!("garbage")), json!("garbage"));
+        let ms = ValueTransform::SecondsToMilliseconds;
+        assert_eq!(
+            ms.outbound(&json!(1_700_000_000_000u64)),
+            json!(1_700_000_000)
+        );
+        assert_eq!(ms.outbound(&json!("1700000000000")), json!("1700000000"));
+        let seconds = ValueTransform::MillisecondsToSeconds;
+        assert_eq!(
+            seconds.outbound(&json!(1_700_000_000)),
+            json!(1_700_000_000_000u64)
+        );
+    }
+}
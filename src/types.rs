@@ -0,0 +1,171 @@
+use serde_json::Value;
+
+/// The Hyperindex (Hasura) type for a subgraph variable type, keeping list and non-null
+/// wrappers, e.g. `[ID!]!` becomes `[String!]!` and `Stream_filter` becomes `Stream_bool_exp`.
+pub fn hasura_type(subgraph_type: &str) -> String {
+    let name = subgraph_type.trim_matches(|c| matches!(c, '[' | ']' | '!'));
+    let hasura = match name {
+        // Hyperindex stores ids and bytes as text, big numbers as numeric
+        "ID" | "Bytes" => "String".to_string(),
+        "BigInt" | "BigDecimal" => "numeric".to_string(),
+        "OrderDirection" => "order_by".to_string(),
+        other => {
+            if let Some(entity) = other.strip_suffix("_filter") {
+                format!("{}_bool_exp", entity)
+            } else if let Some(entity) = other.strip_suffix("_orderBy") {
+                format!("{}_select_column", entity)
+            } else {
+                other.to_string()
+            }
+        }
+    };
+    subgraph_type.replacen(name, &hasura, 1)
+}
+
+/// Checks a variable's JSON value against its declared subgraph type. The error describes what
+/// was expected. Enums and custom scalars the converter doesn't know are accepted as is.
+pub fn check_value(subgraph_type: &str, value: &Value) -> Result<(), &'static str> {
+    let subgraph_type = subgraph_type.trim();
+    if let Some(inner) = subgraph_type.strip_suffix('!') {
+        if value.is_null() {
+            return Err("a non-null value");
+        }
+        return check_value(inner, value);
+    }
+    if value.is_null() {
+        return Ok(());
+    }
+    if let Some(item_type) = subgraph_type
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+    {
+        return match value {
+            Value::Array(items) => items
+                .iter()
+                .try_for_each(|item| check_value(item_type, item)),
+            // GraphQL coerces a single value to a one-item list
+            other => check_value(item_type, other),
+        };
+    }
+    let (fits, expected) = match subgraph_type {
+        "Int" => (
+            value.as_i64().is_some_and(|n| i32::try_from(n).is_ok()),
+            "an Int",
+        ),
+        "Float" => (value.is_number(), "a Float"),
+        "String" => (value.is_string(), "a String"),
+        "Boolean" => (value.is_boolean(), "a Boolean"),
+        "ID" => (value.is_string() || value.is_i64(), "an ID"),
+        "Bytes" => (
+            value.as_str().is_some_and(|s| {
+                s.strip_prefix("0x")
+                    .is_some_and(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            }),
+            "a 0x-prefixed hex string",
+        ),
+        "BigInt" => (
+            value.is_i64()
+                || value.is_u64()
+                || value.as_str().is_some_and(|s| {
+                    let digits = s.strip_prefix('-').unwrap_or(s);
+                    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+                }),
+            "a BigInt",
+        ),
+        "BigDecimal" => (
+            value.is_number() || value.as_str().is_some_and(|s| s.parse::<f64>().is_ok()),
+            "a BigDecimal",
+        ),
+        "OrderDirection" => (
+            matches!(value.as_str(), Some("asc" | "desc")),
+            "asc or desc",
+        ),
+        other if other.ends_with("_filter") => (value.is_object(), "an object"),
+        other if other.ends_with("_orderBy") => {
+            (value.as_str().is_some_and(is_name), "a field name")
+        }
+        _ => return Ok(()),
+    };
+    if fits {
+        Ok(())
+    } else {
+        Err(expected)
+    }
+}
+
+/// Whether a named subgraph type is a built-in scalar, rather than an entity, enum or filter.
+pub fn is_scalar(type_name: &str) -> bool {
+    matches!(
+        type_name,
+        "Int"
+            | "Float"
+            | "String"
+            | "Boolean"
+            | "ID"
+            | "Bytes"
+            | "BigInt"
+            | "BigDecimal"
+            | "Int8"
+            | "Timestamp"
+    )
+}
+
+/// Whether `s` is a GraphQL name (`[_A-Za-z][_0-9A-Za-z]*`), safe to write into a document
+/// unquoted.
+pub fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Whether values of a named subgraph type are enum values, written unquoted in a document:
+/// `OrderDirection`, `<Entity>_orderBy`, and any other type that isn't a known scalar or filter.
+pub fn is_enum(type_name: &str) -> bool {
+    !is_scalar(type_name) && !type_name.ends_with("_filter")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hasura_type() {
+        assert_eq!(hasura_type("[ID!]!"), "[String!]!");
+        assert_eq!(hasura_type("BigInt"), "numeric");
+        assert_eq!(hasura_type("Stream_filter!"), "Stream_bool_exp!");
+        assert_eq!(hasura_type("Stream_orderBy"), "Stream_select_column");
+        assert_eq!(hasura_type("OrderDirection"), "order_by");
+        assert_eq!(hasura_type("Boolean"), "Boolean");
+    }
+
+    #[test]
+    fn test_check_value() {
+        assert!(check_value("Int!", &json!(10)).is_ok());
+        assert_eq!(check_value("Int!", &Value::Null), Err("a non-null value"));
+        assert_eq!(check_value("Int", &json!("10")), Err("an Int"));
+        assert!(check_value("[BigInt!]", &json!(["1", 2])).is_ok());
+        assert_eq!(check_value("[BigInt!]", &json!(["1.5"])), Err("a BigInt"));
+        assert_eq!(
+            check_value("Bytes", &json!("abc")),
+            Err("a 0x-prefixed hex string")
+        );
+        assert!(check_value("Stream_filter", &json!({ "name": "a" })).is_ok());
+        assert_eq!(check_value("Stream_filter", &json!("a")), Err("an object"));
+        assert!(check_value("Stream_orderBy", &json!("timestamp")).is_ok());
+        assert!(check_value("Stream_orderBy", &json!("id: asc}, limit: 1000")).is_err());
+        assert_eq!(
+            check_value("OrderDirection", &json!("up")),
+            Err("asc or desc")
+        );
+        assert!(check_value("Status", &json!("OPEN")).is_ok());
+    }
+
+    #[test]
+    fn test_is_enum() {
+        assert!(is_enum("OrderDirection"));
+        assert!(is_enum("Stream_orderBy"));
+        assert!(is_enum("Status"));
+        assert!(!is_enum("BigInt"));
+        assert!(!is_enum("Stream_filter"));
+    }
+}
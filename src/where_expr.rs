@@ -0,0 +1,298 @@
+//! Hasura boolean expressions (`where` arguments), built up during conversion and rendered
+//! once, so merging and nesting happen on structure rather than on strings.
+
+use std::fmt;
+
+/// A Hasura comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,
+    Nin,
+    Like,
+    Ilike,
+    /// Array containment (`_contains`), for list columns.
+    Contains,
+}
+
+impl Op {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Op::Eq => "_eq",
+            Op::Neq => "_neq",
+            Op::Gt => "_gt",
+            Op::Gte => "_gte",
+            Op::Lt => "_lt",
+            Op::Lte => "_lte",
+            Op::In => "_in",
+            Op::Nin => "_nin",
+            Op::Like => "_like",
+            Op::Ilike => "_ilike",
+            Op::Contains => "_contains",
+        }
+    }
+}
+
+/// One condition of a `where` argument. Values are GraphQL literal source (or a `$variable`),
+/// exactly as they are emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhereExpr {
+    /// `field: {_op: value}`
+    Compare {
+        field: String,
+        op: Op,
+        value: String,
+    },
+    /// `_not: {...}`
+    Not(Box<WhereExpr>),
+    /// Conditions that must all hold, written as one object. Comparisons on the same field are
+    /// merged (`amount: {_gte: 1, _lte: 2}`); anything whose key is already taken moves under
+    /// `_and`.
+    All(Vec<WhereExpr>),
+    /// `_and: [{...}, ...]`, kept as separate objects.
+    And(Vec<WhereExpr>),
+    /// `_or: [{...}, ...]`
+    Or(Vec<WhereExpr>),
+    /// `field: {...}`: a condition on a related entity.
+    Nested { field: String, expr: Box<WhereExpr> },
+}
+
+impl WhereExpr {
+    pub fn compare(field: impl Into<String>, op: Op, value: impl Into<String>) -> Self {
+        WhereExpr::Compare {
+            field: field.into(),
+            op,
+            value: value.into(),
+        }
+    }
+
+    pub fn negate(expr: WhereExpr) -> Self {
+        WhereExpr::Not(Box::new(expr))
+    }
+
+    pub fn nested(field: impl Into<String>, expr: WhereExpr) -> Self {
+        WhereExpr::Nested {
+            field: field.into(),
+            expr: Box::new(expr),
+        }
+    }
+
+    /// `where: {...}`, or an empty string without conditions.
+    pub fn argument(expr: Option<&WhereExpr>) -> String {
+        match expr {
+            Some(WhereExpr::All(items)) if items.is_empty() => String::new(),
+            Some(expr) => format!("where: {{{}}}", expr),
+            None => String::new(),
+        }
+    }
+
+    /// Whether a condition anywhere in the expression is on a field named `field`.
+    pub fn mentions(&self, field: &str) -> bool {
+        match self {
+            WhereExpr::Compare { field: f, .. } => f == field,
+            WhereExpr::Nested { field: f, expr } => f == field || expr.mentions(field),
+            WhereExpr::Not(expr) => expr.mentions(field),
+            WhereExpr::All(items) | WhereExpr::And(items) | WhereExpr::Or(items) => {
+                items.iter().any(|item| item.mentions(field))
+            }
+        }
+    }
+
+    /// Fields the conditions are on at this level; a condition on a related entity counts as its
+    /// relationship field.
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            WhereExpr::Compare { field, .. } | WhereExpr::Nested { field, .. } => vec![field],
+            WhereExpr::Not(expr) => expr.fields(),
+            WhereExpr::All(items) | WhereExpr::And(items) | WhereExpr::Or(items) => {
+                items.iter().flat_map(WhereExpr::fields).collect()
+            }
+        }
+    }
+
+    /// The same conditions with every field name passed through `rename`.
+    pub fn renamed(&self, rename: &dyn Fn(&str) -> String) -> WhereExpr {
+        let all = |items: &[WhereExpr]| items.iter().map(|item| item.renamed(rename)).collect();
+        match self {
+            WhereExpr::Compare { field, op, value } => WhereExpr::Compare {
+                field: rename(field),
+                op: *op,
+                value: value.clone(),
+            },
+            WhereExpr::Nested { field, expr } => {
+                WhereExpr::nested(rename(field), expr.renamed(rename))
+            }
+            WhereExpr::Not(expr) => WhereExpr::negate(expr.renamed(rename)),
+            WhereExpr::All(items) => WhereExpr::All(all(items)),
+            WhereExpr::And(items) => WhereExpr::And(all(items)),
+            WhereExpr::Or(items) => WhereExpr::Or(all(items)),
+        }
+    }
+
+    // The object key this condition is written under.
+    fn key(&self) -> &str {
+        match self {
+            WhereExpr::Compare { field, .. } | WhereExpr::Nested { field, .. } => field,
+            WhereExpr::Not(_) => "_not",
+            WhereExpr::All(_) | WhereExpr::And(_) => "_and",
+            WhereExpr::Or(_) => "_or",
+        }
+    }
+}
+
+// One key of an object being written: a field's merged comparisons, or any other condition.
+enum Entry<'a> {
+    Field(&'a str, Vec<(Op, &'a str)>),
+    Other(&'a WhereExpr),
+}
+
+impl Entry<'_> {
+    fn key(&self) -> &str {
+        match self {
+            Entry::Field(field, _) => field,
+            Entry::Other(expr) => expr.key(),
+        }
+    }
+}
+
+// Lays out the conditions of an `All` as object keys, flattening nested `All`s; conditions
+// that can't share the object are returned for `_and`.
+fn layout<'a>(
+    items: &'a [WhereExpr],
+    entries: &mut Vec<Entry<'a>>,
+    overflow: &mut Vec<&'a WhereExpr>,
+) {
+    for item in items {
+        match item {
+            WhereExpr::All(inner) => layout(inner, entries, overflow),
+            WhereExpr::Compare { field, op, value } => {
+                match entries.iter_mut().find(|entry| entry.key() == field) {
+                    Some(Entry::Field(_, ops))
+                        if !ops.iter().any(|(existing, _)| existing == op) =>
+                    {
+                        ops.push((*op, value))
+                    }
+                    Some(_) => overflow.push(item),
+                    None => entries.push(Entry::Field(field, vec![(*op, value)])),
+                }
+            }
+            _ if entries.iter().any(|entry| entry.key() == item.key()) => overflow.push(item),
+            _ => entries.push(Entry::Other(item)),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, key: &str, items: &[&WhereExpr]) -> fmt::Result {
+    write!(f, "{}: [", key)?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{{{}}}", item)?;
+    }
+    f.write_str("]")
+}
+
+/// The contents of the object the condition is written in, without the braces.
+impl fmt::Display for WhereExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WhereExpr::Compare { field, op, value } => {
+                write!(f, "{}: {{{}: {}}}", field, op.as_str(), value)
+            }
+            WhereExpr::Not(expr) => write!(f, "_not: {{{}}}", expr),
+            WhereExpr::Nested { field, expr } => write!(f, "{}: {{{}}}", field, expr),
+            WhereExpr::And(items) => write_list(f, "_and", &items.iter().collect::<Vec<_>>()),
+            WhereExpr::Or(items) => write_list(f, "_or", &items.iter().collect::<Vec<_>>()),
+            WhereExpr::All(items) => {
+                let mut entries = Vec::new();
+                let mut overflow = Vec::new();
+                layout(items, &mut entries, &mut overflow);
+                for (i, entry) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match entry {
+                        Entry::Field(field, mut ops) => {
+                            ops.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+                            write!(f, "{}: {{", field)?;
+                            for (j, (op, value)) in ops.into_iter().enumerate() {
+                                if j > 0 {
+                                    f.write_str(", ")?;
+                                }
+                                write!(f, "{}: {}", op.as_str(), value)?;
+                            }
+                            f.write_str("}")?;
+                        }
+                        Entry::Other(expr) => write!(f, "{}", expr)?,
+                    }
+                }
+                // Overflow only happens on a taken key, so something was written before it
+                if !overflow.is_empty() {
+                    f.write_str(", ")?;
+                    write_list(f, "_and", &overflow)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merges_comparisons_on_one_field() {
+        let expr = WhereExpr::All(vec![
+            WhereExpr::compare("chainId", Op::Eq, "\"1\""),
+            WhereExpr::compare("amount", Op::Lte, "10"),
+            WhereExpr::compare("amount", Op::Gte, "1"),
+            WhereExpr::compare("amount", Op::Gte, "2"),
+        ]);
+        assert_eq!(
+            WhereExpr::argument(Some(&expr)),
+            "where: {chainId: {_eq: \"1\"}, amount: {_gte: 1, _lte: 10}, _and: [{amount: {_gte: 2}}]}"
+        );
+    }
+
+    #[test]
+    fn test_colliding_keys_move_under_and() {
+        let expr = WhereExpr::All(vec![
+            WhereExpr::negate(WhereExpr::compare("name", Op::Like, "\"%a%\"")),
+            WhereExpr::negate(WhereExpr::compare("symbol", Op::Like, "\"%b%\"")),
+            WhereExpr::nested("pair", WhereExpr::compare("id", Op::Eq, "\"0x1\"")),
+            WhereExpr::nested("pair", WhereExpr::compare("fee", Op::Gt, "3")),
+        ]);
+        assert_eq!(
+            expr.to_string(),
+            concat!(
+                "_not: {name: {_like: \"%a%\"}}, pair: {id: {_eq: \"0x1\"}}, ",
+                "_and: [{_not: {symbol: {_like: \"%b%\"}}}, {pair: {fee: {_gt: 3}}}]"
+            )
+        );
+    }
+
+    #[test]
+    fn test_explicit_and_or() {
+        let client = WhereExpr::All(vec![WhereExpr::compare("owner", Op::Eq, "\"a\"")]);
+        let search = WhereExpr::Or(vec![
+            WhereExpr::compare("name", Op::Ilike, "\"%x%\""),
+            WhereExpr::compare("symbol", Op::Ilike, "\"%x%\""),
+        ]);
+        let expr = WhereExpr::And(vec![client, search]);
+        assert_eq!(
+            expr.to_string(),
+            "_and: [{owner: {_eq: \"a\"}}, {_or: [{name: {_ilike: \"%x%\"}}, {symbol: {_ilike: \"%x%\"}}]}]"
+        );
+        assert!(expr.mentions("symbol"));
+        assert!(!expr.mentions("id"));
+        assert_eq!(expr.fields(), ["owner", "name", "symbol"]);
+        assert_eq!(WhereExpr::argument(Some(&WhereExpr::All(Vec::new()))), "");
+    }
+}
@@ -0,0 +1,123 @@
+//! Property tests for the query converter: whatever document a client sends, conversion must
+//! return a converted query or a `ConversionError`, never panic.
+//!
+//! Failing cases are shrunk by proptest and recorded in `conversion_props.proptest-regressions`
+//! next to this file; commit it so the case is replayed on every run.
+//! `PROPTEST_CASES=100000 cargo test --test conversion_props` runs a longer search.
+
+use proptest::prelude::*;
+use serde_json::{json, Value};
+
+use subgraph_converter::conversion::{
+    convert_subgraph_to_hyperindex_with_options, ConversionMode, ConversionOptions,
+};
+
+// Fragments of subgraph queries, including the unbalanced and truncated pieces that trip
+// index-based scanning.
+#[rustfmt::skip]
+const TOKENS: &[&str] = &[
+    "{", "}", "(", ")", "[", "]", ":", ",", " ", "\n", "\"", "\"\"\"", "\\", "#", "$", "!", "=",
+    "@", "...", "query", "query Q", "($id: ID!)", "fragment", "F", "on", "Stream", "...F",
+    "... on Stream", "mutation", "subscription", "streams", "stream", "_meta", "block", "number",
+    "id", "name", "first", "skip", "orderBy", "orderDirection", "desc", "where", "id_in",
+    "name_contains", "amount_gt", "asset_", "and", "or", "true", "null", "0", "-1", "1e3", "10",
+    "\"0xAbC\"", "\"a\"", "é", "💥", "@include(if: $x)", "_not", "_nocase", "text",
+];
+
+fn token_soup() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(TOKENS), 0..48).prop_map(|tokens| tokens.concat())
+}
+
+// Well-formed queries with a few characters deleted or swapped, to reach deeper into the
+// converter than random soup does.
+fn damaged_query() -> impl Strategy<Value = String> {
+    let query = prop::sample::select(&[
+        "{ streams(first: 10, where: {name_contains: \"a\", amount_gt: 5}) { id asset { id } } }",
+        "query Q($id: ID!) { stream(id: $id) { id name } }",
+        "fragment F on Stream { id name } query { streams(orderBy: name, orderDirection: desc) { ...F } }",
+        "{ streams(where: {or: [{name: \"a\"}, {asset_: {id_in: [\"1\", \"2\"]}}]}) { id } }",
+        "{ _meta { block { number } } }",
+        "{ a: streams(skip: 5) { id } b: assets { ... on Asset { id } } }",
+    ][..]);
+    (
+        query,
+        prop::collection::vec((any::<prop::sample::Index>(), 0u8..3, any::<char>()), 0..6),
+    )
+        .prop_map(|(query, edits)| {
+            let mut chars: Vec<char> = query.chars().collect();
+            for (index, kind, c) in edits {
+                if chars.is_empty() {
+                    break;
+                }
+                let i = index.index(chars.len());
+                match kind {
+                    0 => {
+                        chars.remove(i);
+                    }
+                    1 => chars.insert(i, c),
+                    _ => chars.truncate(i),
+                }
+            }
+            chars.into_iter().collect()
+        })
+}
+
+fn variables() -> impl Strategy<Value = Value> {
+    prop_oneof![
+        Just(Value::Null),
+        Just(json!({ "id": "1", "x": true })),
+        Just(json!({ "id": 1, "x": [1, "a"] })),
+    ]
+}
+
+fn convert(query: &str, variables: Value, chain_id: Option<&str>, mode: ConversionMode) {
+    let options = ConversionOptions {
+        mode,
+        ..Default::default()
+    };
+    let payload = json!({ "query": query, "variables": variables });
+    // Either outcome is fine; proptest reports a panic as a failure
+    let _ = convert_subgraph_to_hyperindex_with_options(&payload, chain_id, &options);
+}
+
+fn mode() -> impl Strategy<Value = ConversionMode> {
+    prop_oneof![Just(ConversionMode::Strict), Just(ConversionMode::Lenient)]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig {
+        cases: std::env::var("PROPTEST_CASES")
+            .ok()
+            .and_then(|cases| cases.parse().ok())
+            .unwrap_or(2000),
+        // Runs each case in a child process, so a conversion that never returns is reported
+        // (and shrunk) like a panic
+        timeout: 2000,
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn token_soup_never_panics(
+        query in token_soup(),
+        variables in variables(),
+        chain in prop::option::of(Just("1")),
+        mode in mode(),
+    ) {
+        convert(&query, variables, chain, mode);
+    }
+
+    #[test]
+    fn damaged_queries_never_panic(
+        query in damaged_query(),
+        variables in variables(),
+        chain in prop::option::of(Just("1")),
+        mode in mode(),
+    ) {
+        convert(&query, variables, chain, mode);
+    }
+
+    #[test]
+    fn arbitrary_strings_never_panic(query in ".{0,200}", mode in mode()) {
+        convert(&query, Value::Null, None, mode);
+    }
+}
@@ -0,0 +1,89 @@
+//! Golden-file tests: every `fixtures/conversions/*.graphql` query is converted with the default
+//! options and compared with its snapshot in `snapshots/`.
+//!
+//! To add a regression case, drop the subgraph query into `fixtures/conversions/` and run
+//! `INSTA_UPDATE=always cargo test --test conversion_snapshots` to write its snapshot (or
+//! `cargo insta review` to accept it interactively), then check the snapshot reads right.
+//! The same flag regenerates existing snapshots after an intended conversion change.
+//!
+//! A fixture may start with comment lines setting how it is converted:
+//!
+//! ```graphql
+//! # chain: 137
+//! # variables: {"id": "0xabc"}
+//! ```
+
+use serde_json::{json, Value};
+
+use subgraph_converter::conversion::{
+    convert_subgraph_to_hyperindex_with_options, ConversionOptions,
+};
+use subgraph_converter::format;
+
+struct Fixture<'a> {
+    chain_id: Option<&'a str>,
+    variables: Option<Value>,
+    query: &'a str,
+}
+
+impl<'a> Fixture<'a> {
+    fn parse(source: &'a str) -> Self {
+        let mut fixture = Fixture {
+            chain_id: None,
+            variables: None,
+            query: source,
+        };
+        for line in source.lines() {
+            let Some(setting) = line.strip_prefix('#') else {
+                break;
+            };
+            match setting.trim().split_once(':') {
+                Some(("chain", chain_id)) => fixture.chain_id = Some(chain_id.trim()),
+                Some(("variables", variables)) => {
+                    fixture.variables =
+                        Some(serde_json::from_str(variables).expect("fixture variables are JSON"))
+                }
+                _ => panic!("unknown fixture setting {:?}", line),
+            }
+        }
+        fixture
+    }
+}
+
+// The converted query in the canonical layout, followed by whatever else the conversion
+// produced, so a snapshot diff shows exactly what changed.
+fn render(fixture: &Fixture) -> String {
+    let mut payload = json!({ "query": fixture.query });
+    if let Some(variables) = &fixture.variables {
+        payload["variables"] = variables.clone();
+    }
+    let converted = match convert_subgraph_to_hyperindex_with_options(
+        &payload,
+        fixture.chain_id,
+        &ConversionOptions::default(),
+    ) {
+        Ok(converted) => converted,
+        Err(e) => return format!("error {}: {}\n", e.code(), e),
+    };
+
+    let mut out = format::pretty(converted.body["query"].as_str().unwrap_or_default());
+    out.push('\n');
+    if let Some(variables) = converted.body.get("variables") {
+        out.push_str(&format!(
+            "\nvariables: {}\n",
+            serde_json::to_string_pretty(variables).unwrap()
+        ));
+    }
+    for warning in &converted.warnings {
+        out.push_str(&format!("\nwarning {}: {}", warning.code, warning.message));
+    }
+    out
+}
+
+#[test]
+fn conversions() {
+    insta::glob!("fixtures/conversions/*.graphql", |path| {
+        let source = std::fs::read_to_string(path).unwrap();
+        insta::assert_snapshot!(render(&Fixture::parse(&source)));
+    });
+}